@@ -1,5 +1,7 @@
 $script:THIS_PATH = $myinvocation.mycommand.path
-$script:BASE_DIR = Split-Path (Resolve-Path "$THIS_PATH/..") -Parent
+# `bin` on unix and `Scripts` on windows, wherever this script is
+$script:BIN_DIR = Split-Path (Resolve-Path $THIS_PATH) -Parent
+$script:BASE_DIR = Split-Path $BIN_DIR -Parent
 
 function global:deactivate([switch] $NonDestructive) {
     if (Test-Path variable:_OLD_VIRTUAL_PATH) {
@@ -37,8 +39,8 @@ deactivate -nondestructive
 $VIRTUAL_ENV = $BASE_DIR
 $env:VIRTUAL_ENV = $VIRTUAL_ENV
 
-if ("" -ne "") {
-    $env:VIRTUAL_ENV_PROMPT = ""
+if ({{ VIRTUAL_PROMPT }} -ne "") {
+    $env:VIRTUAL_ENV_PROMPT = {{ VIRTUAL_PROMPT }}
 }
 else {
     $env:VIRTUAL_ENV_PROMPT = $( Split-Path $env:VIRTUAL_ENV -Leaf )
@@ -46,7 +48,8 @@ else {
 
 New-Variable -Scope global -Name _OLD_VIRTUAL_PATH -Value $env:PATH
 
-$env:PATH = "$env:VIRTUAL_ENV/bin:" + $env:PATH
+# `:` on unix and `;` on windows
+$env:PATH = $BIN_DIR + [System.IO.Path]::PathSeparator + $env:PATH
 if (!$env:VIRTUAL_ENV_DISABLE_PROMPT) {
     function global:_old_virtual_prompt {
         ""
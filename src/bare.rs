@@ -1,22 +1,35 @@
 //! Create a bare virtualenv without any packages install
 
-use crate::interpreter::InterpreterInfo;
+use crate::cache::{write_atomic, CACHEDIR_TAG};
+use crate::codesign;
+use crate::freeze::{is_frozen, make_writable};
+use crate::interpreter::{get_interpreter_info, InterpreterInfo};
+use crate::link::LinkMode;
+use crate::paths::{canonicalize, extended, is_unc, simplified, venv_root};
+use crate::pyvenv_cfg::PyVenvCfg;
+use crate::scripts::normalize_name;
+use crate::universal2::{self, Universal2};
+use crate::{Context, Error, VenvOptions};
 use camino::{Utf8Path, Utf8PathBuf};
 use fs_err as fs;
 #[cfg(unix)]
 use fs_err::os::unix::fs::symlink;
-use fs_err::File;
+use serde::{Deserialize, Serialize};
 use std::io;
-use std::io::{BufWriter, Write};
-use tracing::info;
+use std::path::PathBuf;
+use std::thread;
+use tempfile::TempDir;
+use tracing::{debug, info, warn};
 
 /// The bash activate scripts with the venv dependent paths patches out
-const ACTIVATE_TEMPLATES: &[(&str, &str)] = &[
+pub(crate) const ACTIVATE_TEMPLATES: &[(&str, &str)] = &[
     ("activate", include_str!("activator/activate")),
     ("activate.csh", include_str!("activator/activate.csh")),
+    ("activate.elv", include_str!("activator/activate.elv")),
     ("activate.fish", include_str!("activator/activate.fish")),
     ("activate.nu", include_str!("activator/activate.nu")),
     ("activate.ps1", include_str!("activator/activate.ps1")),
+    ("activate.xsh", include_str!("activator/activate.xsh")),
     (
         "activate_this.py",
         include_str!("activator/activate_this.py"),
@@ -24,16 +37,104 @@ const ACTIVATE_TEMPLATES: &[(&str, &str)] = &[
 ];
 const VIRTUALENV_PATCH: &str = include_str!("_virtualenv.py");
 
-/// Very basic `.cfg` file format writer.
-fn write_cfg(f: &mut impl Write, data: &[(&str, String); 8]) -> io::Result<()> {
-    for (key, value) in data {
-        writeln!(f, "{} = {}", key, value)?;
+/// The keys that gourgeist writes to `pyvenv.cfg` itself
+const PYVENV_CFG_KEYS: [&str; 15] = [
+    "home",
+    "implementation",
+    "version_info",
+    "gourgeist",
+    "include-system-site-packages",
+    "base-prefix",
+    "base-exec-prefix",
+    "base-executable",
+    "gourgeist-options",
+    "relocatable",
+    "resolve-symlinks",
+    "command",
+    "prompt",
+    "gourgeist-scripts",
+    "gourgeist-purelib",
+];
+
+/// Check that an extra `pyvenv.cfg` entry can be written and read back (see
+/// [`PyVenvCfg::check_entry`]) and that it's not one of the keys gourgeist writes itself.
+pub fn check_pyvenv_cfg_entry(key: &str, value: &str) -> Result<(), String> {
+    PyVenvCfg::check_entry(key, value)?;
+    if PYVENV_CFG_KEYS.contains(&key) {
+        return Err(format!("`{key}` is set by gourgeist"));
+    }
+    Ok(())
+}
+
+/// Parse a `key=value` entry for [`VenvOptions::extra_pyvenv_cfg`].
+pub fn parse_pyvenv_cfg_entry(entry: &str) -> Result<(String, String), String> {
+    let (key, value) = entry
+        .split_once('=')
+        .ok_or_else(|| format!("Expected key=value, got {entry}"))?;
+    let (key, value) = (key.trim(), value.trim());
+    check_pyvenv_cfg_entry(key, value)?;
+    Ok((key.to_string(), value.to_string()))
+}
+
+/// Check that an alias for [`VenvOptions::aliases`] is a plain file name with a target.
+///
+/// Whether the name collides with the interpreter links or the activators depends on the
+/// interpreter and is checked when creating the venv.
+pub fn check_alias(name: &str, target: &str) -> Result<(), String> {
+    if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\', '\0']) {
+        return Err(format!("`{name}` is not a file name"));
+    }
+    if target.is_empty() || target.contains('\0') {
+        return Err(format!("`{target}` is not a valid target for `{name}`"));
     }
     Ok(())
 }
 
+/// Parse a `name=target` alias for [`VenvOptions::aliases`].
+pub fn parse_alias(alias: &str) -> Result<(String, String), String> {
+    let (name, target) = alias
+        .split_once('=')
+        .ok_or_else(|| format!("Expected name=target, got {alias}"))?;
+    let (name, target) = (name.trim(), target.trim());
+    check_alias(name, target)?;
+    Ok((name.to_string(), target.to_string()))
+}
+
+/// The `pyvenv.cfg` of the venv at `location`.
+pub fn read_pyvenv_cfg(location: &Utf8Path) -> Result<PyVenvCfg, Error> {
+    PyVenvCfg::read(&location.join("pyvenv.cfg"))
+        .map_err(|_| Error::NotAVenv(location.to_path_buf()))
+}
+
+/// The packages we seed venvs with
+pub const SEED_PACKAGES: [&str; 3] = ["pip", "setuptools", "wheel"];
+
+/// The installed versions of the [`SEED_PACKAGES`] as (name, version), found through their
+/// `.dist-info` directories.
+pub fn seed_packages(paths: &VenvPaths) -> io::Result<Vec<(String, String)>> {
+    if !paths.site_packages.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut packages = Vec::new();
+    for entry in fs::read_dir(&paths.site_packages)? {
+        let file_name = entry?.file_name();
+        let Some((name, version)) = file_name
+            .to_str()
+            .and_then(|file_name| file_name.strip_suffix(".dist-info"))
+            .and_then(|stem| stem.split_once('-'))
+        else {
+            continue;
+        };
+        if SEED_PACKAGES.contains(&normalize_name(name).as_str()) {
+            packages.push((normalize_name(name), version.to_string()));
+        }
+    }
+    packages.sort();
+    Ok(packages)
+}
+
 /// Absolute paths of the virtualenv
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VenvPaths {
     /// The location of the virtualenv, e.g. `.venv`
     pub root: Utf8PathBuf,
@@ -46,81 +147,791 @@ pub struct VenvPaths {
     pub site_packages: Utf8PathBuf,
 }
 
+impl VenvPaths {
+    /// The layout of a venv at `root` for the given interpreter, following its sysconfig scheme,
+    /// which differs between platforms and distributions.
+    pub fn new(root: &Utf8Path, info: &InterpreterInfo) -> Self {
+        let bin = root.join(&info.sysconfig_paths.scripts);
+        Self {
+            root: root.to_path_buf(),
+            interpreter: bin.join(&interpreter_names(info)[0]),
+            bin,
+            site_packages: root.join(&info.sysconfig_paths.purelib),
+        }
+    }
+
+    /// The layout of an existing venv at `root` with the given python version, e.g. from
+    /// `pyvenv.cfg`, when we don't have the interpreter to query its sysconfig scheme.
+    ///
+    /// Uses the scheme directories gourgeist recorded in `pyvenv.cfg` and guesses the default
+    /// scheme for venvs from other tools.
+    pub fn with_version(root: &Utf8Path, major: u8, minor: u8) -> Self {
+        let bin = bin_dir(root);
+        let interpreter = venv_python(root);
+        let cfg = PyVenvCfg::read(&root.join("pyvenv.cfg")).unwrap_or_default();
+        let site_packages = if let Some(purelib) = cfg.get("gourgeist-purelib") {
+            root.join(purelib)
+        } else if cfg!(windows) {
+            root.join("Lib").join("site-packages")
+        } else {
+            let is_pypy = cfg
+                .get("implementation")
+                .is_some_and(|implementation| implementation.eq_ignore_ascii_case("pypy"));
+            let lib = if is_pypy { "pypy" } else { "python" };
+            root.join("lib")
+                .join(format!("{lib}{major}.{minor}"))
+                .join("site-packages")
+        };
+        Self {
+            root: root.to_path_buf(),
+            interpreter,
+            bin,
+            site_packages,
+        }
+    }
+}
+
+/// The directory with the scripts of the existing venv at `root`, as recorded in its `pyvenv.cfg`
+/// or else the default scheme's
+pub(crate) fn bin_dir(root: &Utf8Path) -> Utf8PathBuf {
+    let recorded = PyVenvCfg::read(&root.join("pyvenv.cfg"))
+        .ok()
+        .and_then(|cfg| {
+            cfg.get("gourgeist-scripts")
+                .map(|scripts| root.join(scripts))
+        });
+    if let Some(bin) = recorded {
+        return bin;
+    }
+    #[cfg(unix)]
+    {
+        root.join("bin")
+    }
+    #[cfg(windows)]
+    {
+        root.join("Scripts")
+    }
+    #[cfg(not(any(unix, windows)))]
+    {
+        compile_error!("only unix (like mac and linux) and windows are supported")
+    }
+}
+
+/// The python interpreter of the venv at `root`, which we need to query before we know the rest of
+/// the layout of an existing venv.
+pub(crate) fn venv_python(root: &Utf8Path) -> Utf8PathBuf {
+    #[cfg(unix)]
+    {
+        bin_dir(root).join("python")
+    }
+    #[cfg(windows)]
+    {
+        bin_dir(root).join("python.exe")
+    }
+    #[cfg(not(any(unix, windows)))]
+    {
+        compile_error!("only unix (like mac and linux) and windows are supported")
+    }
+}
+
 /// Write all the files that belong to a venv without any packages installed.
+///
+/// The files are written to `staging`, while all paths that end up in the files point to
+/// `location`, where the caller moves the venv to once it's complete.
 pub fn create_bare_venv(
     location: &Utf8Path,
+    staging: &Utf8Path,
     base_python: &Utf8Path,
     info: &InterpreterInfo,
+    options: &VenvOptions,
 ) -> io::Result<VenvPaths> {
-    if location.exists() {
-        if location.join("pyvenv.cfg").is_file() {
-            info!("Removing existing directory");
-            fs::remove_dir_all(location)?;
-        } else {
-            return Err(io::Error::new(
-                io::ErrorKind::AlreadyExists,
-                format!("The directory {location} exists, but it is not virtualenv"),
+    let paths = VenvPaths::new(location, info);
+    let staging = VenvPaths::new(staging, info);
+
+    fs::create_dir_all(&staging.bin)?;
+    // The files are independent of each other, so we write them concurrently, which helps
+    // especially on network filesystems
+    let tasks: Vec<WriteTask> = vec![
+        Box::new(|| {
+            // Keep a `.gitignore` the user put into the venv we're replacing
+            let existing_gitignore = location.join(".gitignore");
+            if existing_gitignore.is_file() {
+                fs::copy(&existing_gitignore, staging.root.join(".gitignore"))?;
+            } else if !options.no_gitignore {
+                fs::write(staging.root.join(".gitignore"), "*")?;
+            }
+            if !options.no_cachedir_tag {
+                fs::write(staging.root.join("CACHEDIR.TAG"), CACHEDIR_TAG)?;
+            }
+            Ok(())
+        }),
+        Box::new(|| write_interpreter(&staging, base_python, info, options)),
+        Box::new(|| write_activators(&staging, &paths, info, options)),
+        Box::new(|| write_pyvenv_cfg(&staging.root, base_python, info, options)),
+        Box::new(|| {
+            write_site_packages(&staging.site_packages)?;
+            // e.g. `lib64/python3.11/site-packages` on Fedora and openSUSE
+            if info.sysconfig_paths.platlib != info.sysconfig_paths.purelib {
+                fs::create_dir_all(staging.root.join(&info.sysconfig_paths.platlib))?;
+            }
+            Ok(())
+        }),
+    ];
+    // Reproducible venvs are written in a stable order
+    run_write_tasks(tasks, !options.reproducible)?;
+
+    Ok(paths)
+}
+
+type WriteTask<'a> = Box<dyn FnOnce() -> io::Result<()> + Send + 'a>;
+
+/// Run independent file writes, each on its own thread if `parallel` is set. Returns the first
+/// error in task order.
+fn run_write_tasks(tasks: Vec<WriteTask>, parallel: bool) -> io::Result<()> {
+    if !parallel {
+        return tasks.into_iter().try_for_each(|task| task());
+    }
+    thread::scope(|scope| {
+        let handles: Vec<_> = tasks.into_iter().map(|task| scope.spawn(task)).collect();
+        handles.into_iter().try_for_each(|handle| {
+            handle
+                .join()
+                .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+        })
+    })
+}
+
+/// Create the site-packages directory with the `_virtualenv` patch.
+fn write_site_packages(site_packages: &Utf8Path) -> io::Result<()> {
+    fs::create_dir_all(site_packages)?;
+    // Install _virtualenv.py patch.
+    // Frankly no idea what that does, i just copied it from virtualenv knowing that
+    // distutils/setuptools will have their cursed reasons
+    fs::write(site_packages.join("_virtualenv.py"), VIRTUALENV_PATCH)?;
+    fs::write(site_packages.join("_virtualenv.pth"), "import _virtualenv")?;
+    Ok(())
+}
+
+/// Point an existing venv to a new base interpreter of the same minor version, e.g. after a patch
+/// upgrade of the system python, like `python -m venv --upgrade`.
+///
+/// The interpreter links, the activators and `pyvenv.cfg` are rewritten, while the installed
+/// packages are left alone.
+pub fn upgrade_venv(
+    ctx: &Context,
+    location: &Utf8Path,
+    base_python: &Utf8Path,
+    info: &InterpreterInfo,
+    options: &VenvOptions,
+) -> Result<VenvPaths, Error> {
+    let location = &ctx.absolute(location);
+    let base_python = &base_executable(&ctx.absolute(base_python), info);
+    let old_cfg = read_pyvenv_cfg(location)?;
+    let (old_version, options) = &prepare_upgrade(location, info, options)?;
+    let paths = VenvPaths::new(&venv_root(location, options.no_resolve_symlinks)?, info);
+    info!(
+        "Upgrading {} from python {old_version} to {}",
+        paths.root, info.python_version
+    );
+    for name in interpreter_names(info) {
+        let path = paths.bin.join(name);
+        if path.symlink_metadata().is_ok() {
+            fs::remove_file(path)?;
+        }
+    }
+    write_interpreter(&paths, base_python, info, options)?;
+    write_activators(&paths, &paths, info, options)?;
+    upgrade_pyvenv_cfg(&old_cfg, base_python, info, options)?
+        .write(&paths.root.join("pyvenv.cfg"))?;
+    Ok(paths)
+}
+
+/// Check that the venv at the absolute `location` can be upgraded to `info` and return its old
+/// python version and the options to upgrade it with.
+pub(crate) fn prepare_upgrade(
+    location: &Utf8Path,
+    info: &InterpreterInfo,
+    options: &VenvOptions,
+) -> Result<(String, VenvOptions), Error> {
+    let cfg = read_pyvenv_cfg(location)?;
+    if is_frozen(location) {
+        return Err(Error::Frozen(location.to_path_buf()));
+    }
+    let old_version = cfg
+        .get("version_info")
+        .map(str::to_string)
+        .unwrap_or_default();
+    let new_minor = format!("{}.{}", info.major, info.minor);
+    if old_version != new_minor && !old_version.starts_with(&format!("{new_minor}.")) {
+        return Err(Error::UpgradeVersionMismatch {
+            location: location.to_path_buf(),
+            old: old_version,
+            new: info.python_version.clone(),
+        });
+    }
+
+    // Keep the venv relocatable, its access to the system site-packages and its logical path
+    let options = VenvOptions {
+        relocatable: options.relocatable || is_relocatable(location),
+        no_resolve_symlinks: options.no_resolve_symlinks || keeps_symlinks(location),
+        system_site_packages: options.system_site_packages || has_system_site_packages(location),
+        prompt: options
+            .prompt
+            .clone()
+            .or_else(|| cfg.get("prompt").map(str::to_string)),
+        ..options.clone()
+    };
+    Ok((old_version, options))
+}
+
+/// Fix a venv whose base interpreter was removed or upgraded.
+///
+/// Dangling or missing interpreter links are recreated pointing to `base_python`, links for another
+/// python version (e.g. `python3.11` after an upgrade to 3.12) are removed and missing activators
+/// are restored. Intact venvs are left alone.
+///
+/// Returns a description of each repair.
+pub fn repair_venv(
+    ctx: &Context,
+    location: &Utf8Path,
+    base_python: &Utf8Path,
+    info: &InterpreterInfo,
+    options: &VenvOptions,
+) -> Result<Vec<String>, Error> {
+    let location = &ctx.absolute(location);
+    let base_python = &base_executable(&ctx.absolute(base_python), info);
+    let cfg = read_pyvenv_cfg(location)?;
+    if is_frozen(location) {
+        return Err(Error::Frozen(location.to_path_buf()));
+    }
+    let options = &VenvOptions {
+        relocatable: options.relocatable || is_relocatable(location),
+        no_resolve_symlinks: options.no_resolve_symlinks || keeps_symlinks(location),
+        system_site_packages: options.system_site_packages || has_system_site_packages(location),
+        prompt: options
+            .prompt
+            .clone()
+            .or_else(|| cfg.get("prompt").map(str::to_string)),
+        ..options.clone()
+    };
+    let paths = VenvPaths::new(&venv_root(location, options.no_resolve_symlinks)?, info);
+    let mut repairs = Vec::new();
+
+    let expected = interpreter_names(info);
+    let mut relink = false;
+    for entry in fs::read_dir(&paths.bin)? {
+        let entry = entry?;
+        let Some(name) = entry.file_name().to_str().map(ToString::to_string) else {
+            continue;
+        };
+        if !is_interpreter_name(&name) {
+            continue;
+        }
+        if !expected.contains(&name) {
+            fs::remove_file(entry.path())?;
+            repairs.push(format!(
+                "Removed {name}, which is for another python version"
+            ));
+        } else if !entry.path().exists() {
+            relink = true;
+            repairs.push(format!(
+                "Relinked {name}, which pointed to a missing interpreter"
             ));
         }
     }
-    fs::create_dir_all(location)?;
-    // TODO: I bet on windows we'll have to strip the prefix again
-    let location = location.canonicalize_utf8()?;
-    let bin_dir = {
-        #[cfg(unix)]
-        {
-            location.join("bin")
+    for name in &expected {
+        if paths.bin.join(name).symlink_metadata().is_err() {
+            relink = true;
+            repairs.push(format!("Restored the missing {name}"));
         }
-        #[cfg(windows)]
-        {
-            location.join("Bin")
+    }
+    if relink {
+        for name in &expected {
+            let path = paths.bin.join(name);
+            if path.symlink_metadata().is_ok() {
+                fs::remove_file(path)?;
+            }
         }
-        #[cfg(not(any(unix, windows)))]
-        {
-            compile_error!("only unix (like mac and linux) and windows are supported")
+        write_interpreter(&paths, base_python, info, options)?;
+        upgrade_pyvenv_cfg(&cfg, base_python, info, options)?
+            .write(&paths.root.join("pyvenv.cfg"))?;
+        let old_version = cfg
+            .get("version_info")
+            .map(str::to_string)
+            .unwrap_or_default();
+        let new_minor = format!("{}.{}", info.major, info.minor);
+        if old_version != new_minor && !old_version.starts_with(&format!("{new_minor}.")) {
+            warn!(
+                "{} was created with python {old_version}, its packages are not visible to python \
+                {}, recreate the venv to reinstall them",
+                paths.root, info.python_version
+            );
+            if !paths.site_packages.is_dir() {
+                write_site_packages(&paths.site_packages)?;
+            }
+        }
+    }
+
+    let missing_activators: Vec<&str> = ACTIVATE_TEMPLATES
+        .iter()
+        .map(|(name, _)| *name)
+        .filter(|name| !paths.bin.join(name).is_file())
+        .collect();
+    // The activators also contain the site-packages path, so they need to match the new version
+    if !missing_activators.is_empty() || relink {
+        write_activators(&paths, &paths, info, options)?;
+        for name in missing_activators {
+            repairs.push(format!("Restored the missing {name}"));
         }
+    }
+    Ok(repairs)
+}
+
+/// Why the venv at `location` silently broke since its creation, e.g. because its base interpreter
+/// was upgraded or removed, or `None` if it still matches its base interpreter.
+///
+/// This compares the version and base executable recorded in `pyvenv.cfg` against the actual base
+/// interpreter, so it also works for venvs created by `python -m venv` or virtualenv.
+pub fn stale_venv(ctx: &Context, location: &Utf8Path) -> Result<Option<String>, Error> {
+    let location = &ctx.absolute(location);
+    let cfg = read_pyvenv_cfg(location)?;
+    // `python -m venv` records `executable` and `version` instead
+    let base_python = cfg.get("base-executable").or_else(|| cfg.get("executable"));
+    let recorded_version = cfg.get("version_info").or_else(|| cfg.get("version"));
+    let Some(base_python) = base_python.map(Utf8PathBuf::from) else {
+        return Ok(None);
     };
+    if !base_python.exists() {
+        return Ok(Some(format!(
+            "its base interpreter {base_python} doesn't exist anymore"
+        )));
+    }
+    if let Some(recorded_version) = recorded_version {
+        let info = get_interpreter_info(ctx, &base_python)?;
+        if *recorded_version != info.python_version {
+            return Ok(Some(format!(
+                "it was created with python {recorded_version}, but {base_python} is now python {}",
+                info.python_version
+            )));
+        }
+    }
+    if !venv_python(location).exists() {
+        return Ok(Some(format!(
+            "{} is a dangling link",
+            venv_python(location)
+        )));
+    }
+    Ok(None)
+}
+
+/// The path from the venv-relative directory `dir` back to the venv root, e.g. `../../..` for
+/// `lib/python3.11/site-packages`.
+pub(crate) fn relative_to_root(dir: &Utf8Path) -> Utf8PathBuf {
+    dir.components().map(|_| "..").collect()
+}
+
+/// The executable the venv interpreter links to.
+///
+/// For macOS framework builds (python.org installers, Xcode), this is the launcher stub in the
+/// framework's `bin`, even if we were given the real executable inside
+/// `Python.framework/Versions/X.Y/Resources/Python.app`: The stub passes its own path to the real
+/// executable through `__PYVENV_LAUNCHER__`, which is how python finds the venv, while running the
+/// executable in the app bundle is what allows GUI code to work. This is what venv and virtualenv
+/// link to as well.
+///
+/// For Microsoft Store pythons, this is the app execution alias of the python package, since the
+/// install directory is sandboxed and its executables can't be started directly.
+pub(crate) fn base_executable(base_python: &Utf8Path, info: &InterpreterInfo) -> Utf8PathBuf {
+    if let Some(family) = windows_store_family(info) {
+        // The aliases are in `%LOCALAPPDATA%\Microsoft\WindowsApps`, a generic `python3.11.exe`
+        // directly and one per package in a subdirectory named after the package family
+        let windows_apps = base_python.ancestors().find(|dir| {
+            dir.file_name() == Some("WindowsApps")
+                && dir.parent().and_then(Utf8Path::file_name) == Some("Microsoft")
+        });
+        if let Some(windows_apps) = windows_apps {
+            // The aliases are reparse points that `is_file` doesn't follow
+            let alias = windows_apps.join(family).join("python.exe");
+            if alias.symlink_metadata().is_ok() {
+                debug!("Using the app execution alias {alias} instead of {base_python}");
+                return alias;
+            }
+        }
+        return base_python.to_path_buf();
+    }
+    if !info.framework {
+        return base_python.to_path_buf();
+    }
+    let launcher = Utf8Path::new(&info.base_prefix)
+        .join("bin")
+        .join(format!("python{}.{}", info.major, info.minor));
+    if launcher.is_file() && launcher != base_python {
+        debug!("Using the framework launcher {launcher} instead of {base_python}");
+        launcher
+    } else {
+        base_python.to_path_buf()
+    }
+}
 
-    fs::write(location.join(".gitignore"), "*")?;
+/// The package family of a Microsoft Store python, e.g.
+/// `PythonSoftwareFoundation.Python.3.11_qbz5n2kfra8p0`, from its install directory, e.g.
+/// `C:\Program Files\WindowsApps\PythonSoftwareFoundation.Python.3.11_3.11.2544.0_x64__qbz5n2kfra8p0`.
+fn windows_store_family(info: &InterpreterInfo) -> Option<String> {
+    let dir_name = info.base_prefix.rsplit(['\\', '/']).next()?;
+    if !dir_name.starts_with("PythonSoftwareFoundation.Python.") {
+        return None;
+    }
+    let (name_version_arch, publisher) = dir_name.split_once("__")?;
+    let name = name_version_arch.split('_').next()?;
+    Some(format!("{name}_{publisher}"))
+}
 
-    // Different names for the python interpreter
-    fs::create_dir(&bin_dir)?;
+/// Copy the venv redirector of a Microsoft Store python as `python.exe` and `pythonw.exe`.
+///
+/// The redirector starts the interpreter from `home` in `pyvenv.cfg`, which is the app
+/// execution alias (see [`base_executable`]), while links or copies of the interpreter in the
+/// sandboxed install directory don't run. This is what venv does on windows.
+#[cfg(windows)]
+fn write_windows_store_launchers(staging: &VenvPaths, info: &InterpreterInfo) -> io::Result<()> {
+    let redirectors = Utf8Path::new(&info.base_prefix)
+        .join("Lib")
+        .join("venv")
+        .join("scripts")
+        .join("nt");
+    // Python 3.13 renamed the redirectors from `python.exe` and `pythonw.exe`
+    for (names, target) in [
+        (["venvlauncher.exe", "python.exe"], "python.exe"),
+        (["venvwlauncher.exe", "pythonw.exe"], "pythonw.exe"),
+    ] {
+        let redirector = names
+            .iter()
+            .map(|name| redirectors.join(name))
+            .find(|redirector| redirector.is_file())
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("The Microsoft Store python has no venv redirector in {redirectors}"),
+                )
+            })?;
+        fs::copy(redirector, staging.bin.join(target))?;
+    }
+    Ok(())
+}
+
+/// Whether `name` is `python` or a versioned alias such as `python3.11` or `pypy3` in `bin`
+pub(crate) fn is_interpreter_name(name: &str) -> bool {
+    let name = name.strip_suffix(".exe").unwrap_or(name);
+    name.strip_prefix("python")
+        .or_else(|| name.strip_prefix("pypy"))
+        .is_some_and(|version| version.chars().all(|c| c.is_ascii_digit() || c == '.'))
+}
+
+/// The `implementation` in `pyvenv.cfg`, spelled like `platform.python_implementation()`
+fn implementation_name(info: &InterpreterInfo) -> String {
+    match info.implementation.as_str() {
+        "cpython" => "CPython".to_string(),
+        "pypy" => "PyPy".to_string(),
+        "graalpy" => "GraalVM".to_string(),
+        other => other.to_string(),
+    }
+}
+
+/// The file names of the interpreter in `bin`, `python` first
+pub(crate) fn interpreter_names(info: &InterpreterInfo) -> Vec<String> {
     let venv_python = {
         #[cfg(unix)]
         {
-            bin_dir.join("python")
+            "python".to_string()
         }
         #[cfg(windows)]
         {
-            bin_dir.join("python.exe")
-        }
-        #[cfg(not(any(unix, windows)))]
-        {
-            compile_error!("only unix (like mac and linux) and windows are supported")
+            "python.exe".to_string()
         }
     };
+    let mut names = vec![
+        venv_python,
+        format!("python{}", info.major),
+        format!("python{}.{}", info.major, info.minor),
+    ];
+    // Like virtualenv, PyPy venvs also get the names the PyPy executable has
+    if info.implementation == "pypy" {
+        names.extend([
+            "pypy".to_string(),
+            format!("pypy{}", info.major),
+            format!("pypy{}.{}", info.major, info.minor),
+        ]);
+    }
+    names
+}
+
+/// PyPy loads its `libpypy3.9-c.so` from next to the executable, so copies and hardlinks of the
+/// executable need the library next to them. Symlinks resolve to the base executable's directory.
+#[cfg(unix)]
+fn link_pypy_libraries(
+    staging: &VenvPaths,
+    base_python: &Utf8Path,
+    link_mode: LinkMode,
+) -> io::Result<()> {
+    let Some(base_bin) = base_python
+        .canonicalize_utf8()?
+        .parent()
+        .map(Utf8Path::to_path_buf)
+    else {
+        return Ok(());
+    };
+    for entry in fs::read_dir(&base_bin)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        let is_library =
+            name.starts_with("libpypy") && (name.ends_with(".so") || name.ends_with(".dylib"));
+        if is_library {
+            let library = staging.bin.join(&name);
+            if link_mode.link_file(&entry.path(), library.as_std_path())? == LinkMode::Copy {
+                codesign::fix_signature(&library)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// How to materialize `bin/python` in the venv at `root`: [`VenvOptions::link_mode`] if set,
+/// otherwise [`LinkMode::interpreter_default`], except on network shares, where windows creates
+/// symlinks just fine but by default refuses to follow them, so we copy. Thinning a universal2
+/// interpreter requires a copy.
+pub(crate) fn interpreter_link_mode(
+    options: &VenvOptions,
+    info: &InterpreterInfo,
+    root: &Utf8Path,
+) -> LinkMode {
+    if options.universal2 == Universal2::Thin && info.universal2 {
+        return LinkMode::Copy;
+    }
+    match options.link_mode {
+        Some(link_mode) => link_mode,
+        None if is_unc(root) => LinkMode::Copy,
+        None => LinkMode::interpreter_default(),
+    }
+}
+
+/// Link or copy `bin/python` and its versioned aliases from the base interpreter.
+fn write_interpreter(
+    staging: &VenvPaths,
+    base_python: &Utf8Path,
+    info: &InterpreterInfo,
+    options: &VenvOptions,
+) -> io::Result<()> {
+    #[cfg(windows)]
+    if windows_store_family(info).is_some() {
+        return write_windows_store_launchers(staging, info);
+    }
+    let link_mode = interpreter_link_mode(options, info, &staging.root)
+        .link_file(base_python.as_std_path(), staging.interpreter.as_std_path())?;
+    if link_mode == LinkMode::Copy {
+        if options.universal2 == Universal2::Thin && info.universal2 {
+            universal2::thin(&staging.interpreter)?;
+        }
+        codesign::fix_signature(&staging.interpreter)?;
+    }
     #[cfg(unix)]
     {
-        symlink(base_python, &venv_python)?;
-        symlink("python", bin_dir.join(format!("python{}", info.major)))?;
-        symlink(
-            "python",
-            bin_dir.join(format!("python{}.{}", info.major, info.minor)),
-        )?;
+        for alias in interpreter_names(info).into_iter().skip(1) {
+            if link_mode == LinkMode::Symlink {
+                symlink("python", staging.bin.join(alias))?;
+            } else {
+                link_mode.link_file(
+                    staging.interpreter.as_std_path(),
+                    staging.bin.join(alias).as_std_path(),
+                )?;
+            }
+        }
+        if info.implementation == "pypy" && link_mode != LinkMode::Symlink {
+            link_pypy_libraries(staging, base_python, link_mode)?;
+        }
     }
+    #[cfg(windows)]
+    {
+        // GUI scripts run with `pythonw.exe`, which doesn't open a console window
+        let base_pythonw = base_python.with_file_name("pythonw.exe");
+        if base_pythonw.is_file() {
+            link_mode.link_file(
+                base_pythonw.as_std_path(),
+                staging.bin.join("pythonw.exe").as_std_path(),
+            )?;
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = info;
+    Ok(())
+}
 
-    // Add all the activate scripts for different shells
-    for (name, template) in ACTIVATE_TEMPLATES {
+/// Create the [`VenvOptions::aliases`] in `bin` as symlinks, with relative targets resolved
+/// against `bin`. On windows, the target is linked or copied instead if symlinks aren't
+/// available.
+///
+/// This runs after seeding, so an alias can't redirect the writes of a console script with the
+/// same name.
+pub(crate) fn write_aliases(bin: &Utf8Path, aliases: &[(String, String)]) -> io::Result<()> {
+    for (name, target) in aliases {
+        let path = bin.join(name);
+        if path.symlink_metadata().is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!(
+                    "The alias {name} conflicts with a console script or another file of the venv"
+                ),
+            ));
+        }
+        let resolved = bin.join(target);
+        if !resolved.exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("The target {target} of the alias {name} doesn't exist"),
+            ));
+        }
+        debug!("Linking {name} to {target}");
+        #[cfg(unix)]
+        {
+            let _ = resolved;
+            symlink(target, path)?;
+        }
+        #[cfg(windows)]
+        {
+            LinkMode::Symlink.link_file(resolved.as_std_path(), path.as_std_path())?;
+        }
+    }
+    Ok(())
+}
+
+/// Add all the activate scripts for different shells to `staging`, pointing to `paths`.
+///
+/// With [`VenvOptions::relocatable`], the activators determine the venv from their own location
+/// instead. This isn't possible for csh and xonsh, so their activators always use the
+/// absolute path.
+fn write_activators(
+    staging: &VenvPaths,
+    paths: &VenvPaths,
+    info: &InterpreterInfo,
+    options: &VenvOptions,
+) -> io::Result<()> {
+    for (name, activator) in render_activators(paths, info, options)? {
+        fs::write(staging.bin.join(&name), activator)?;
+    }
+    Ok(())
+}
+
+/// The names and contents of the activators of the venv at `paths`, see [`write_activators`].
+pub(crate) fn render_activators(
+    paths: &VenvPaths,
+    info: &InterpreterInfo,
+    options: &VenvOptions,
+) -> io::Result<Vec<(String, String)>> {
+    let mut activators = Vec::new();
+    for (name, template) in activator_templates(options.activator_templates.as_deref())? {
+        let relocatable_dir = match name.as_str() {
+            "activate" => Some(
+                r#""$(CDPATH= cd -- "$(dirname -- "${BASH_SOURCE[0]:-${(%):-%x}}")"/.. && pwd)""#,
+            ),
+            "activate.fish" => Some("(builtin realpath (dirname (status --current-filename))/..)"),
+            "activate.nu" => Some("($env.CURRENT_FILE | path dirname | path dirname)"),
+            "activate.elv" => Some("(path:dir (path:dir (src)[name]))"),
+            _ => None,
+        };
+        let template = match relocatable_dir {
+            Some(relocatable_dir) if options.relocatable => {
+                template.replace("'{{ VIRTUAL_ENV_DIR }}'", relocatable_dir)
+            }
+            _ => template,
+        };
         let activator = template
-            .replace("{{ VIRTUAL_ENV_DIR }}", location.as_str())
+            .replace("{{ VIRTUAL_ENV_DIR }}", paths.root.as_str())
+            .replace(
+                "{{ VIRTUAL_PROMPT }}",
+                &quote_prompt(&name, options.prompt.as_deref().unwrap_or_default()),
+            )
             .replace(
                 "{{ RELATIVE_SITE_PACKAGES }}",
-                &format!("../lib/python{}.{}/site-packages", info.major, info.minor),
+                relative_to_root(&info.sysconfig_paths.scripts)
+                    .join(&info.sysconfig_paths.purelib)
+                    .as_str(),
             );
-        fs::write(bin_dir.join(name), activator)?;
+        activators.push((name, activator));
     }
+    Ok(activators)
+}
 
-    // pyvenv.cfg
+/// The embedded activator templates, with the files in `custom_dir` replacing the embedded
+/// template of the same name or adding new activators.
+pub(crate) fn activator_templates(
+    custom_dir: Option<&Utf8Path>,
+) -> io::Result<Vec<(String, String)>> {
+    let mut templates: Vec<(String, String)> = ACTIVATE_TEMPLATES
+        .iter()
+        .map(|(name, template)| (name.to_string(), template.to_string()))
+        .collect();
+    let Some(custom_dir) = custom_dir else {
+        return Ok(templates);
+    };
+    let mut custom = Vec::new();
+    for entry in fs::read_dir(custom_dir)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_file() {
+            continue;
+        }
+        let name = entry
+            .file_name()
+            .to_str()
+            .map(ToString::to_string)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Non-utf8 activator template name in {custom_dir}"),
+                )
+            })?;
+        custom.push((name, fs::read_to_string(entry.path())?));
+    }
+    // Stable order for reproducible venvs
+    custom.sort();
+    for (name, template) in custom {
+        debug!("Using the custom activator template {custom_dir}/{name}");
+        if let Some(existing) = templates.iter_mut().find(|(existing, _)| *existing == name) {
+            existing.1 = template;
+        } else {
+            templates.push((name, template));
+        }
+    }
+    Ok(templates)
+}
+
+/// The prompt as a string literal in the language of the activator. The activators fall back to the
+/// venv directory name for an empty prompt.
+fn quote_prompt(activator: &str, prompt: &str) -> String {
+    match activator {
+        "activate" | "activate.csh" => format!("'{}'", prompt.replace('\'', r"'\''")),
+        "activate.fish" => format!("'{}'", prompt.replace('\\', r"\\").replace('\'', r"\'")),
+        "activate.elv" | "activate.ps1" => format!("'{}'", prompt.replace('\'', "''")),
+        "activate.nu" => format!("r#'{prompt}'#"),
+        // JSON strings are valid python strings, and custom activators get the same quoting
+        _ => serde_json::to_string(prompt).expect("Strings are serializable"),
+    }
+}
+
+fn write_pyvenv_cfg(
+    root: &Utf8Path,
+    base_python: &Utf8Path,
+    info: &InterpreterInfo,
+    options: &VenvOptions,
+) -> io::Result<()> {
+    render_pyvenv_cfg(base_python, info, options)?.write(&root.join("pyvenv.cfg"))
+}
+
+/// The `pyvenv.cfg` of a venv of `base_python` with the given options.
+pub(crate) fn render_pyvenv_cfg(
+    base_python: &Utf8Path,
+    info: &InterpreterInfo,
+    options: &VenvOptions,
+) -> io::Result<PyVenvCfg> {
+    // `site.py` can't read `home` in the extended-length form
+    let base_python = &simplified(base_python);
     let python_home = base_python
         .parent()
         .ok_or_else(|| {
@@ -130,37 +941,367 @@ pub fn create_bare_venv(
             )
         })?
         .to_string();
-    let pyvenv_cfg_data = &[
-        ("home", python_home),
-        ("implementation", "CPython".to_string()),
-        ("version_info", info.python_version.clone()),
+    let mut pyvenv_cfg = PyVenvCfg::default();
+    pyvenv_cfg.set("home", python_home)?;
+    pyvenv_cfg.set("implementation", implementation_name(info))?;
+    pyvenv_cfg.set("version_info", &info.python_version)?;
+    pyvenv_cfg.set("gourgeist", env!("CARGO_PKG_VERSION"))?;
+    pyvenv_cfg.set(
+        "include-system-site-packages",
+        options.system_site_packages.to_string(),
+    )?;
+    pyvenv_cfg.set("base-prefix", &info.base_prefix)?;
+    pyvenv_cfg.set("base-exec-prefix", &info.base_exec_prefix)?;
+    pyvenv_cfg.set("base-executable", base_python.as_str())?;
+    pyvenv_cfg.set("gourgeist-options", options.fingerprint())?;
+    if options.relocatable {
+        pyvenv_cfg.set("relocatable", "true")?;
+    }
+    if options.no_resolve_symlinks {
+        pyvenv_cfg.set("resolve-symlinks", "false")?;
+    }
+    if let Some(prompt) = &options.prompt {
+        pyvenv_cfg.set("prompt", prompt)?;
+    }
+    if let Some(command) = &options.command {
+        pyvenv_cfg.set("command", command)?;
+    }
+    // The layout of the venv for when we don't have the interpreter, see `VenvPaths::with_version`
+    pyvenv_cfg.set("gourgeist-scripts", info.sysconfig_paths.scripts.as_str())?;
+    pyvenv_cfg.set("gourgeist-purelib", info.sysconfig_paths.purelib.as_str())?;
+    for (key, value) in &options.extra_pyvenv_cfg {
+        pyvenv_cfg.set(key, value)?;
+    }
+    Ok(pyvenv_cfg)
+}
+
+/// The `pyvenv.cfg` of an upgraded or repaired venv: the entries of [`render_pyvenv_cfg`] on top
+/// of the `old` ones, so entries written by other tools survive. `command` and `gourgeist-options`
+/// describe how the venv was created, so their old values are kept.
+pub(crate) fn upgrade_pyvenv_cfg(
+    old: &PyVenvCfg,
+    base_python: &Utf8Path,
+    info: &InterpreterInfo,
+    options: &VenvOptions,
+) -> io::Result<PyVenvCfg> {
+    let mut pyvenv_cfg = old.clone();
+    for (key, value) in render_pyvenv_cfg(base_python, info, options)?.entries() {
+        if matches!(key, "command" | "gourgeist-options") && old.get(key).is_some() {
+            continue;
+        }
+        pyvenv_cfg.set(key, value)?;
+    }
+    Ok(pyvenv_cfg)
+}
+
+/// Whether the venv at `root` was created with [`VenvOptions::relocatable`]
+pub(crate) fn is_relocatable(root: &Utf8Path) -> bool {
+    PyVenvCfg::read(&root.join("pyvenv.cfg"))
+        .is_ok_and(|cfg| cfg.get("relocatable") == Some("true"))
+}
+
+/// Whether the venv at `root` was created with [`VenvOptions::no_resolve_symlinks`]
+pub(crate) fn keeps_symlinks(root: &Utf8Path) -> bool {
+    PyVenvCfg::read(&root.join("pyvenv.cfg"))
+        .is_ok_and(|cfg| cfg.get("resolve-symlinks") == Some("false"))
+}
+
+/// Whether the venv at `root` can see the packages of its base interpreter
+pub(crate) fn has_system_site_packages(root: &Utf8Path) -> bool {
+    PyVenvCfg::read(&root.join("pyvenv.cfg")).is_ok_and(|cfg| {
+        cfg.get("include-system-site-packages")
+            .is_some_and(|value| value.eq_ignore_ascii_case("true"))
+    })
+}
+
+/// Allow or disallow the venv at `location` to see the packages of its base interpreter by
+/// editing `include-system-site-packages` in its `pyvenv.cfg`. All other entries are kept as they
+/// are.
+pub fn set_system_site_packages(
+    ctx: &Context,
+    location: &Utf8Path,
+    enabled: bool,
+) -> Result<(), Error> {
+    let location = &ctx.absolute(location);
+    let mut pyvenv_cfg = read_pyvenv_cfg(location)?;
+    if is_frozen(location) {
+        return Err(Error::Frozen(location.to_path_buf()));
+    }
+    pyvenv_cfg.set("include-system-site-packages", enabled.to_string())?;
+    write_atomic(
+        &location.join("pyvenv.cfg"),
+        pyvenv_cfg.to_string().as_bytes(),
+    )?;
+    Ok(())
+}
+
+/// Whether the venv at `location` was created by this gourgeist version from the same interpreter
+/// with the same options, so we can reuse it instead of recreating it.
+pub fn matches_existing(
+    location: &Utf8Path,
+    base_python: &Utf8Path,
+    info: &InterpreterInfo,
+    options: &VenvOptions,
+) -> bool {
+    let Ok(cfg) = PyVenvCfg::read(&location.join("pyvenv.cfg")) else {
+        return false;
+    };
+    let expected = [
         ("gourgeist", env!("CARGO_PKG_VERSION").to_string()),
-        // I wouldn't allow this option anyway
-        ("include-system-site-packages", "false".to_string()),
-        ("base-prefix", info.base_prefix.clone()),
-        ("base-exec-prefix", info.base_exec_prefix.clone()),
+        // Not part of the fingerprint since it can be toggled with `set_system_site_packages`
+        (
+            "include-system-site-packages",
+            options.system_site_packages.to_string(),
+        ),
         ("base-executable", base_python.to_string()),
+        ("version_info", info.python_version.clone()),
+        ("gourgeist-options", options.fingerprint()),
     ];
-    let mut pyvenv_cfg = BufWriter::new(File::create(location.join("pyvenv.cfg"))?);
-    write_cfg(&mut pyvenv_cfg, pyvenv_cfg_data)?;
-    drop(pyvenv_cfg);
-
-    // TODO: This is different on windows
-    let site_packages = location
-        .join("lib")
-        .join(format!("python{}.{}", info.major, info.minor))
-        .join("site-packages");
-    fs::create_dir_all(&site_packages)?;
-    // Install _virtualenv.py patch.
-    // Frankly no idea what that does, i just copied it from virtualenv knowing that
-    // distutils/setuptools will have their cursed reasons
-    fs::write(site_packages.join("_virtualenv.py"), VIRTUALENV_PATCH)?;
-    fs::write(site_packages.join("_virtualenv.pth"), "import _virtualenv")?;
+    let matches = expected
+        .iter()
+        .all(|(key, value)| cfg.get(key) == Some(value.as_str()));
+    // The interpreter link may have been broken by removing the base python
+    matches && VenvPaths::new(location, info).interpreter.exists()
+}
+
+/// Check that we may replace the absolute `location` and return its canonical path, or the
+/// normalized path with [`VenvOptions::no_resolve_symlinks`].
+///
+/// An existing directory is only replaced if it's a venv (it contains a `pyvenv.cfg`) or empty,
+/// unless `force` is set, so pointing gourgeist at the wrong path doesn't delete arbitrary data.
+///
+/// The parent directory is created if missing, but the location itself is not, that is done by
+/// moving the staging directory there in [`replace_with_staging`].
+pub fn prepare_location(location: &Utf8Path, options: &VenvOptions) -> io::Result<Utf8PathBuf> {
+    if check_location(location, options.force)? {
+        warn!("Replacing {location}, which is not a virtualenv");
+    }
+    // TODO: I bet on windows we'll have to strip the prefix again
+    if location.exists() {
+        return venv_root(location, options.no_resolve_symlinks);
+    }
+    let name = location.file_name().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("The venv location {location} must end with a directory name"),
+        )
+    })?;
+    let parent = location.parent().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("The venv location {location} must have a parent directory"),
+        )
+    })?;
+    if !parent.is_dir() {
+        debug!("Creating the missing parent directories {parent}");
+        fs::create_dir_all(parent)?;
+    }
+    Ok(venv_root(parent, options.no_resolve_symlinks)?.join(name))
+}
+
+/// Whether the venv at `location` is the active one, i.e. the caller's `VIRTUAL_ENV` or the venv
+/// of the running executable (e.g. a python that uses gourgeist as a library).
+pub fn is_active_venv(ctx: &Context, location: &Utf8Path) -> bool {
+    let location = &ctx.absolute(location);
+    if !location.join("pyvenv.cfg").is_file() {
+        return false;
+    }
+    let Ok(root) = canonicalize(location) else {
+        return false;
+    };
+    let is_virtual_env = ctx
+        .var("VIRTUAL_ENV")
+        .and_then(|venv| canonicalize(&ctx.absolute(Utf8Path::new(venv))).ok())
+        .is_some_and(|venv| venv == root);
+    // `current_exe` resolves the interpreter symlink on linux, but `argv[0]` is the venv python
+    // if it was started by path
+    let runs_from_venv = [
+        std::env::current_exe().ok(),
+        std::env::args_os().next().map(PathBuf::from),
+    ]
+    .into_iter()
+    .flatten()
+    .filter(|exe| exe.components().count() > 1)
+    .filter_map(|exe| Utf8PathBuf::from_path_buf(exe).ok())
+    .filter_map(|exe| canonicalize(&ctx.absolute(exe.parent()?)).ok())
+    .any(|exe_dir| exe_dir.starts_with(&root));
+    is_virtual_env || runs_from_venv
+}
 
-    Ok(VenvPaths {
-        root: location.to_path_buf(),
-        interpreter: venv_python,
-        bin: bin_dir,
-        site_packages,
+/// Refuse to replace the venv at `location` if it's the active one (see [`is_active_venv`]),
+/// unless `force` is set.
+pub(crate) fn check_active(ctx: &Context, location: &Utf8Path, force: bool) -> Result<(), Error> {
+    if !is_active_venv(ctx, location) {
+        return Ok(());
+    }
+    let root = canonicalize(location)?;
+    if !force {
+        return Err(Error::ActiveVenv(root));
+    }
+    warn!("Replacing {root}, which is the active virtualenv, the shells and processes using it will break");
+    Ok(())
+}
+
+/// Refuse to delete or replace the filesystem root, the home directory or the prefix of the base
+/// interpreter, where a wrong venv path would be catastrophic, unless `allow` is set.
+pub(crate) fn check_dangerous(
+    ctx: &Context,
+    location: &Utf8Path,
+    info: Option<&InterpreterInfo>,
+    allow: bool,
+) -> Result<(), Error> {
+    if allow {
+        return Ok(());
+    }
+    let Ok(root) = canonicalize(&ctx.absolute(location)) else {
+        return Ok(());
+    };
+    let is_root = |path: &str| {
+        canonicalize(&ctx.absolute(Utf8Path::new(path))).is_ok_and(|path| path == root)
+    };
+    let reason = if root.parent().is_none() {
+        "the filesystem root"
+    } else if ctx
+        .var("HOME")
+        .or_else(|| ctx.var("USERPROFILE"))
+        .is_some_and(is_root)
+    {
+        "the home directory"
+    } else if info.is_some_and(|info| is_root(&info.base_prefix) || is_root(&info.base_exec_prefix))
+    {
+        "the prefix of the base interpreter"
+    } else {
+        return Ok(());
+    };
+    Err(Error::DangerousLocation {
+        location: root,
+        reason,
     })
 }
+
+/// The checks of [`prepare_location`] without touching the filesystem, returning whether a
+/// directory that is neither empty nor a venv would be replaced because of `force`.
+pub(crate) fn check_location(location: &Utf8Path, force: bool) -> io::Result<bool> {
+    if location.exists() {
+        if !location.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{location} exists, but it is not a directory"),
+            ));
+        }
+        let is_venv = location.join("pyvenv.cfg").is_file();
+        if !is_venv && fs::read_dir(location)?.next().is_some() {
+            if !force {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!(
+                        "The directory {location} exists, but it is not virtualenv \
+                        (use --force to replace it anyway)"
+                    ),
+                ));
+            }
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Check that [`remove_venv`] would delete `location`, without deleting it.
+pub fn check_removable(
+    ctx: &Context,
+    location: &Utf8Path,
+    allow_dangerous_location: bool,
+) -> Result<(), Error> {
+    let location = &ctx.absolute(location);
+    if !location.join("pyvenv.cfg").is_file() {
+        return Err(Error::NotAVenv(location.to_path_buf()));
+    }
+    if !location.is_symlink() {
+        check_dangerous(ctx, location, None, allow_dangerous_location)?;
+    }
+    Ok(())
+}
+
+/// Delete the venv at `location`, including read-only (e.g. frozen) files, and return its canonical
+/// path.
+///
+/// Only directories with a `pyvenv.cfg` are deleted, so pointing gourgeist at the wrong path doesn't
+/// delete arbitrary data. A symlink to a venv is removed without touching the venv itself. The
+/// filesystem root and the home directory are only deleted with `allow_dangerous_location`.
+pub fn remove_venv(
+    ctx: &Context,
+    location: &Utf8Path,
+    allow_dangerous_location: bool,
+) -> Result<Utf8PathBuf, Error> {
+    check_removable(ctx, location, allow_dangerous_location)?;
+    let location = &ctx.absolute(location);
+    if location.is_symlink() {
+        fs::remove_file(location)?;
+        return Ok(location.to_path_buf());
+    }
+    let location = canonicalize(location)?;
+    // Deeply nested site-packages exceed `MAX_PATH` on windows
+    make_writable(&extended(&location))?;
+    fs::remove_dir_all(extended(&location))?;
+    Ok(location)
+}
+
+/// Create an empty staging directory next to `location`, so it can be renamed into place.
+///
+/// The directory is removed when dropped, i.e. if creating the venv fails halfway. On windows, its
+/// path has the extended-length prefix, so the files below it may exceed `MAX_PATH`.
+pub fn staging_dir(location: &Utf8Path) -> io::Result<TempDir> {
+    let parent = location.parent().unwrap_or(Utf8Path::new("."));
+    let staging = tempfile::Builder::new()
+        .prefix(".gourgeist-")
+        .tempdir_in(extended(parent))?;
+    // Temp dirs are private by default, but the venv should get the usual directory permissions
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(staging.path(), std::fs::Permissions::from_mode(0o755))?;
+    }
+    Ok(staging)
+}
+
+/// Atomically move the finished venv from `staging` to `location`, replacing an existing venv.
+///
+/// An existing venv is first moved aside, so `location` never contains a half-deleted venv.
+pub fn replace_with_staging(staging: TempDir, location: &Utf8Path) -> io::Result<()> {
+    let location = &extended(location);
+    let old = if location.exists() {
+        info!("Removing existing directory");
+        let parent = location.parent().unwrap_or(Utf8Path::new("."));
+        let old = tempfile::Builder::new()
+            .prefix(".gourgeist-old-")
+            .tempdir_in(parent)?;
+        fs::rename(location, old.path().join("venv"))?;
+        Some(old)
+    } else {
+        None
+    };
+    // The staging dir is gone after the rename, so dropping the `TempDir` is a no-op
+    if let Err(err) = fs::rename(staging.path(), location) {
+        // Put the old venv back instead of deleting it with its `TempDir`
+        if let Some(old) = old {
+            let old_venv = old.path().join("venv");
+            if let Err(restore_err) = fs::rename(&old_venv, location) {
+                warn!(
+                    "Failed to move the old venv back to {location} ({restore_err}), it's at {}",
+                    old_venv.display()
+                );
+                // Don't delete the old venv with its `TempDir`
+                std::mem::forget(old);
+                return Err(err);
+            }
+            old.close()?;
+        }
+        return Err(err);
+    }
+    drop(staging);
+    // Delete the old venv, if any
+    if let Some(old) = old {
+        old.close()?;
+    }
+    Ok(())
+}
@@ -0,0 +1,180 @@
+//! Measure how long creating venvs takes, optionally compared to `venv` and `virtualenv`
+
+use crate::interpreter::InterpreterInfo;
+use crate::{create_venv, Context, Error, VenvOptions};
+use camino::Utf8Path;
+use serde::Serialize;
+use std::io;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+use tracing::{debug, warn};
+
+/// The durations of repeatedly creating a venv in one configuration
+#[derive(Debug, Clone, Serialize)]
+pub struct BenchResult {
+    /// E.g. `gourgeist --bare` or `python -m venv`
+    pub name: String,
+    #[serde(serialize_with = "serialize_millis")]
+    pub samples: Vec<Duration>,
+}
+
+impl BenchResult {
+    pub fn mean(&self) -> Duration {
+        self.samples.iter().sum::<Duration>() / self.samples.len().max(1) as u32
+    }
+
+    /// The nearest-rank percentile, e.g. `percentile(90.0)` for the p90
+    pub fn percentile(&self, percentile: f64) -> Duration {
+        let mut sorted = self.samples.clone();
+        sorted.sort();
+        let rank = (percentile / 100.0 * sorted.len() as f64).ceil() as usize;
+        sorted
+            .get(rank.saturating_sub(1))
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+fn serialize_millis<S: serde::Serializer>(
+    samples: &[Duration],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(samples.iter().map(|sample| sample.as_secs_f64() * 1000.0))
+}
+
+/// Create `iterations` bare and seeded venvs each in a temporary directory and record the
+/// durations.
+///
+/// With `compare`, the same is done with `python -m venv` and, if it's in `PATH`, `virtualenv`.
+/// The first run of each configuration is a warmup that isn't recorded, so e.g. the interpreter
+/// and wheel caches are populated as they would be in regular use.
+pub fn bench(
+    ctx: &Context,
+    base_python: &Utf8Path,
+    info: &InterpreterInfo,
+    iterations: usize,
+    compare: bool,
+) -> Result<Vec<BenchResult>, Error> {
+    let temp_dir = TempDir::new()?;
+    let temp_dir = Utf8Path::from_path(temp_dir.path()).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Non-utf8 temporary directory {}", temp_dir.path().display()),
+        )
+    })?;
+
+    let mut results = Vec::new();
+    for bare in [true, false] {
+        let options = VenvOptions {
+            bare,
+            rebuild: true,
+            ..VenvOptions::default()
+        };
+        let name = if bare {
+            "gourgeist --bare"
+        } else {
+            "gourgeist"
+        };
+        results.push(measure(name, temp_dir, iterations, |location| {
+            create_venv(ctx, location, base_python, info, &options)?;
+            Ok(())
+        })?);
+    }
+
+    if compare {
+        let venv = |args: &'static [&'static str]| {
+            move |location: &Utf8Path| {
+                let mut command = Command::new(base_python);
+                command.args(["-m", "venv", "--clear"]).args(args);
+                run_tool(command, location)
+            }
+        };
+        results.push(measure(
+            "python -m venv --without-pip",
+            temp_dir,
+            iterations,
+            venv(&["--without-pip"]),
+        )?);
+        results.push(measure("python -m venv", temp_dir, iterations, venv(&[]))?);
+
+        match which::which_in("virtualenv", ctx.var("PATH"), &ctx.cwd) {
+            Ok(virtualenv) => {
+                let virtualenv = |args: &'static [&'static str]| {
+                    let virtualenv = virtualenv.clone();
+                    move |location: &Utf8Path| {
+                        let mut command = Command::new(&virtualenv);
+                        command.arg("-p").arg(base_python).args(args);
+                        run_tool(command, location)
+                    }
+                };
+                results.push(measure(
+                    "virtualenv --no-seed",
+                    temp_dir,
+                    iterations,
+                    virtualenv(&["--no-seed"]),
+                )?);
+                results.push(measure(
+                    "virtualenv",
+                    temp_dir,
+                    iterations,
+                    virtualenv(&[]),
+                )?);
+            }
+            Err(err) => warn!("Skipping virtualenv, it's not installed ({err})"),
+        }
+    }
+    Ok(results)
+}
+
+/// Run the operation `iterations` times after a warmup, each time with a new location
+fn measure(
+    name: &str,
+    temp_dir: &Utf8Path,
+    iterations: usize,
+    mut operation: impl FnMut(&Utf8Path) -> Result<(), Error>,
+) -> Result<BenchResult, Error> {
+    debug!("Benchmarking {name}");
+    let mut samples = Vec::new();
+    for iteration in 0..=iterations {
+        let slug: String = name
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        let location = temp_dir.join(format!("{slug}-{iteration}"));
+        let start = Instant::now();
+        operation(&location)?;
+        // The warmup run isn't recorded
+        if iteration > 0 {
+            samples.push(start.elapsed());
+        }
+    }
+    Ok(BenchResult {
+        name: name.to_string(),
+        samples,
+    })
+}
+
+/// Run another venv tool with the location as last argument
+fn run_tool(mut command: Command, location: &Utf8Path) -> Result<(), Error> {
+    let command_str = format!("{command:?}");
+    let output = command
+        .arg(location)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|err| Error::Run {
+            command: command_str.clone(),
+            err,
+        })?;
+    if !output.status.success() {
+        return Err(Error::Run {
+            command: command_str,
+            err: io::Error::other(format!(
+                "{}\n{}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )),
+        });
+    }
+    Ok(())
+}
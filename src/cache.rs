@@ -0,0 +1,403 @@
+//! Inspect and purge the cache
+
+use crate::interpreter::CacheEntry;
+use crate::{Context, Error};
+use camino::{Utf8Path, Utf8PathBuf};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use fs_err as fs;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::io::Read;
+use std::io::Write;
+use std::time::{Duration, SystemTime};
+use tempfile::NamedTempFile;
+use tracing::debug;
+
+/// Touched after each garbage collection, so [`gc_cache_periodically`] runs at most once a day
+const LAST_GC_MARKER: &str = ".last-gc";
+
+/// The first entry of a cache archive, see [`export_cache`]
+const ARCHIVE_METADATA: &str = "gourgeist-cache.json";
+
+/// The buckets in a cache archive. The store isn't archived, its files are shared with venvs
+const ARCHIVED_BUCKETS: [CacheBucket; 2] = [CacheBucket::Interpreters, CacheBucket::Wheels];
+
+/// Bumped when the cache entries change incompatibly, archives with another layout are rejected
+const CACHE_LAYOUT: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ArchiveMetadata {
+    layout: u32,
+    /// The gourgeist version that wrote the archive, for the error message
+    version: String,
+}
+
+/// Marks a directory as disposable for backup tools and `tar --exclude-caches`, see
+/// <https://bford.info/cachedir/>
+pub(crate) const CACHEDIR_TAG: &str = "Signature: 8a477f597d28d172789f06886806bc55
+# This file is a cache directory tag created by gourgeist.
+# For information about cache directory tags, see:
+#	https://bford.info/cachedir/
+";
+
+/// A directory in the cache dir with one kind of cache entries
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheBucket {
+    /// The results of querying interpreters, see [`crate::get_interpreter_info`]
+    Interpreters,
+    /// The downloaded seed wheels
+    Wheels,
+    /// The installed seed package files shared between venvs, see [`crate::VenvOptions::store`]
+    Store,
+}
+
+impl CacheBucket {
+    pub const ALL: [CacheBucket; 3] = [
+        CacheBucket::Interpreters,
+        CacheBucket::Wheels,
+        CacheBucket::Store,
+    ];
+
+    /// The name of the directory in the cache dir
+    pub fn dir_name(self) -> &'static str {
+        match self {
+            CacheBucket::Interpreters => "interpreter_info",
+            CacheBucket::Wheels => "wheels",
+            CacheBucket::Store => "store",
+        }
+    }
+
+    pub fn path(self, ctx: &Context) -> io::Result<Utf8PathBuf> {
+        Ok(ctx.cache_dir()?.join(self.dir_name()))
+    }
+
+    /// Create the bucket directory, tagging the cache dir with a `CACHEDIR.TAG` on first use.
+    pub(crate) fn create(self, ctx: &Context) -> io::Result<Utf8PathBuf> {
+        let path = self.path(ctx)?;
+        fs::create_dir_all(&path)?;
+        let tag = ctx.cache_dir()?.join("CACHEDIR.TAG");
+        if !tag.is_file() {
+            write_atomic(&tag, CACHEDIR_TAG.as_bytes())?;
+        }
+        Ok(path)
+    }
+}
+
+impl Display for CacheBucket {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.pad(self.dir_name())
+    }
+}
+
+/// The total size of the files in the bucket in bytes, 0 if it doesn't exist yet.
+pub fn cache_size(ctx: &Context, bucket: CacheBucket) -> io::Result<u64> {
+    let path = bucket.path(ctx)?;
+    if !path.is_dir() {
+        return Ok(0);
+    }
+    dir_size(&path)
+}
+
+/// Remove all entries of the bucket and return how many bytes were freed.
+pub fn clean_cache(ctx: &Context, bucket: CacheBucket) -> io::Result<u64> {
+    let path = bucket.path(ctx)?;
+    if !path.is_dir() {
+        return Ok(0);
+    }
+    let size = dir_size(&path)?;
+    fs::remove_dir_all(&path)?;
+    Ok(size)
+}
+
+/// What a garbage collection removed
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CacheGc {
+    pub removed: usize,
+    pub freed: u64,
+}
+
+/// Remove the interpreter info of interpreters that don't exist anymore and the wheels that
+/// weren't used for `max_age`.
+pub fn gc_cache(ctx: &Context, max_age: Duration) -> io::Result<CacheGc> {
+    let mut gc = CacheGc::default();
+    let now = SystemTime::now();
+    for bucket in CacheBucket::ALL {
+        let path = bucket.path(ctx)?;
+        if !path.is_dir() {
+            continue;
+        }
+        for entry in fs::read_dir(&path)? {
+            let entry = entry?;
+            let path = Utf8PathBuf::try_from(entry.path()).map_err(|err| err.into_io_error())?;
+            let metadata = entry.metadata()?;
+            if !metadata.is_file() {
+                continue;
+            }
+            let unused = match bucket {
+                CacheBucket::Interpreters => {
+                    // Broken entries are removed too, they would be replaced anyway
+                    let cache_entry: Option<CacheEntry> = fs::read(&path)
+                        .ok()
+                        .and_then(|cache_entry| serde_json::from_slice(&cache_entry).ok());
+                    !cache_entry.is_some_and(|cache_entry| cache_entry.key.interpreter.is_file())
+                }
+                CacheBucket::Wheels => {
+                    let age = now.duration_since(metadata.modified()?).unwrap_or_default();
+                    age > max_age
+                }
+                // The store only has directories, which venvs link into
+                CacheBucket::Store => false,
+            };
+            if unused {
+                debug!("Removing unused cache entry {path}");
+                fs::remove_file(&path)?;
+                gc.removed += 1;
+                gc.freed += metadata.len();
+            }
+        }
+    }
+    Ok(gc)
+}
+
+/// Run [`gc_cache`] if it didn't run in the last day, so it can be called after each creation.
+pub fn gc_cache_periodically(ctx: &Context, max_age: Duration) -> io::Result<Option<CacheGc>> {
+    let marker = ctx.cache_dir()?.join(LAST_GC_MARKER);
+    let last_gc = fs::metadata(&marker).and_then(|metadata| metadata.modified());
+    if let Ok(last_gc) = last_gc {
+        let since = SystemTime::now()
+            .duration_since(last_gc)
+            .unwrap_or_default();
+        if since < Duration::from_secs(24 * 60 * 60) {
+            return Ok(None);
+        }
+    }
+    let gc = gc_cache(ctx, max_age)?;
+    fs::create_dir_all(ctx.cache_dir()?)?;
+    fs::write(&marker, "")?;
+    Ok(Some(gc))
+}
+
+/// Write the interpreter info and wheels into a `.tar.gz` (or `.tgz`, or uncompressed `.tar`)
+/// archive, e.g. to restore them on another machine or in a CI cache step. Returns the number of
+/// cache entries written.
+pub fn export_cache(ctx: &Context, archive: &Utf8Path) -> Result<usize, Error> {
+    let compressed = is_compressed(archive)?;
+    let parent = archive.parent().unwrap_or(Utf8Path::new("."));
+    let tempfile = NamedTempFile::new_in(if parent.as_str().is_empty() {
+        Utf8Path::new(".")
+    } else {
+        parent
+    })?;
+    let entries = if compressed {
+        let mut builder =
+            tar::Builder::new(GzEncoder::new(tempfile.as_file(), Compression::default()));
+        let entries = append_cache_entries(ctx, &mut builder)?;
+        // Dropping the encoder would swallow the error of writing the gzip trailer
+        builder.into_inner()?.finish()?.flush()?;
+        entries
+    } else {
+        let mut builder = tar::Builder::new(tempfile.as_file());
+        let entries = append_cache_entries(ctx, &mut builder)?;
+        builder.into_inner()?.flush()?;
+        entries
+    };
+    tempfile.persist(archive)?;
+    Ok(entries)
+}
+
+/// Add the metadata and the entries of the archived buckets to the archive.
+fn append_cache_entries(
+    ctx: &Context,
+    builder: &mut tar::Builder<impl Write>,
+) -> Result<usize, Error> {
+    let metadata = serde_json::to_vec(&ArchiveMetadata {
+        layout: CACHE_LAYOUT,
+        version: env!("CARGO_PKG_VERSION").to_string(),
+    })
+    .map_err(io::Error::from)?;
+    append_file(
+        builder,
+        ARCHIVE_METADATA,
+        &metadata[..],
+        metadata.len() as u64,
+    )?;
+    let mut entries = 0;
+    for bucket in ARCHIVED_BUCKETS {
+        let path = bucket.path(ctx)?;
+        if !path.is_dir() {
+            continue;
+        }
+        for entry in fs::read_dir(&path)? {
+            let entry = entry?;
+            let file_name = entry.file_name();
+            let Some(file_name) = file_name.to_str() else {
+                continue;
+            };
+            // Skip the temporary files of concurrent writes
+            if file_name.starts_with('.') || !entry.metadata()?.is_file() {
+                continue;
+            }
+            let file = fs::File::open(path.join(file_name))?;
+            let len = file.metadata()?.len();
+            append_file(
+                builder,
+                &format!("{}/{file_name}", bucket.dir_name()),
+                file,
+                len,
+            )?;
+            entries += 1;
+        }
+    }
+    Ok(entries)
+}
+
+/// Add the entries of an archive written by [`export_cache`] to the cache, replacing existing
+/// entries with the same name. Returns the number of cache entries read.
+///
+/// Archives with a different cache layout or entries outside the cache buckets are rejected
+/// before anything is written.
+pub fn import_cache(ctx: &Context, archive: &Utf8Path) -> Result<usize, Error> {
+    let invalid = |message: String| Error::InvalidCacheArchive {
+        path: archive.to_path_buf(),
+        message,
+    };
+    let file = fs::File::open(archive)?;
+    let reader: Box<dyn Read> = if is_compressed(archive)? {
+        Box::new(GzDecoder::new(file))
+    } else {
+        Box::new(file)
+    };
+    let mut tar = tar::Archive::new(reader);
+    let mut entries = tar.entries()?;
+
+    let Some(first) = entries.next() else {
+        return Err(invalid("The archive is empty".to_string()));
+    };
+    let mut first = first?;
+    if first.path()?.to_str() != Some(ARCHIVE_METADATA) {
+        return Err(invalid(format!(
+            "The archive doesn't start with {ARCHIVE_METADATA}, it wasn't written by `gourgeist cache export`"
+        )));
+    }
+    let mut metadata = Vec::new();
+    first.read_to_end(&mut metadata)?;
+    let metadata: ArchiveMetadata =
+        serde_json::from_slice(&metadata).map_err(|err| invalid(err.to_string()))?;
+    if metadata.layout != CACHE_LAYOUT {
+        return Err(invalid(format!(
+            "The archive has cache layout {} from gourgeist {}, but this gourgeist uses layout {CACHE_LAYOUT}",
+            metadata.layout, metadata.version
+        )));
+    }
+
+    // Unpack into a staging directory first and only move the entries into the buckets once the
+    // whole archive is valid
+    let cache_dir = ctx.cache_dir()?;
+    fs::create_dir_all(cache_dir)?;
+    let staging = tempfile::Builder::new()
+        .prefix(".import-")
+        .tempdir_in(cache_dir)?;
+    let mut staged = Vec::new();
+    for entry in entries {
+        let mut entry = entry?;
+        let path = entry.path()?.to_path_buf();
+        let (bucket, file_name) = path
+            .to_str()
+            .and_then(|path| path.split_once('/'))
+            .and_then(|(dir_name, file_name)| {
+                let bucket = ARCHIVED_BUCKETS
+                    .into_iter()
+                    .find(|bucket| bucket.dir_name() == dir_name)?;
+                let valid = !file_name.is_empty()
+                    && !file_name.starts_with('.')
+                    && !file_name.contains(['/', '\\']);
+                valid.then(|| (bucket, file_name.to_string()))
+            })
+            .ok_or_else(|| {
+                invalid(format!(
+                    "Unexpected entry {}, expected only cache entries",
+                    path.display()
+                ))
+            })?;
+        let staged_file = staging.path().join(staged.len().to_string());
+        io::copy(&mut entry, &mut fs::File::create(&staged_file)?)?;
+        staged.push((bucket, file_name, staged_file));
+    }
+    for (bucket, file_name, staged_file) in &staged {
+        fs::rename(staged_file, bucket.create(ctx)?.join(file_name))?;
+    }
+    Ok(staged.len())
+}
+
+/// Whether the archive is gzip compressed, judging by its extension
+fn is_compressed(archive: &Utf8Path) -> Result<bool, Error> {
+    let name = archive.file_name().unwrap_or_default();
+    if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        Ok(true)
+    } else if name.ends_with(".tar") {
+        Ok(false)
+    } else {
+        Err(Error::InvalidCacheArchive {
+            path: archive.to_path_buf(),
+            message: "Unsupported archive format, expected a .tar.gz, .tgz or .tar file"
+                .to_string(),
+        })
+    }
+}
+
+fn append_file(
+    builder: &mut tar::Builder<impl Write>,
+    path: &str,
+    contents: impl Read,
+    len: u64,
+) -> io::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(len);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder.append_data(&mut header, path, contents)
+}
+
+/// Human readable sizes with binary prefixes, e.g. `1.5 MiB`
+pub fn format_bytes(bytes: u64) -> String {
+    let mut size = bytes as f64;
+    for unit in ["B", "KiB", "MiB"] {
+        if size < 1024.0 {
+            return if unit == "B" {
+                format!("{bytes} B")
+            } else {
+                format!("{size:.1} {unit}")
+            };
+        }
+        size /= 1024.0;
+    }
+    format!("{size:.1} GiB")
+}
+
+/// Write a cache file through a temporary file in the same directory, so concurrent readers see
+/// either the old or the new contents, but never a partially written file.
+pub(crate) fn write_atomic(path: &Utf8Path, contents: &[u8]) -> io::Result<()> {
+    let parent = path.parent().unwrap_or(Utf8Path::new("."));
+    let mut tempfile = NamedTempFile::new_in(parent)?;
+    tempfile.write_all(contents)?;
+    tempfile.persist(path).map_err(|err| err.error)?;
+    Ok(())
+}
+
+fn dir_size(dir: &Utf8Path) -> io::Result<u64> {
+    let mut size = 0;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if metadata.is_dir() {
+            let path = Utf8PathBuf::try_from(entry.path()).map_err(|err| err.into_io_error())?;
+            size += dir_size(&path)?;
+        } else {
+            size += metadata.len();
+        }
+    }
+    Ok(size)
+}
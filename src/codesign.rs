@@ -0,0 +1,57 @@
+//! Keep copied interpreters runnable on macOS on Apple Silicon, where the kernel kills binaries
+//! without a valid code signature. Copies usually keep the ad-hoc signature of the base
+//! interpreter, but copying over an existing file or a signature that covers the original path
+//! invalidates it, so we check and re-sign the copies.
+
+use camino::Utf8Path;
+use std::io;
+
+/// Re-sign the copied binary at `path` with an ad-hoc signature if its signature doesn't verify.
+///
+/// Only Apple Silicon enforces signatures, which we check at runtime rather than by our own
+/// architecture: an `x86_64` gourgeist under Rosetta thins universal2 copies to `arm64`. Does
+/// nothing on other platforms, and only warns if `codesign`, which ships with macOS, is missing
+/// from `PATH`, since the binary may still work.
+pub(crate) fn fix_signature(path: &Utf8Path) -> io::Result<()> {
+    #[cfg(target_os = "macos")]
+    {
+        use std::process::{Command, Stdio};
+        use tracing::{debug, warn};
+
+        if crate::universal2::native_machine() != "arm64" {
+            return Ok(());
+        }
+        let verify = Command::new("codesign")
+            .args(["--verify", "--strict"])
+            .arg(path)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+        match verify {
+            Ok(status) if status.success() => return Ok(()),
+            Ok(_) => {}
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                warn!("Can't check the code signature of {path}, `codesign` is not on PATH");
+                return Ok(());
+            }
+            Err(err) => return Err(err),
+        }
+        debug!("Re-signing {path}, its code signature is invalid");
+        let output = Command::new("codesign")
+            .args(["--force", "--sign", "-"])
+            .arg(path)
+            .output()?;
+        if !output.status.success() {
+            return Err(io::Error::other(format!(
+                "Failed to re-sign {path}, macOS will refuse to run it: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        Ok(())
+    }
+    #[cfg(not(target_os = "macos"))]
+    {
+        let _ = path;
+        Ok(())
+    }
+}
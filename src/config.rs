@@ -0,0 +1,200 @@
+//! Defaults for the command line options from `config.toml` files
+//!
+//! The user config is `gourgeist/config.toml` in the platform config directory (e.g.
+//! `~/.config/gourgeist/config.toml` on linux), the project config is the `gourgeist.toml` in the
+//! current directory or the closest parent that has one. Project settings override user settings,
+//! command line options override both.
+
+use crate::link::LinkMode;
+use crate::{Context, Error};
+use camino::{Utf8Path, Utf8PathBuf};
+use clap::ValueEnum;
+use fs_err as fs;
+use serde::{Deserialize, Serialize};
+use std::io;
+use tracing::debug;
+
+/// The project config file name
+pub const PROJECT_CONFIG_FILE: &str = "gourgeist.toml";
+
+/// The keys of a config file, all optional
+pub const CONFIG_KEYS: &[&str] = &[
+    "python",
+    "link-mode",
+    "prompt",
+    "index-url",
+    "python-downloads",
+];
+
+/// The settings of a config file, see [`CONFIG_KEYS`]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Config {
+    /// The default `--python`, e.g. `3.11` or a path
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub python: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub link_mode: Option<LinkMode>,
+    /// The default `--prompt`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prompt: Option<String>,
+    /// The base URL of the pypi JSON API, for mirrors, see [`Context::index_url`]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub index_url: Option<String>,
+    /// `false` is like `--no-python-downloads`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub python_downloads: Option<bool>,
+}
+
+impl Config {
+    /// Read and validate a config file, an empty config if it doesn't exist.
+    ///
+    /// All unknown keys are reported at once, so a typo doesn't silently fall back to the default.
+    pub fn read(path: &Utf8Path) -> Result<Self, Error> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(err.into()),
+        };
+        let invalid = |message: String| Error::InvalidConfig {
+            path: path.to_path_buf(),
+            message,
+        };
+        let table: toml::Table =
+            toml::from_str(&contents).map_err(|err| invalid(err.to_string()))?;
+        let unknown: Vec<_> = table
+            .keys()
+            .filter(|key| !CONFIG_KEYS.contains(&key.as_str()))
+            .map(|key| format!("`{key}`"))
+            .collect();
+        if !unknown.is_empty() {
+            return Err(invalid(format!(
+                "Unknown keys {}, expected one of {}",
+                unknown.join(", "),
+                CONFIG_KEYS.join(", ")
+            )));
+        }
+        toml::from_str(&contents).map_err(|err| invalid(err.to_string()))
+    }
+
+    /// The user config merged with the project config.
+    pub fn load(ctx: &Context) -> Result<Self, Error> {
+        // Without a home directory, there is no user config
+        let user = match &ctx.config_dir {
+            Some(_) => Self::read(&user_config_path(ctx)?)?,
+            None => Self::default(),
+        };
+        let project = match project_config_path(ctx) {
+            Some(path) => {
+                debug!("Using the project config {path}");
+                Self::read(&path)?
+            }
+            None => Self::default(),
+        };
+        Ok(project.or(user))
+    }
+
+    /// The keys that are set with their values, in the order of [`CONFIG_KEYS`].
+    pub fn entries(&self) -> Vec<(&'static str, String)> {
+        let entries = [
+            ("python", self.python.clone()),
+            ("link-mode", self.link_mode.map(|mode| mode.to_string())),
+            ("prompt", self.prompt.clone()),
+            ("index-url", self.index_url.clone()),
+            (
+                "python-downloads",
+                self.python_downloads.map(|enabled| enabled.to_string()),
+            ),
+        ];
+        entries
+            .into_iter()
+            .filter_map(|(key, value)| Some((key, value?)))
+            .collect()
+    }
+
+    /// The value of a key, `None` if it's not set.
+    pub fn get(&self, key: &str) -> Result<Option<String>, String> {
+        if !CONFIG_KEYS.contains(&key) {
+            return Err(unknown_key(key));
+        }
+        Ok(self
+            .entries()
+            .into_iter()
+            .find(|(existing, _)| *existing == key)
+            .map(|(_, value)| value))
+    }
+
+    /// Parse `value` for the key and set it.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "python" => self.python = Some(value.to_string()),
+            "link-mode" => self.link_mode = Some(LinkMode::from_str(value, false)?),
+            "prompt" => self.prompt = Some(value.to_string()),
+            "index-url" => self.index_url = Some(value.to_string()),
+            "python-downloads" => {
+                self.python_downloads = Some(value.parse().map_err(|_| {
+                    format!("`python-downloads` must be `true` or `false`, got `{value}`")
+                })?);
+            }
+            _ => return Err(unknown_key(key)),
+        }
+        Ok(())
+    }
+
+    /// Remove a key, doing nothing if it's not set.
+    pub fn unset(&mut self, key: &str) -> Result<(), String> {
+        match key {
+            "python" => self.python = None,
+            "link-mode" => self.link_mode = None,
+            "prompt" => self.prompt = None,
+            "index-url" => self.index_url = None,
+            "python-downloads" => self.python_downloads = None,
+            _ => return Err(unknown_key(key)),
+        }
+        Ok(())
+    }
+
+    /// Write the config, replacing the file. Comments in an existing file are not preserved.
+    pub fn write(&self, path: &Utf8Path) -> Result<(), Error> {
+        let contents = toml::to_string(self).map_err(|err| Error::InvalidConfig {
+            path: path.to_path_buf(),
+            message: err.to_string(),
+        })?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, contents)?;
+        Ok(())
+    }
+
+    /// The settings of `self`, with the unset ones taken from `other`.
+    pub fn or(self, other: Self) -> Self {
+        Self {
+            python: self.python.or(other.python),
+            link_mode: self.link_mode.or(other.link_mode),
+            prompt: self.prompt.or(other.prompt),
+            index_url: self.index_url.or(other.index_url),
+            python_downloads: self.python_downloads.or(other.python_downloads),
+        }
+    }
+}
+
+fn unknown_key(key: &str) -> String {
+    format!(
+        "Unknown key `{key}`, expected one of {}",
+        CONFIG_KEYS.join(", ")
+    )
+}
+
+/// `gourgeist/config.toml` in the platform config directory
+pub fn user_config_path(ctx: &Context) -> io::Result<Utf8PathBuf> {
+    Ok(ctx.config_dir()?.join("config.toml"))
+}
+
+/// The [`PROJECT_CONFIG_FILE`] in the current directory or the closest parent that has one.
+pub fn project_config_path(ctx: &Context) -> Option<Utf8PathBuf> {
+    ctx.cwd
+        .ancestors()
+        .map(|dir| dir.join(PROJECT_CONFIG_FILE))
+        .find(|path| path.is_file())
+}
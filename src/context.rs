@@ -0,0 +1,160 @@
+//! The process state gourgeist depends on, passed explicitly instead of read from the process
+
+use crate::interpreter::InterpreterCacheMode;
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io;
+use std::time::Duration;
+
+/// The pypi JSON API
+pub const DEFAULT_INDEX_URL: &str = "https://pypi.org/pypi";
+
+/// The working directory, environment variables and directories that gourgeist uses.
+///
+/// All functions that resolve relative paths, run the interpreter or use the cache take a context
+/// rather than reading the process-global state, so multiple creations with different
+/// configurations can run in one process, e.g. in a test harness. Functions without a context only
+/// touch the paths they are given.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Context {
+    /// Relative paths are resolved against this directory, which is also the working directory of
+    /// the interpreter
+    pub cwd: Utf8PathBuf,
+    /// The environment for interpreter subprocesses and `PATH` lookups
+    pub env: BTreeMap<String, String>,
+    /// e.g. the interpreter info and the wheels, `None` if there is no platform cache directory
+    /// (e.g. without `HOME`) and none was configured
+    pub cache_dir: Option<Utf8PathBuf>,
+    /// e.g. the shell hook allowlist, `None` like `cache_dir`
+    pub data_dir: Option<Utf8PathBuf>,
+    /// virtualenv's app data with the unpacked seed wheels, `VIRTUALENV_OVERRIDE_APP_DATA` if set
+    pub virtualenv_data_dir: Option<Utf8PathBuf>,
+    /// The user `config.toml`, `None` like `cache_dir`
+    pub config_dir: Option<Utf8PathBuf>,
+    /// Query the interpreters and download the wheels without reading or writing the cache
+    pub no_cache: bool,
+    /// How cached interpreter info is invalidated
+    pub interpreter_cache: InterpreterCacheMode,
+    /// How long to wait for an interpreter to report its info before giving up
+    pub query_timeout: Duration,
+    /// Download a standalone build if the requested python version isn't installed, with the
+    /// `managed-python` feature
+    pub python_downloads: bool,
+    /// The base URL of the pypi JSON API, for upgrading the seed packages from a mirror
+    pub index_url: String,
+    /// Fsync the venv and the cache entries before returning, see the `durable` module
+    pub durable: bool,
+}
+
+impl Context {
+    /// Capture the current directory, the environment and the platform default directories of
+    /// this process.
+    ///
+    /// A platform directory that can't be detected is left unset rather than failing here, since
+    /// it may be overridden with [`Context::with_cache_dir`] or [`Context::with_data_dir`]. Only
+    /// using it fails.
+    pub fn from_process() -> io::Result<Self> {
+        let cwd =
+            Utf8PathBuf::try_from(std::env::current_dir()?).map_err(|err| err.into_io_error())?;
+        // Like `pwd -L`, the shell's logical path if it's the same directory, so relative venv
+        // paths with `--no-resolve-symlinks` keep the symlinks the user went through
+        let cwd = match std::env::var("PWD") {
+            Ok(pwd)
+                if Utf8Path::new(&pwd).is_absolute()
+                    && std::fs::canonicalize(&pwd).is_ok_and(|pwd| pwd == cwd) =>
+            {
+                Utf8PathBuf::from(pwd)
+            }
+            _ => cwd,
+        };
+        // Non-utf8 variables are dropped, neither we nor the interpreter query need them
+        let env: BTreeMap<String, String> = std::env::vars_os()
+            .filter_map(|(key, value)| Some((key.into_string().ok()?, value.into_string().ok()?)))
+            .collect();
+        let platform_dir = |dir: Option<std::path::PathBuf>| {
+            dir.and_then(|path| Utf8PathBuf::from_path_buf(path).ok())
+                .map(|path| path.join(env!("CARGO_PKG_NAME")))
+        };
+        let cache_dir = platform_dir(dirs::cache_dir());
+        let data_dir = platform_dir(dirs::data_dir());
+        // virtualenv's app data lives next to ours, unless overridden
+        let virtualenv_data_dir = match env.get("VIRTUALENV_OVERRIDE_APP_DATA") {
+            Some(app_data) => Some(Utf8PathBuf::from(app_data)),
+            None => data_dir
+                .as_ref()
+                .map(|data_dir| data_dir.parent().unwrap_or(data_dir).join("virtualenv")),
+        };
+        let config_dir = platform_dir(dirs::config_dir());
+        Ok(Self {
+            cwd,
+            env,
+            cache_dir,
+            data_dir,
+            virtualenv_data_dir,
+            config_dir,
+            no_cache: false,
+            interpreter_cache: InterpreterCacheMode::default(),
+            query_timeout: Duration::from_secs(30),
+            python_downloads: true,
+            index_url: DEFAULT_INDEX_URL.to_string(),
+            durable: false,
+        })
+    }
+
+    /// Use `cache_dir` instead of the platform cache directory.
+    pub fn with_cache_dir(mut self, cache_dir: Utf8PathBuf) -> Self {
+        self.cache_dir = Some(cache_dir);
+        self
+    }
+
+    /// Use `data_dir` instead of the platform data directory, for sandboxes where the home
+    /// directory is read-only. Unless `VIRTUALENV_OVERRIDE_APP_DATA` is set, virtualenv's app data
+    /// moves along into `data_dir/virtualenv`, so nothing is read from or written to the home
+    /// directory anymore.
+    pub fn with_data_dir(mut self, data_dir: Utf8PathBuf) -> Self {
+        if self.var("VIRTUALENV_OVERRIDE_APP_DATA").is_none() {
+            self.virtualenv_data_dir = Some(data_dir.join("virtualenv"));
+        }
+        self.data_dir = Some(data_dir);
+        self
+    }
+
+    /// The cache directory, or an error if it couldn't be detected
+    pub fn cache_dir(&self) -> io::Result<&Utf8Path> {
+        configured_dir(&self.cache_dir, "cache dir, set `--cache-dir`")
+    }
+
+    /// The data directory, or an error if it couldn't be detected
+    pub fn data_dir(&self) -> io::Result<&Utf8Path> {
+        configured_dir(&self.data_dir, "data dir, set `--data-dir`")
+    }
+
+    /// virtualenv's app data, or an error if it couldn't be detected
+    pub fn virtualenv_data_dir(&self) -> io::Result<&Utf8Path> {
+        configured_dir(
+            &self.virtualenv_data_dir,
+            "virtualenv app data dir, set `--data-dir` or `VIRTUALENV_OVERRIDE_APP_DATA`",
+        )
+    }
+
+    /// The config directory, or an error if it couldn't be detected
+    pub fn config_dir(&self) -> io::Result<&Utf8Path> {
+        configured_dir(&self.config_dir, "config dir")
+    }
+
+    /// Resolve `path` against [`Context::cwd`], without requiring it to exist.
+    pub fn absolute(&self, path: &Utf8Path) -> Utf8PathBuf {
+        self.cwd.join(path)
+    }
+
+    /// An environment variable of the context
+    pub fn var(&self, key: &str) -> Option<&str> {
+        self.env.get(key).map(String::as_str)
+    }
+}
+
+fn configured_dir<'a>(dir: &'a Option<Utf8PathBuf>, what: &str) -> io::Result<&'a Utf8Path> {
+    dir.as_deref()
+        .ok_or_else(|| io::Error::other(format!("Couldn't detect the {what}")))
+}
@@ -0,0 +1,309 @@
+//! A long-lived process that keeps interpreter info warm and creates venvs on request, so repeated
+//! creations skip the process startup and the interpreter query.
+//!
+//! The protocol is one JSON [`DaemonRequest`] line per connection, answered with one JSON line.
+//! Each request carries the protocol version and the gourgeist version of the client, and a daemon
+//! from another build answers [`DaemonResponse::Incompatible`] instead of creating the venv with
+//! its own code, so the client falls back to creating it in-process.
+
+use crate::{Context, Error, VenvOptions, VenvPaths};
+use camino::Utf8PathBuf;
+use serde::{Deserialize, Serialize};
+use std::io;
+
+/// Bumped when the framing of requests and responses changes
+const PROTOCOL_VERSION: u32 = 1;
+
+/// The versions a request is sent with, checked before the request itself is parsed since its
+/// fields differ between builds
+#[derive(Debug, Serialize, Deserialize)]
+struct Envelope<T> {
+    protocol: u32,
+    version: String,
+    request: T,
+}
+
+/// A request to create a venv
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DaemonRequest {
+    /// The context of the client, since the daemon has its own working directory and environment
+    pub context: Context,
+    pub location: Utf8PathBuf,
+    pub base_python: Utf8PathBuf,
+    /// See [`crate::override_interpreter_info`]
+    pub interpreter_overrides: Vec<(String, serde_json::Value)>,
+    pub options: VenvOptions,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+enum DaemonResponse {
+    Created(VenvPaths),
+    /// The error and its sources
+    Failed(Vec<String>),
+    /// The daemon is from another gourgeist build, with its version
+    Incompatible(String),
+}
+
+/// The socket the daemon listens on, in a directory only its owner can access
+pub fn socket_path(ctx: &Context) -> io::Result<Utf8PathBuf> {
+    Ok(ctx.cache_dir()?.join("daemon").join("daemon.sock"))
+}
+
+#[cfg(unix)]
+mod unix {
+    use super::{socket_path, DaemonRequest, DaemonResponse, Envelope, PROTOCOL_VERSION};
+    use crate::interpreter::CacheKey;
+    use crate::{
+        create_venv, get_interpreter_info, override_interpreter_info, Context, Error,
+        InterpreterInfo, VenvPaths,
+    };
+    use camino::Utf8PathBuf;
+    use fs_err as fs;
+    use std::collections::HashMap;
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::fs::PermissionsExt;
+    use std::os::unix::io::AsRawFd;
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::sync::{Arc, Mutex};
+    use std::{io, thread};
+    use tracing::{debug, info, warn};
+
+    /// Interpreter info by path, with the mtime of the interpreter when we queried it
+    type InterpreterCache = Mutex<HashMap<Utf8PathBuf, (CacheKey, InterpreterInfo)>>;
+
+    /// Listen on [`socket_path`] and serve requests until killed.
+    pub fn run_daemon(ctx: &Context) -> Result<(), Error> {
+        let socket = socket_path(ctx)?;
+        if socket.exists() {
+            if UnixStream::connect(&socket).is_ok() {
+                return Err(Error::DaemonRunning(socket));
+            }
+            debug!("Removing stale socket {socket}");
+            fs::remove_file(&socket)?;
+        }
+        // The daemon runs the interpreters the clients send, so only our own user may connect
+        if let Some(parent) = socket.parent() {
+            fs::create_dir_all(parent)?;
+            fs::set_permissions(parent, std::fs::Permissions::from_mode(0o700))?;
+        }
+        let listener = UnixListener::bind(&socket)?;
+        fs::set_permissions(&socket, std::fs::Permissions::from_mode(0o600))?;
+        info!("Listening on {socket}");
+        let interpreters: Arc<InterpreterCache> = Arc::default();
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(err) => {
+                    warn!("Failed to accept connection: {err}");
+                    continue;
+                }
+            };
+            match peer_uid(&stream) {
+                // SAFETY: `geteuid` has no preconditions and can't fail
+                Ok(uid) if uid == unsafe { libc::geteuid() } => {}
+                Ok(uid) => {
+                    warn!("Rejecting a connection from user {uid}");
+                    continue;
+                }
+                Err(err) => {
+                    warn!("Rejecting a connection with unknown credentials: {err}");
+                    continue;
+                }
+            }
+            let interpreters = interpreters.clone();
+            thread::spawn(move || {
+                if let Err(err) = handle_connection(&stream, &interpreters) {
+                    warn!("Failed to handle request: {err}");
+                }
+            });
+        }
+        Ok(())
+    }
+
+    /// The user id of the process on the other end of `stream`.
+    fn peer_uid(stream: &UnixStream) -> io::Result<libc::uid_t> {
+        let fd = stream.as_raw_fd();
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        {
+            let mut cred = libc::ucred {
+                pid: 0,
+                uid: 0,
+                gid: 0,
+            };
+            let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+            // SAFETY: `cred` and `len` are valid for writes and `len` is the size of `cred`
+            let result = unsafe {
+                libc::getsockopt(
+                    fd,
+                    libc::SOL_SOCKET,
+                    libc::SO_PEERCRED,
+                    std::ptr::addr_of_mut!(cred).cast(),
+                    &mut len,
+                )
+            };
+            if result != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(cred.uid)
+        }
+        #[cfg(not(any(target_os = "linux", target_os = "android")))]
+        {
+            let mut uid = 0;
+            let mut gid = 0;
+            // SAFETY: `uid` and `gid` are valid for writes
+            if unsafe { libc::getpeereid(fd, &mut uid, &mut gid) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(uid)
+        }
+    }
+
+    fn handle_connection(stream: &UnixStream, interpreters: &InterpreterCache) -> io::Result<()> {
+        let mut line = String::new();
+        BufReader::new(stream).read_line(&mut line)?;
+        // Only the versions are parsed before we know the request has our format
+        let envelope: Option<Envelope<serde_json::Value>> = serde_json::from_str(&line).ok();
+        let response = match envelope {
+            Some(envelope)
+                if envelope.protocol == PROTOCOL_VERSION
+                    && envelope.version == env!("CARGO_PKG_VERSION") =>
+            {
+                let request: DaemonRequest = serde_json::from_value(envelope.request)?;
+                debug!("Creating {}", request.location);
+                respond(&request, interpreters)
+            }
+            envelope => {
+                let version =
+                    envelope.map_or_else(|| "unknown".to_string(), |envelope| envelope.version);
+                debug!("Rejecting a request from gourgeist {version}");
+                DaemonResponse::Incompatible(env!("CARGO_PKG_VERSION").to_string())
+            }
+        };
+        let mut writer = stream;
+        serde_json::to_writer(&mut writer, &response)?;
+        writer.write_all(b"\n")?;
+        Ok(())
+    }
+
+    fn respond(request: &DaemonRequest, interpreters: &InterpreterCache) -> DaemonResponse {
+        match serve(request, interpreters) {
+            Ok(paths) => DaemonResponse::Created(paths),
+            Err(err) => {
+                let mut messages = Vec::new();
+                let mut source: Option<&(dyn std::error::Error + 'static)> = Some(&err);
+                while let Some(err) = source {
+                    messages.push(err.to_string());
+                    source = err.source();
+                }
+                DaemonResponse::Failed(messages)
+            }
+        }
+    }
+
+    fn serve(request: &DaemonRequest, interpreters: &InterpreterCache) -> Result<VenvPaths, Error> {
+        let ctx = &request.context;
+        let base_python = ctx.absolute(&request.base_python);
+        let key = CacheKey::new(&base_python, ctx.interpreter_cache)?;
+        let cached = interpreters
+            .lock()
+            .unwrap()
+            .get(&base_python)
+            .filter(|(cached_key, _)| *cached_key == key)
+            .map(|(_, info)| info.clone());
+        let info = match cached {
+            Some(info) => info,
+            None => {
+                let info = get_interpreter_info(ctx, &base_python)?;
+                interpreters
+                    .lock()
+                    .unwrap()
+                    .insert(base_python.clone(), (key, info.clone()));
+                info
+            }
+        };
+        let info = override_interpreter_info(info, &request.interpreter_overrides)?;
+        create_venv(
+            ctx,
+            &request.location,
+            &request.base_python,
+            &info,
+            &request.options,
+        )
+    }
+
+    pub fn create_via_daemon(request: &DaemonRequest) -> Option<Result<VenvPaths, Error>> {
+        // Without a cache dir, there can't be a daemon
+        let socket = socket_path(&request.context).ok()?;
+        let stream = match UnixStream::connect(&socket) {
+            Ok(stream) => stream,
+            Err(err) => {
+                debug!("Not using the daemon at {socket}: {err}");
+                return None;
+            }
+        };
+        debug!("Using the daemon at {socket}");
+        send_request(&stream, request).transpose()
+    }
+
+    /// Send the request and wait for the venv, `None` if the daemon is from another build.
+    fn send_request(
+        stream: &UnixStream,
+        request: &DaemonRequest,
+    ) -> Result<Option<VenvPaths>, Error> {
+        let envelope = Envelope {
+            protocol: PROTOCOL_VERSION,
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            request,
+        };
+        let mut writer = stream;
+        serde_json::to_writer(&mut writer, &envelope).map_err(io::Error::from)?;
+        writer.write_all(b"\n")?;
+        let mut line = String::new();
+        BufReader::new(stream).read_line(&mut line)?;
+        // Daemons from before the version check close the connection without an answer when they
+        // can't parse the request, and later ones may answer in a format we don't know
+        let Ok(response) = serde_json::from_str(&line) else {
+            warn!("The daemon doesn't speak our protocol, restart it with this gourgeist build");
+            return Ok(None);
+        };
+        match response {
+            DaemonResponse::Created(paths) => Ok(Some(paths)),
+            DaemonResponse::Failed(messages) => Err(Error::Daemon(messages.join(": "))),
+            DaemonResponse::Incompatible(version) => {
+                warn!(
+                    "The daemon is from gourgeist {version}, restart it with this build to use it"
+                );
+                Ok(None)
+            }
+        }
+    }
+}
+
+/// Listen on [`socket_path`] and serve creation requests until killed.
+pub fn run_daemon(ctx: &Context) -> Result<(), Error> {
+    #[cfg(unix)]
+    {
+        unix::run_daemon(ctx)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = ctx;
+        Err(Error::Unsupported("The daemon"))
+    }
+}
+
+/// Create a venv through the daemon listening in the cache dir of the request context.
+///
+/// Returns `None` if no daemon is running or it's from another gourgeist build, so the caller can
+/// create the venv in-process instead.
+pub fn create_via_daemon(request: &DaemonRequest) -> Option<Result<VenvPaths, Error>> {
+    #[cfg(unix)]
+    {
+        unix::create_via_daemon(request)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = request;
+        None
+    }
+}
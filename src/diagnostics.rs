@@ -0,0 +1,82 @@
+//! Remediation hints for common failures
+
+use crate::discovery::find_pythons;
+use crate::{Context, Error};
+use std::io;
+
+/// Suggestions on how to fix `err`, to be shown after the error chain.
+///
+/// Hints come from every error in the chain, e.g. a permission error is recognized even when
+/// it's wrapped as the source of another error. The list is empty if there's nothing useful to
+/// say.
+pub fn hints(ctx: &Context, err: &Error) -> Vec<String> {
+    let mut hints = Vec::new();
+    if let Error::Batch(batch) = err {
+        for (_, err) in &batch.failures {
+            for hint in self::hints(ctx, err) {
+                if !hints.contains(&hint) {
+                    hints.push(hint);
+                }
+            }
+        }
+        return hints;
+    }
+
+    match err {
+        Error::InvalidPythonInterpreter(_) => hints.push(interpreter_hint(ctx)),
+        Error::PythonSubcommand { err, .. } if err.kind() == io::ErrorKind::NotFound => {
+            hints.push(interpreter_hint(ctx));
+        }
+        #[cfg(not(feature = "install"))]
+        Error::MissingSeedPackage { .. } => hints.push(
+            "The seed packages are copied from virtualenv's app data, run `virtualenv` once to \
+             populate it, pass `--bare` to skip the seed packages or use a gourgeist built with \
+             the `install` feature to download them"
+                .to_string(),
+        ),
+        #[cfg(any(feature = "install", feature = "managed-python"))]
+        Error::MinReq(_) | Error::HttpStatus { .. } => hints.push(
+            "Check your network connection and proxy settings (`HTTPS_PROXY`), or pass `--bare` \
+             to skip downloading the seed packages"
+                .to_string(),
+        ),
+        Error::Frozen(location) => {
+            hints.push(format!("Unfreeze it with `gourgeist unfreeze {location}`"));
+        }
+        _ => {}
+    }
+
+    let mut source: Option<&(dyn std::error::Error + 'static)> = Some(err);
+    while let Some(current) = source {
+        if let Some(err) = current.downcast_ref::<io::Error>() {
+            if err.kind() == io::ErrorKind::PermissionDenied {
+                hints.push(
+                    "Check the permissions of the location or choose a location you can write \
+                     to, e.g. in your home directory or the project directory"
+                        .to_string(),
+                );
+                break;
+            }
+        }
+        source = current.source();
+    }
+    hints
+}
+
+/// List the interpreters we could have used instead
+fn interpreter_hint(ctx: &Context) -> String {
+    let pythons = find_pythons(ctx).unwrap_or_default();
+    if pythons.is_empty() {
+        return "No python interpreters were found, install python or pass the path to one with \
+                `--python`"
+            .to_string();
+    }
+    let mut hint = "Pass one of the discovered interpreters with `--python`:".to_string();
+    for python in pythons {
+        hint.push_str(&format!(
+            "\n  {} ({} {}, {})",
+            python.path, python.implementation, python.python_version, python.source
+        ));
+    }
+    hint
+}
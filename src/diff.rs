@@ -0,0 +1,165 @@
+//! Show what upgrading an existing venv would change, without changing it
+
+use crate::bare::{
+    base_executable, interpreter_link_mode, interpreter_names, prepare_upgrade, read_pyvenv_cfg,
+    render_activators, upgrade_pyvenv_cfg, VenvPaths,
+};
+use crate::interpreter::InterpreterInfo;
+use crate::link::LinkMode;
+use crate::paths::venv_root;
+use crate::{Context, Error, VenvOptions};
+use camino::{Utf8Path, Utf8PathBuf};
+use fs_err as fs;
+use serde::Serialize;
+use similar::TextDiff;
+use std::io;
+
+/// An interpreter link in `bin` that would be retargeted, added or replaced by a copy
+#[derive(Debug, Clone, Serialize)]
+pub struct LinkChange {
+    pub path: Utf8PathBuf,
+    /// The symlink target, `file` for copies and hardlinks, `None` if missing
+    pub old: Option<String>,
+    pub new: String,
+}
+
+/// A file that would be rewritten, with a unified diff of the change
+#[derive(Debug, Clone, Serialize)]
+pub struct FileChange {
+    /// Relative to the venv
+    pub path: Utf8PathBuf,
+    pub diff: String,
+}
+
+/// A seed package that would be upgraded
+#[derive(Debug, Clone, Serialize)]
+pub struct SeedChange {
+    pub name: String,
+    /// `None` if the package is missing
+    pub old_version: Option<String>,
+    pub new_version: String,
+}
+
+/// The changes of [`diff_upgrade`]
+#[derive(Debug, Clone, Serialize)]
+pub struct VenvDiff {
+    pub location: Utf8PathBuf,
+    pub links: Vec<LinkChange>,
+    /// `pyvenv.cfg` and the activators
+    pub files: Vec<FileChange>,
+    pub seeds: Vec<SeedChange>,
+}
+
+impl VenvDiff {
+    pub fn is_empty(&self) -> bool {
+        self.links.is_empty() && self.files.is_empty() && self.seeds.is_empty()
+    }
+}
+
+/// Compute what [`upgrade_venv`](crate::upgrade_venv) with the same arguments would change in the
+/// venv at `location`, and with `deps` also what [`upgrade_deps`](crate::upgrade_deps) would
+/// change, which queries the index.
+///
+pub fn diff_upgrade(
+    ctx: &Context,
+    location: &Utf8Path,
+    base_python: &Utf8Path,
+    info: &InterpreterInfo,
+    options: &VenvOptions,
+    deps: bool,
+) -> Result<VenvDiff, Error> {
+    let location = ctx.absolute(location);
+    let base_python = base_executable(&ctx.absolute(base_python), info);
+    let old_cfg = read_pyvenv_cfg(&location)?;
+    let (_old_version, options) = prepare_upgrade(&location, info, options)?;
+    let paths = VenvPaths::new(&venv_root(&location, options.no_resolve_symlinks)?, info);
+    let mut diff = VenvDiff {
+        location: paths.root.clone(),
+        links: Vec::new(),
+        files: Vec::new(),
+        seeds: Vec::new(),
+    };
+
+    let link_mode = interpreter_link_mode(&options, info, &paths.root);
+    // Copies and hardlinks only change if they are of another interpreter
+    let same_base = old_cfg.get("base-executable") == Some(base_python.as_str());
+    for (index, name) in interpreter_names(info).into_iter().enumerate() {
+        let path = paths.bin.join(name);
+        let old = match path.symlink_metadata() {
+            Ok(metadata) if metadata.is_symlink() => {
+                Some(fs::read_link(&path)?.display().to_string())
+            }
+            Ok(_) => Some("file".to_string()),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+            Err(err) => return Err(err.into()),
+        };
+        let new = if link_mode != LinkMode::Symlink {
+            if old.as_deref() == Some("file") && same_base {
+                continue;
+            }
+            format!("{link_mode} of {base_python}")
+        } else if index > 0 {
+            "python".to_string()
+        } else {
+            base_python.to_string()
+        };
+        if old.as_deref() != Some(new.as_str()) {
+            diff.links.push(LinkChange { path, old, new });
+        }
+    }
+
+    let new_cfg = upgrade_pyvenv_cfg(&old_cfg, &base_python, info, &options)?;
+    diff_file(
+        &mut diff.files,
+        &paths.root,
+        "pyvenv.cfg".into(),
+        &new_cfg.to_string(),
+    )?;
+    let bin = paths.bin.strip_prefix(&paths.root).unwrap_or(&paths.bin);
+    for (name, activator) in render_activators(&paths, info, &options)? {
+        diff_file(&mut diff.files, &paths.root, bin.join(name), &activator)?;
+    }
+
+    if deps {
+        #[cfg(feature = "install")]
+        {
+            diff.seeds =
+                crate::packages::outdated_seeds(ctx, &paths.site_packages, &info.python_version)?
+                    .into_iter()
+                    .map(|upgrade| SeedChange {
+                        name: upgrade.name,
+                        old_version: Some(upgrade.old_version)
+                            .filter(|version| !version.is_empty()),
+                        new_version: upgrade.new_version,
+                    })
+                    .collect();
+        }
+        #[cfg(not(feature = "install"))]
+        return Err(Error::RequiresFeature("Upgrading the seed packages"));
+    }
+    Ok(diff)
+}
+
+/// Add the unified diff between the file at `root/path` and `new` to `files`, if they differ.
+fn diff_file(
+    files: &mut Vec<FileChange>,
+    root: &Utf8Path,
+    path: Utf8PathBuf,
+    new: &str,
+) -> io::Result<()> {
+    let old = match fs::read_to_string(root.join(&path)) {
+        Ok(old) => old,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(err),
+    };
+    if old == new {
+        return Ok(());
+    }
+    let text_diff = TextDiff::from_lines(old.as_str(), new);
+    let diff = text_diff
+        .unified_diff()
+        .header(&format!("a/{path}"), &format!("b/{path}"))
+        .to_string();
+    files.push(FileChange { path, diff });
+    Ok(())
+}
@@ -0,0 +1,223 @@
+//! Find the python interpreters installed on this machine
+
+use crate::bare::is_interpreter_name;
+use crate::interpreter::get_interpreter_info;
+use crate::{Context, Error};
+use camino::{Utf8Path, Utf8PathBuf};
+use fs_err as fs;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fmt;
+use std::io;
+use tracing::debug;
+
+/// Where an interpreter was found
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PythonSource {
+    /// A `python*` executable in `PATH`
+    Path,
+    /// A version installed with pyenv (`$PYENV_ROOT/versions`)
+    Pyenv,
+    /// An installation registered in the windows registry, as listed by the `py` launcher
+    Registry,
+    /// A python-build-standalone download of gourgeist
+    Managed,
+}
+
+impl fmt::Display for PythonSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            PythonSource::Path => "path",
+            PythonSource::Pyenv => "pyenv",
+            PythonSource::Registry => "registry",
+            PythonSource::Managed => "managed",
+        })
+    }
+}
+
+/// An interpreter found by [`find_pythons`]
+#[derive(Debug, Clone, Serialize)]
+pub struct FoundPython {
+    pub path: Utf8PathBuf,
+    pub source: PythonSource,
+    pub python_version: String,
+    /// `sys.implementation.name`, e.g. `cpython` or `pypy`
+    pub implementation: String,
+    /// `platform.machine()`, e.g. `x86_64` or `arm64`
+    pub machine: String,
+}
+
+/// All interpreters in `PATH`, pyenv, the windows registry and the managed downloads.
+///
+/// Interpreters that are reachable under multiple names, e.g. `python3` and `python3.11`, are
+/// only listed once, with the first name found. Interpreters that fail to report their info are
+/// skipped.
+pub fn find_pythons(ctx: &Context) -> Result<Vec<FoundPython>, Error> {
+    let mut pythons = Vec::new();
+    for (path, source) in python_candidates(ctx)? {
+        let info = match get_interpreter_info(ctx, &path) {
+            Ok(info) => info,
+            Err(err) => {
+                debug!("Skipping {path}: {err}");
+                continue;
+            }
+        };
+        pythons.push(FoundPython {
+            path,
+            source,
+            python_version: info.python_version,
+            implementation: info.implementation,
+            machine: info.machine,
+        });
+    }
+    Ok(pythons)
+}
+
+/// The interpreters of [`find_pythons`] before querying them, each interpreter only once.
+pub(crate) fn python_candidates(ctx: &Context) -> Result<Vec<(Utf8PathBuf, PythonSource)>, Error> {
+    let mut candidates = Vec::new();
+    candidates.extend(
+        path_pythons(ctx)?
+            .into_iter()
+            .map(|path| (path, PythonSource::Path)),
+    );
+    candidates.extend(
+        pyenv_pythons(ctx)?
+            .into_iter()
+            .map(|path| (path, PythonSource::Pyenv)),
+    );
+    candidates.extend(
+        registry_pythons(ctx)
+            .into_iter()
+            .map(|path| (path, PythonSource::Registry)),
+    );
+    #[cfg(feature = "managed-python")]
+    candidates.extend(
+        crate::managed::managed_pythons(ctx)?
+            .into_iter()
+            .map(|path| (path, PythonSource::Managed)),
+    );
+
+    let mut seen = HashSet::new();
+    Ok(candidates
+        .into_iter()
+        .filter(|(path, _)| seen.insert(path.canonicalize_utf8().unwrap_or_else(|_| path.clone())))
+        .collect())
+}
+
+/// The entries of `dir` that are named like an interpreter, sorted by name.
+fn interpreters_in(dir: &Utf8Path) -> io::Result<Vec<Utf8PathBuf>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+    let mut interpreters = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let Some(name) = entry.file_name().to_str().map(str::to_string) else {
+            continue;
+        };
+        // `python3-config` and `python3.11m` don't pass, the latter is covered by `python3.11`
+        if is_interpreter_name(&name) && dir.join(&name).is_file() {
+            interpreters.push(dir.join(name));
+        }
+    }
+    interpreters.sort();
+    Ok(interpreters)
+}
+
+fn path_pythons(ctx: &Context) -> io::Result<Vec<Utf8PathBuf>> {
+    let Some(path) = ctx.var("PATH") else {
+        return Ok(Vec::new());
+    };
+    let mut pythons = Vec::new();
+    for dir in std::env::split_paths(path) {
+        let Ok(dir) = Utf8PathBuf::try_from(dir) else {
+            continue;
+        };
+        // Don't fail the whole listing on an unreadable `PATH` entry
+        match interpreters_in(&ctx.absolute(&dir)) {
+            Ok(interpreters) => pythons.extend(interpreters),
+            Err(err) => debug!("Skipping {dir} in PATH: {err}"),
+        }
+    }
+    Ok(pythons)
+}
+
+fn pyenv_pythons(ctx: &Context) -> io::Result<Vec<Utf8PathBuf>> {
+    let root = if let Some(root) = ctx.var("PYENV_ROOT") {
+        Utf8PathBuf::from(root)
+    } else if let Some(home) = ctx.var("HOME").or_else(|| ctx.var("USERPROFILE")) {
+        Utf8PathBuf::from(home).join(".pyenv")
+    } else {
+        return Ok(Vec::new());
+    };
+    let versions = root.join("versions");
+    if !versions.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut versions: Vec<_> = fs::read_dir(&versions)?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<io::Result<_>>()?;
+    versions.sort();
+    let mut pythons = Vec::new();
+    for version in versions {
+        let Ok(version) = Utf8PathBuf::try_from(version) else {
+            continue;
+        };
+        // pyenv-win puts the interpreter in the version dir itself
+        let bin = if cfg!(windows) {
+            version
+        } else {
+            version.join("bin")
+        };
+        // `python3` is the most specific name that all versions have, pypy has no `python3.X`
+        if let Some(python) = interpreters_in(&bin)?.into_iter().find(|python| {
+            python.file_stem() == Some("python3") || python.file_stem() == Some("python")
+        }) {
+            pythons.push(python);
+        }
+    }
+    Ok(pythons)
+}
+
+/// The installations registered according to PEP 514, as listed by `py --list-paths`.
+#[cfg(windows)]
+fn registry_pythons(ctx: &Context) -> Vec<Utf8PathBuf> {
+    let output = match std::process::Command::new("py")
+        .arg("--list-paths")
+        .env_clear()
+        .envs(&ctx.env)
+        .current_dir(&ctx.cwd)
+        .output()
+    {
+        Ok(output) if output.status.success() => output,
+        Ok(output) => {
+            debug!("`py --list-paths` failed with {}", output.status);
+            return Vec::new();
+        }
+        Err(err) => {
+            debug!("Can't run the python launcher: {err}");
+            return Vec::new();
+        }
+    };
+    // Lines look like ` -V:3.11 *        C:\Python311\python.exe`, where `*` marks the default
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let (tag, path) = line.trim().split_once(char::is_whitespace)?;
+            if !tag.starts_with('-') {
+                return None;
+            }
+            let path = path.trim().trim_start_matches('*').trim();
+            (!path.is_empty()).then(|| Utf8PathBuf::from(path))
+        })
+        .collect()
+}
+
+#[cfg(not(windows))]
+fn registry_pythons(_ctx: &Context) -> Vec<Utf8PathBuf> {
+    Vec::new()
+}
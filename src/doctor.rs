@@ -0,0 +1,271 @@
+//! Inspect the host for problems that would make creating venvs fail or slow
+
+use crate::cache::{cache_size, format_bytes, CacheBucket};
+use crate::discovery::python_candidates;
+use crate::interpreter::get_interpreter_info;
+use crate::link::LinkMode;
+use crate::{Context, Error};
+use camino::Utf8Path;
+use fs_err as fs;
+use serde::Serialize;
+use std::fmt;
+use std::io;
+use tempfile::TempDir;
+
+/// How bad a finding is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Severity {
+    Ok,
+    /// Creating venvs works, but slower or with fewer features
+    Warning,
+    /// Creating venvs fails
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Severity::Ok => "ok",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        })
+    }
+}
+
+/// The result of one check of [`doctor`]
+#[derive(Debug, Clone, Serialize)]
+pub struct Finding {
+    /// e.g. `cache` or `interpreter`
+    pub check: String,
+    pub severity: Severity,
+    pub message: String,
+    /// What to do about it, for warnings and errors
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hint: Option<String>,
+}
+
+impl Finding {
+    fn ok(check: &str, message: impl Into<String>) -> Self {
+        Self {
+            check: check.to_string(),
+            severity: Severity::Ok,
+            message: message.into(),
+            hint: None,
+        }
+    }
+
+    fn problem(
+        check: &str,
+        severity: Severity,
+        message: impl Into<String>,
+        hint: impl Into<String>,
+    ) -> Self {
+        Self {
+            check: check.to_string(),
+            severity,
+            message: message.into(),
+            hint: Some(hint.into()),
+        }
+    }
+}
+
+/// Check the cache directory, the discovered interpreters, which link modes work on the
+/// filesystem of `target` (where the venvs will be created) and whether the index is reachable.
+///
+/// Failing checks are reported as findings, only errors that prevent checking at all are
+/// returned as `Err`.
+pub fn doctor(ctx: &Context, target: &Utf8Path) -> Result<Vec<Finding>, Error> {
+    let target = ctx.absolute(target);
+    let target = target.canonicalize_utf8().unwrap_or(target);
+    let mut findings = Vec::new();
+    check_cache(ctx, &mut findings);
+    check_interpreters(ctx, &mut findings)?;
+    check_links(ctx, &target, &mut findings);
+    check_index(ctx, &mut findings);
+    Ok(findings)
+}
+
+fn check_cache(ctx: &Context, findings: &mut Vec<Finding>) {
+    let cache_dir = match ctx.cache_dir() {
+        Ok(cache_dir) => cache_dir,
+        Err(err) => {
+            findings.push(Finding::problem(
+                "cache",
+                Severity::Error,
+                err.to_string(),
+                "Pass `--cache-dir` with a writable directory",
+            ));
+            return;
+        }
+    };
+    let writable =
+        fs::create_dir_all(cache_dir).and_then(|()| tempfile::tempfile_in(cache_dir).map(|_| ()));
+    if let Err(err) = writable {
+        findings.push(Finding::problem(
+            "cache",
+            Severity::Error,
+            format!("The cache directory {cache_dir} isn't writable: {err}"),
+            "Pass `--cache-dir` with a writable directory",
+        ));
+        return;
+    }
+    let mut sizes = Vec::new();
+    for bucket in CacheBucket::ALL {
+        match cache_size(ctx, bucket) {
+            Ok(size) => sizes.push(format!("{bucket} {}", format_bytes(size))),
+            Err(err) => {
+                findings.push(Finding::problem(
+                    "cache",
+                    Severity::Warning,
+                    format!("Failed to read the {bucket} cache: {err}"),
+                    "Remove it with `gourgeist cache clean`",
+                ));
+                return;
+            }
+        }
+    }
+    findings.push(Finding::ok(
+        "cache",
+        format!("{cache_dir} is writable ({})", sizes.join(", ")),
+    ));
+}
+
+fn check_interpreters(ctx: &Context, findings: &mut Vec<Finding>) -> Result<(), Error> {
+    let candidates = python_candidates(ctx)?;
+    if candidates.is_empty() {
+        findings.push(Finding::problem(
+            "interpreter",
+            Severity::Error,
+            "No python interpreter found in PATH, pyenv or the windows registry",
+            "Install python or pass the path of an interpreter with `--python`",
+        ));
+    }
+    for (path, source) in candidates {
+        match get_interpreter_info(ctx, &path) {
+            Ok(info) => findings.push(Finding::ok(
+                "interpreter",
+                format!(
+                    "{path} ({source}) is {} {}",
+                    info.implementation, info.python_version
+                ),
+            )),
+            Err(err) => {
+                // The innermost error has the interpreter output, of which the first line
+                // usually says what's wrong
+                let mut cause = err.to_string();
+                let mut next = std::error::Error::source(&err);
+                while let Some(err) = next {
+                    cause = err.to_string();
+                    next = err.source();
+                }
+                let cause: Vec<&str> = cause
+                    .lines()
+                    .map(str::trim)
+                    .filter(|line| !line.is_empty() && !line.starts_with("---"))
+                    .take(2)
+                    .collect();
+                let message = format!("{path} ({source}) can't be queried: {}", cause.join(" "));
+                findings.push(Finding::problem(
+                    "interpreter",
+                    Severity::Warning,
+                    message,
+                    "Remove the broken interpreter or increase `--query-timeout` if it's slow",
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Which link modes work in `target` and from the cache to `target`
+fn check_links(ctx: &Context, target: &Utf8Path, findings: &mut Vec<Finding>) {
+    let result = (|| -> io::Result<Vec<(LinkMode, &str, io::Result<()>)>> {
+        let dir = TempDir::new_in(target)?;
+        let src = dir.path().join("src");
+        fs::write(&src, "gourgeist")?;
+        let mut results = Vec::new();
+        for mode in [LinkMode::Symlink, LinkMode::Hardlink, LinkMode::Reflink] {
+            let dst = dir.path().join(mode.as_str());
+            results.push((mode, "in", mode.try_link(&src, &dst)));
+        }
+        // The seed packages are linked from the cache
+        let cache_dir = ctx.cache_dir()?;
+        fs::create_dir_all(cache_dir)?;
+        let cache_file = tempfile::NamedTempFile::new_in(cache_dir)?;
+        let dst = dir.path().join("from-cache");
+        results.push((
+            LinkMode::Hardlink,
+            "from the cache to",
+            LinkMode::Hardlink.try_link(cache_file.path(), &dst),
+        ));
+        Ok(results)
+    })();
+    let results = match result {
+        Ok(results) => results,
+        Err(err) => {
+            findings.push(Finding::problem(
+                "links",
+                Severity::Error,
+                format!("Failed to write a test file in {target}: {err}"),
+                "Check that the directory exists and is writable",
+            ));
+            return;
+        }
+    };
+    for (mode, location, result) in results {
+        findings.push(match (mode, result) {
+            (_, Ok(())) => Finding::ok("links", format!("{mode} works {location} {target}")),
+            (LinkMode::Symlink, Err(err)) => Finding::problem(
+                "links",
+                Severity::Warning,
+                format!("{mode} fails {location} {target}: {err}"),
+                "`bin/python` will be copied, pass `--link-mode copy` to silence the fallback",
+            ),
+            (LinkMode::Hardlink, Err(err)) if location != "in" => Finding::problem(
+                "links",
+                Severity::Warning,
+                format!("{mode} fails {location} {target}: {err}"),
+                "The cache is on another filesystem, so the seed packages are copied. Put the \
+                 cache on the same filesystem with `--cache-dir` to share them",
+            ),
+            (_, Err(err)) => Finding::problem(
+                "links",
+                Severity::Warning,
+                format!("{mode} fails {location} {target}: {err}"),
+                "The seed packages fall back to a cheaper link mode or copies",
+            ),
+        });
+    }
+}
+
+#[cfg(feature = "install")]
+fn check_index(ctx: &Context, findings: &mut Vec<Finding>) {
+    let url = format!("{}/pip/json", ctx.index_url.trim_end_matches('/'));
+    match minreq::get(&url).with_timeout(10).send() {
+        Ok(response) if response.status_code == 200 => {
+            findings.push(Finding::ok("index", format!("{url} is reachable")));
+        }
+        Ok(response) => findings.push(Finding::problem(
+            "index",
+            Severity::Warning,
+            format!("{url} returned status {}", response.status_code),
+            "Check `--index-url`, upgrading the seed packages needs a pypi JSON API",
+        )),
+        Err(err) => findings.push(Finding::problem(
+            "index",
+            Severity::Warning,
+            format!("{url} is unreachable: {err}"),
+            "Check your network connection and proxy settings (`HTTPS_PROXY`), or prefetch the \
+             seed packages with `gourgeist fetch-seeds`",
+        )),
+    }
+}
+
+#[cfg(not(feature = "install"))]
+fn check_index(_ctx: &Context, findings: &mut Vec<Finding>) {
+    findings.push(Finding::ok(
+        "index",
+        "Skipped, the seed packages come from virtualenv's app data without the `install` feature",
+    ));
+}
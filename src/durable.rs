@@ -0,0 +1,51 @@
+//! Flush written files to disk with [`Context::durable`], so a power loss or a network filesystem
+//! with write-back caching doesn't leave truncated files behind after we reported success
+
+use crate::Context;
+use camino::Utf8Path;
+use fs_err as fs;
+use std::io;
+
+/// Fsync all files and directories in `root`, children before their directory. Symlinks are
+/// stored in their directory, so syncing the directory covers them.
+pub(crate) fn sync_tree(root: &Utf8Path) -> io::Result<()> {
+    sync_tree_inner(root.as_std_path())
+}
+
+fn sync_tree_inner(dir: &std::path::Path) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            sync_tree_inner(&entry.path())?;
+        } else if file_type.is_file() {
+            fs::File::open(entry.path())?.sync_all()?;
+        }
+    }
+    sync_dir(dir)
+}
+
+/// Fsync a directory, which persists the creation, renaming and removal of its entries.
+///
+/// Windows can't open directories as files and persists renames without it.
+pub(crate) fn sync_dir(dir: impl AsRef<std::path::Path>) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        fs::File::open(dir.as_ref())?.sync_all()
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = dir;
+        Ok(())
+    }
+}
+
+/// Fsync a file that was just written and the directory with its entry, if
+/// [`Context::durable`] is set.
+pub(crate) fn sync_file(ctx: &Context, path: &Utf8Path) -> io::Result<()> {
+    if !ctx.durable {
+        return Ok(());
+    }
+    fs::File::open(path)?.sync_all()?;
+    sync_dir(path.parent().unwrap_or(Utf8Path::new(".")))
+}
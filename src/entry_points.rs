@@ -0,0 +1,94 @@
+//! Parser for the `entry_points.txt` of a `.dist-info` directory
+//!
+//! <https://packaging.python.org/en/latest/specifications/entry-points/#file-format>
+
+use std::collections::BTreeMap;
+
+/// An entry point, e.g. `pip = pip._internal.cli.main:main` or `name = pkg.mod:obj.attr [extra]`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct EntryPoint {
+    pub(crate) name: String,
+    /// The module to import, e.g. `pip._internal.cli.main`
+    pub(crate) module: String,
+    /// The (possibly dotted) object in the module, e.g. `main` or `obj.attr`, if any
+    pub(crate) object: Option<String>,
+    /// Extras required by the entry point, deprecated but still found in real-world files
+    pub(crate) extras: Vec<String>,
+}
+
+/// Parse the entry points by group, e.g. `console_scripts`.
+///
+/// Unlike a generic ini parser, names are case-sensitive, only `=` is a delimiter and `#` and `;`
+/// start comment lines, matching what `importlib.metadata` accepts.
+pub(crate) fn parse_entry_points(text: &str) -> Result<BTreeMap<String, Vec<EntryPoint>>, String> {
+    let mut groups: BTreeMap<String, Vec<EntryPoint>> = BTreeMap::new();
+    let mut group = None;
+    for (line_number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if let Some(name) = line
+            .strip_prefix('[')
+            .and_then(|line| line.strip_suffix(']'))
+        {
+            group = Some(name.trim().to_string());
+            groups.entry(name.trim().to_string()).or_default();
+            continue;
+        }
+        let Some(group) = &group else {
+            return Err(format!(
+                "Line {} is outside of a section: {line:?}",
+                line_number + 1
+            ));
+        };
+        let (name, value) = line
+            .split_once('=')
+            .ok_or_else(|| format!("Line {} is not `name = value`: {line:?}", line_number + 1))?;
+        let entry_point = parse_value(name.trim(), value.trim())
+            .map_err(|err| format!("Line {}: {err}", line_number + 1))?;
+        groups.entry(group.clone()).or_default().push(entry_point);
+    }
+    Ok(groups)
+}
+
+/// Parse `module:object [extra1, extra2]`
+fn parse_value(name: &str, value: &str) -> Result<EntryPoint, String> {
+    if name.is_empty() {
+        return Err(format!("Missing name for {value:?}"));
+    }
+    let (reference, extras) = match value
+        .strip_suffix(']')
+        .and_then(|value| value.rsplit_once('['))
+    {
+        Some((reference, extras)) => (
+            reference.trim(),
+            extras
+                .split(',')
+                .map(str::trim)
+                .filter(|extra| !extra.is_empty())
+                .map(ToString::to_string)
+                .collect(),
+        ),
+        None => (value, Vec::new()),
+    };
+    let (module, object) = match reference.split_once(':') {
+        Some((module, object)) => (module.trim(), Some(object.trim())),
+        None => (reference, None),
+    };
+    let is_dotted_name = |name: &str| {
+        !name.is_empty()
+            && name.split('.').all(|part| {
+                !part.is_empty() && part.chars().all(|c| c.is_alphanumeric() || c == '_')
+            })
+    };
+    if !is_dotted_name(module) || object.is_some_and(|object| !is_dotted_name(object)) {
+        return Err(format!("{name} has an invalid object reference {value:?}"));
+    }
+    Ok(EntryPoint {
+        name: name.to_string(),
+        module: module.to_string(),
+        object: object.map(ToString::to_string),
+        extras,
+    })
+}
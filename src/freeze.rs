@@ -0,0 +1,112 @@
+//! Read-only venvs for deployments where the environment must not change at runtime
+
+use crate::Error;
+use camino::{Utf8Path, Utf8PathBuf};
+use fs_err as fs;
+use std::io;
+use tracing::debug;
+
+/// Marker file in the venv root, written by [`freeze_venv`]
+pub const FROZEN_MARKER: &str = ".gourgeist-frozen";
+
+/// Mark all files and directories in the venv read-only and write the frozen marker.
+///
+/// Symlinks and hardlinked files are skipped since changing their permissions would change the
+/// target or the other links, e.g. the base interpreter or the wheel cache.
+pub fn freeze_venv(location: &Utf8Path) -> Result<(), Error> {
+    fs::write(
+        location.join(FROZEN_MARKER),
+        "This venv was frozen by gourgeist, use `gourgeist unfreeze` to make it writable again\n",
+    )?;
+    let mut count = 0;
+    walk_venv(location, &mut |path, metadata| {
+        let mut permissions = metadata.permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(path, permissions)?;
+        count += 1;
+        Ok(())
+    })?;
+    debug!("Made {count} files and directories read-only");
+    Ok(())
+}
+
+/// Make the venv writable again (for the owner) and remove the frozen marker.
+pub fn unfreeze_venv(location: &Utf8Path) -> Result<(), Error> {
+    if !is_frozen(location) {
+        return Err(Error::NotFrozen(location.to_path_buf()));
+    }
+    make_writable(location)?;
+    fs::remove_file(location.join(FROZEN_MARKER))?;
+    Ok(())
+}
+
+/// Make all files and directories in the venv writable for the owner, e.g. so they can be deleted.
+pub(crate) fn make_writable(location: &Utf8Path) -> io::Result<()> {
+    walk_venv(location, &mut |path, metadata| {
+        let mut permissions = metadata.permissions();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            permissions.set_mode(permissions.mode() | 0o200);
+        }
+        #[cfg(not(unix))]
+        {
+            permissions.set_readonly(false);
+        }
+        fs::set_permissions(path, permissions)?;
+        Ok(())
+    })
+}
+
+/// Whether the venv has the frozen marker
+pub fn is_frozen(location: &Utf8Path) -> bool {
+    location.join(FROZEN_MARKER).is_file()
+}
+
+/// Check a frozen venv, returning all files and directories that are writable again.
+pub fn check_frozen(location: &Utf8Path) -> Result<Vec<Utf8PathBuf>, Error> {
+    if !is_frozen(location) {
+        return Err(Error::NotFrozen(location.to_path_buf()));
+    }
+    let mut writable = Vec::new();
+    walk_venv(location, &mut |path, metadata| {
+        if !metadata.permissions().readonly() {
+            writable.push(path.to_path_buf());
+        }
+        Ok(())
+    })?;
+    Ok(writable)
+}
+
+/// Call `f` for every file and directory in the venv (including the root), children before their
+/// parent directory, skipping symlinks and files with other hardlinks, which are shared with the
+/// cache, the package store or the base interpreter.
+fn walk_venv(
+    dir: &Utf8Path,
+    f: &mut impl FnMut(&Utf8Path, &std::fs::Metadata) -> io::Result<()>,
+) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = Utf8PathBuf::try_from(entry.path()).map_err(|err| err.into_io_error())?;
+        let metadata = fs::symlink_metadata(&path)?;
+        if metadata.is_dir() {
+            walk_venv(&path, f)?;
+        } else if !metadata.is_symlink() && !is_shared_hardlink(&metadata) {
+            f(&path, &metadata)?;
+        }
+    }
+    f(dir, &fs::symlink_metadata(dir)?)
+}
+
+fn is_shared_hardlink(metadata: &std::fs::Metadata) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        metadata.nlink() > 1
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = metadata;
+        false
+    }
+}
@@ -0,0 +1,98 @@
+//! Describe an existing venv
+
+use crate::bare::{read_pyvenv_cfg, seed_packages, venv_python, VenvPaths};
+use crate::link::LinkMode;
+use crate::paths::canonicalize;
+use crate::{Context, Error};
+use camino::{Utf8Path, Utf8PathBuf};
+use fs_err as fs;
+use serde::Serialize;
+
+/// Details about an existing venv, see [`venv_info`]
+#[derive(Debug, Clone, Serialize)]
+pub struct VenvInfo {
+    pub root: Utf8PathBuf,
+    /// The interpreter the venv was created from
+    pub base_interpreter: Option<Utf8PathBuf>,
+    /// Whether the base interpreter still exists, i.e. whether the venv still works
+    pub base_interpreter_exists: bool,
+    pub python_version: Option<String>,
+    /// The `prompt` from `pyvenv.cfg`, the directory name by default like in the activators
+    pub prompt: String,
+    /// How `bin/python` was created. Reflinks can't be told apart from copies
+    pub link_mode: Option<LinkMode>,
+    /// The gourgeist version that created the venv, if it was created by gourgeist
+    pub gourgeist_version: Option<String>,
+    pub seed_packages: Vec<SeedPackageVersion>,
+}
+
+/// An installed seed package
+#[derive(Debug, Clone, Serialize)]
+pub struct SeedPackageVersion {
+    pub name: String,
+    pub version: String,
+}
+
+/// Collect the details of the venv at `location` from its `pyvenv.cfg` and its files.
+///
+/// This works for venvs created by `python -m venv` and virtualenv too.
+pub fn venv_info(ctx: &Context, location: &Utf8Path) -> Result<VenvInfo, Error> {
+    let location = &ctx.absolute(location);
+    let cfg = read_pyvenv_cfg(location)?;
+    let root = canonicalize(location)?;
+    // `python -m venv` records `executable` and `version` instead
+    let base_interpreter = cfg
+        .get("base-executable")
+        .or_else(|| cfg.get("executable"))
+        .map(Utf8PathBuf::from);
+    let python_version = cfg
+        .get("version_info")
+        .or_else(|| cfg.get("version"))
+        .map(str::to_string);
+    let prompt = cfg.get("prompt").map(str::to_string).unwrap_or_else(|| {
+        root.file_name()
+            .map(ToString::to_string)
+            .unwrap_or_default()
+    });
+    let seed_packages = match python_version.as_deref().and_then(parse_minor) {
+        Some((major, minor)) => seed_packages(&VenvPaths::with_version(&root, major, minor))?
+            .into_iter()
+            .map(|(name, version)| SeedPackageVersion { name, version })
+            .collect(),
+        None => Vec::new(),
+    };
+    Ok(VenvInfo {
+        base_interpreter_exists: base_interpreter
+            .as_ref()
+            .is_some_and(|base_interpreter| base_interpreter.exists()),
+        base_interpreter,
+        python_version,
+        prompt,
+        link_mode: interpreter_link_mode(&venv_python(&root)),
+        gourgeist_version: cfg.get("gourgeist").map(str::to_string),
+        seed_packages,
+        root,
+    })
+}
+
+/// `3.11.7` to `(3, 11)`
+pub(crate) fn parse_minor(version: &str) -> Option<(u8, u8)> {
+    let mut parts = version.split('.');
+    Some((parts.next()?.parse().ok()?, parts.next()?.parse().ok()?))
+}
+
+/// Tell how `bin/python` was created from its file type
+fn interpreter_link_mode(interpreter: &Utf8Path) -> Option<LinkMode> {
+    let metadata = fs::symlink_metadata(interpreter).ok()?;
+    if metadata.file_type().is_symlink() {
+        return Some(LinkMode::Symlink);
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        if metadata.nlink() > 1 {
+            return Some(LinkMode::Hardlink);
+        }
+    }
+    Some(LinkMode::Copy)
+}
@@ -1,12 +1,20 @@
-use crate::{crate_cache_dir, Error};
+use crate::cache::{write_atomic, CacheBucket};
+use crate::durable::sync_file;
+use crate::paths::canonicalize;
+use crate::pyvenv_cfg::PyVenvCfg;
+use crate::universal2;
+use crate::{Context, Error};
 use camino::{FromPathBufError, Utf8Path, Utf8PathBuf};
+use clap::ValueEnum;
 use fs_err as fs;
 use fs_err::File;
 use serde::{Deserialize, Serialize};
 use std::io;
-use std::io::{BufReader, Write};
-use std::process::{Command, Stdio};
-use std::time::SystemTime;
+use std::io::{BufReader, Read, Write};
+use std::process::{Child, Command, Output, Stdio};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime};
 use tracing::{debug, error, info, warn};
 
 const QUERY_PYTHON: &str = include_str!("query_python.py");
@@ -18,20 +26,63 @@ pub struct InterpreterInfo {
     pub major: u8,
     pub minor: u8,
     pub python_version: String,
+    /// `sys.implementation.name`, e.g. `cpython` or `pypy`
+    pub implementation: String,
+    /// `sys.platform`, e.g. `linux`, `darwin` or `win32`
+    pub platform: String,
+    /// `platform.machine()`, e.g. `x86_64` or `arm64`
+    pub machine: String,
+    /// The ABI tag of extension modules, e.g. `cpython-311-x86_64-linux-gnu`
+    pub soabi: Option<String>,
+    /// The file extension of extension modules, e.g. `.cpython-311-x86_64-linux-gnu.so`
+    pub ext_suffix: Option<String>,
+    /// Whether this is a macOS framework build
+    pub framework: bool,
+    /// Whether this is a macOS universal2 build, with both an `arm64` and an `x86_64` binary
+    #[serde(default)]
+    pub universal2: bool,
+    /// Where a venv of this interpreter has its files
+    pub sysconfig_paths: SysconfigPaths,
+}
+
+/// The sysconfig install paths of a venv, relative to the venv root
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SysconfigPaths {
+    /// Pure python packages, e.g. `lib/python3.11/site-packages`
+    pub purelib: Utf8PathBuf,
+    /// Packages with extension modules, usually the same as `purelib`
+    pub platlib: Utf8PathBuf,
+    /// Console scripts, `bin` on unix and `Scripts` on windows
+    pub scripts: Utf8PathBuf,
+    /// C headers, e.g. `include/python3.11`
+    pub include: Utf8PathBuf,
 }
 
 /// Gets the interpreter.rs info, either cached or by running it.
-pub fn get_interpreter_info(interpreter: &Utf8Path) -> Result<InterpreterInfo, Error> {
-    let cache_dir = crate_cache_dir()?.join("interpreter_info");
+pub fn get_interpreter_info(
+    ctx: &Context,
+    interpreter: &Utf8Path,
+) -> Result<InterpreterInfo, Error> {
+    let interpreter = &ctx.absolute(interpreter);
+    let cache_dir = CacheBucket::Interpreters.path(ctx)?;
+    let start = Instant::now();
 
-    let index = seahash::hash(interpreter.as_str().as_bytes());
-    let cache_file = cache_dir.join(index.to_string()).with_extension("json");
+    if ctx.no_cache {
+        let interpreter_info = query_interpreter(ctx, interpreter)?;
+        debug!(
+            step = "interpreter",
+            path = %interpreter,
+            cached = false,
+            duration_ms = start.elapsed().as_millis() as u64,
+            "Queried the interpreter info without the cache"
+        );
+        return Ok(interpreter_info);
+    }
 
-    let modified = fs::metadata(interpreter)?
-        .modified()?
-        .duration_since(SystemTime::UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_millis();
+    // Aliases such as `python3` and `python3.11` share an entry through the resolved path
+    let key = CacheKey::new(interpreter, ctx.interpreter_cache)?;
+    let index = seahash::hash(key.interpreter.as_str().as_bytes());
+    let cache_file = cache_dir.join(index.to_string()).with_extension("json");
 
     if cache_file.exists() {
         let cache_entry: Result<CacheEntry, String> = File::open(&cache_file)
@@ -42,12 +93,19 @@ pub fn get_interpreter_info(interpreter: &Utf8Path) -> Result<InterpreterInfo, E
         match cache_entry {
             Ok(cache_entry) => {
                 debug!("Using cache entry {cache_file}");
-                if modified == cache_entry.modified && interpreter == cache_entry.interpreter {
+                if key == cache_entry.key {
+                    debug!(
+                        step = "interpreter",
+                        path = %interpreter,
+                        cached = true,
+                        duration_ms = start.elapsed().as_millis() as u64,
+                        "Read the cached interpreter info"
+                    );
                     return Ok(cache_entry.interpreter_info);
                 } else {
                     debug!(
-                        "Removing mismatching cache entry {cache_file} ({} {} {} {})",
-                        modified, cache_entry.modified, interpreter, cache_entry.interpreter
+                        "Removing mismatching cache entry {cache_file} ({:?} {:?})",
+                        key, cache_entry.key
                     );
                     if let Err(remove_err) = fs::remove_file(&cache_file) {
                         warn!("Failed to mismatching cache file at {cache_file}: {remove_err}")
@@ -63,29 +121,150 @@ pub fn get_interpreter_info(interpreter: &Utf8Path) -> Result<InterpreterInfo, E
         }
     }
 
-    let interpreter_info = query_interpreter(interpreter)?;
-    fs::create_dir_all(&cache_dir)?;
+    let interpreter_info = query_interpreter(ctx, interpreter)?;
+    CacheBucket::Interpreters.create(ctx)?;
     let cache_entry = CacheEntry {
-        interpreter: interpreter.to_path_buf(),
-        modified,
+        key,
+        queried_as: interpreter.to_path_buf(),
         interpreter_info: interpreter_info.clone(),
     };
-    let mut cache_writer = File::create(&cache_file)?;
-    serde_json::to_writer(&mut cache_writer, &cache_entry).map_err(io::Error::from)?;
+    write_atomic(
+        &cache_file,
+        &serde_json::to_vec(&cache_entry).map_err(io::Error::from)?,
+    )?;
+    sync_file(ctx, &cache_file)?;
+    debug!(
+        step = "interpreter",
+        path = %interpreter,
+        cached = false,
+        duration_ms = start.elapsed().as_millis() as u64,
+        "Queried the interpreter info"
+    );
 
     Ok(interpreter_info)
 }
 
+/// How cached interpreter info is invalidated
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum InterpreterCacheMode {
+    /// Compare the path, modification time and size of the interpreter
+    #[default]
+    Metadata,
+    /// Additionally compare a hash of the interpreter binary, for package managers that replace
+    /// the interpreter in place while keeping its modification time
+    Hash,
+}
+
+/// A cache entry is valid as long as the interpreter at the same path has the same modification
+/// time and size, which changes when the interpreter is upgraded or replaced.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub(crate) struct CacheKey {
+    /// The interpreter with all symlinks resolved
+    pub(crate) interpreter: Utf8PathBuf,
+    /// Milliseconds since the unix epoch. Not `u128`, which serde can't flatten
+    modified: u64,
+    size: u64,
+    /// The seahash of the interpreter binary with [`InterpreterCacheMode::Hash`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    hash: Option<u64>,
+}
+
+impl CacheKey {
+    pub(crate) fn new(interpreter: &Utf8Path, mode: InterpreterCacheMode) -> io::Result<Self> {
+        let interpreter = &canonicalize(interpreter)?;
+        let metadata = fs::metadata(interpreter)?;
+        let modified = metadata
+            .modified()?
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        Ok(Self {
+            interpreter: interpreter.to_path_buf(),
+            modified,
+            size: metadata.len(),
+            hash: match mode {
+                InterpreterCacheMode::Metadata => None,
+                InterpreterCacheMode::Hash => Some(seahash::hash(&fs::read(interpreter)?)),
+            },
+        })
+    }
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
-struct CacheEntry {
-    interpreter: Utf8PathBuf,
-    modified: u128,
+pub(crate) struct CacheEntry {
+    #[serde(flatten)]
+    pub(crate) key: CacheKey,
+    /// The path the interpreter was queried through, which may be a symlink
+    queried_as: Utf8PathBuf,
     interpreter_info: InterpreterInfo,
 }
 
+/// Like [`std::process::Child::wait_with_output`], but kill the child if it doesn't exit within
+/// `timeout`, e.g. because a broken shim waits for input.
+fn wait_with_timeout(mut child: Child, timeout: Duration) -> io::Result<Output> {
+    // Read the pipes concurrently, otherwise a child with a lot of output blocks on a full pipe
+    let stdout = child.stdout.take().map(read_in_thread);
+    let stderr = child.stderr.take().map(read_in_thread);
+    let start = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if start.elapsed() > timeout {
+            child.kill()?;
+            child.wait()?;
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!(
+                    "The interpreter didn't respond within {}s, check that it runs on its own \
+                    or increase the timeout with `--query-timeout`",
+                    timeout.as_secs_f32()
+                ),
+            ));
+        }
+        thread::sleep(Duration::from_millis(5));
+    };
+    let join = |reader: Option<JoinHandle<io::Result<Vec<u8>>>>| {
+        reader
+            .map(|reader| reader.join().expect("The pipe reader panicked"))
+            .transpose()
+            .map(Option::unwrap_or_default)
+    };
+    Ok(Output {
+        status,
+        stdout: join(stdout)?,
+        stderr: join(stderr)?,
+    })
+}
+
+fn read_in_thread(mut pipe: impl Read + Send + 'static) -> JoinHandle<io::Result<Vec<u8>>> {
+    thread::spawn(move || {
+        let mut buffer = Vec::new();
+        pipe.read_to_end(&mut buffer)?;
+        Ok(buffer)
+    })
+}
+
+/// Environment variables that change which code python runs on startup or where it looks for its
+/// standard library. `-I` already ignores them, but we also remove them for the interpreters that
+/// are wrapper scripts.
+const SCRUBBED_ENV: [&str; 3] = ["PYTHONPATH", "PYTHONSTARTUP", "PYTHONHOME"];
+
 /// Runs a python script that returns the relevant info about the interpreter.rs as json
-fn query_interpreter(interpreter: &Utf8Path) -> Result<InterpreterInfo, Error> {
+///
+/// The interpreter runs in isolated mode, so neither the user site-packages nor the `PYTHON*`
+/// environment variables can print to stderr or change the reported prefixes.
+fn query_interpreter(ctx: &Context, interpreter: &Utf8Path) -> Result<InterpreterInfo, Error> {
+    let env = ctx
+        .env
+        .iter()
+        .filter(|(key, _)| !SCRUBBED_ENV.contains(&key.as_str()));
     let mut child = Command::new(interpreter)
+        .arg("-I")
+        .current_dir(&ctx.cwd)
+        .env_clear()
+        .envs(env)
         .stdin(Stdio::piped())
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
@@ -99,7 +278,11 @@ fn query_interpreter(interpreter: &Utf8Path) -> Result<InterpreterInfo, Error> {
                 err,
             })?;
     }
-    let output = child.wait_with_output()?;
+    let output =
+        wait_with_timeout(child, ctx.query_timeout).map_err(|err| Error::PythonSubcommand {
+            interpreter: interpreter.to_path_buf(),
+            err,
+        })?;
     let stdout = String::from_utf8(output.stdout).unwrap_or_else(|err| {
         // At this point, there was most likely an error caused by a non-utf8 character, so we're in
         // an ugly case but still very much want to give the user a chance
@@ -133,7 +316,7 @@ fn query_interpreter(interpreter: &Utf8Path) -> Result<InterpreterInfo, Error> {
             )
         });
     }
-    let data = serde_json::from_str::<InterpreterInfo>(&stdout).map_err(|err|
+    let mut data = serde_json::from_str::<InterpreterInfo>(&stdout).map_err(|err|
         Error::PythonSubcommand {
             interpreter: interpreter.to_path_buf(),
             err: io::Error::new(
@@ -148,12 +331,173 @@ fn query_interpreter(interpreter: &Utf8Path) -> Result<InterpreterInfo, Error> {
             )
         }
     )?;
+    // A universal2 interpreter started by gourgeist under Rosetta runs as `x86_64`, while the venv
+    // python runs natively
+    if data.universal2 && universal2::is_translated() {
+        debug!(
+            "Recording {interpreter} as {} instead of {}, we're running under Rosetta",
+            universal2::native_machine(),
+            data.machine
+        );
+        data.machine = universal2::native_machine().to_string();
+    }
     Ok(data)
 }
 
+/// Build the interpreter info from the python version without running the interpreter, for
+/// callers that already know the interpreter, e.g. because they just installed it.
+///
+/// This assumes a CPython with the standard layout for the current platform: The prefix is the
+/// parent of the `bin` directory of the resolved interpreter path (the directory of the
+/// interpreter on windows) and the venv uses the default install scheme. Fields that don't fit
+/// can be corrected with [`override_interpreter_info`].
+pub fn assumed_interpreter_info(
+    ctx: &Context,
+    interpreter: &Utf8Path,
+    python_version: &str,
+) -> Result<InterpreterInfo, Error> {
+    let invalid_version = || Error::InvalidPythonVersion(python_version.to_string());
+    let mut parts = python_version.split('.');
+    let major: u8 = parts
+        .next()
+        .and_then(|major| major.parse().ok())
+        .ok_or_else(invalid_version)?;
+    let minor: u8 = parts
+        .next()
+        .and_then(|minor| minor.parse().ok())
+        .ok_or_else(invalid_version)?;
+    if major != 3 {
+        return Err(invalid_version());
+    }
+
+    let interpreter = canonicalize(&ctx.absolute(interpreter))?;
+    let bin = interpreter.parent().unwrap_or(&interpreter);
+    let prefix = if cfg!(windows) {
+        bin
+    } else {
+        bin.parent().unwrap_or(bin)
+    };
+    let (platform, machine) = match (std::env::consts::OS, std::env::consts::ARCH) {
+        ("windows", "x86_64") => ("win32", "AMD64"),
+        ("windows", "aarch64") => ("win32", "ARM64"),
+        ("windows", arch) => ("win32", arch),
+        ("macos", "aarch64") => ("darwin", "arm64"),
+        ("macos", arch) => ("darwin", arch),
+        (os, arch) => (os, arch),
+    };
+    let soabi = match platform {
+        "win32" => None,
+        "darwin" => Some(format!("cpython-{major}{minor}-darwin")),
+        _ => Some(format!("cpython-{major}{minor}-{machine}-{platform}-gnu")),
+    };
+    let ext_suffix = match &soabi {
+        Some(soabi) => Some(format!(".{soabi}.so")),
+        None => Some(format!(
+            ".cp{major}{minor}-{}.pyd",
+            if machine == "ARM64" {
+                "win_arm64"
+            } else {
+                "win_amd64"
+            }
+        )),
+    };
+    let sysconfig_paths = if platform == "win32" {
+        SysconfigPaths {
+            purelib: Utf8PathBuf::from("Lib/site-packages"),
+            platlib: Utf8PathBuf::from("Lib/site-packages"),
+            scripts: Utf8PathBuf::from("Scripts"),
+            include: Utf8PathBuf::from("Include"),
+        }
+    } else {
+        let site_packages = Utf8PathBuf::from(format!("lib/python{major}.{minor}/site-packages"));
+        SysconfigPaths {
+            purelib: site_packages.clone(),
+            platlib: site_packages,
+            scripts: Utf8PathBuf::from("bin"),
+            include: Utf8PathBuf::from(format!("include/python{major}.{minor}")),
+        }
+    };
+    info!("Assuming {interpreter} is python {python_version} with the prefix {prefix}");
+    Ok(InterpreterInfo {
+        base_exec_prefix: prefix.to_string(),
+        base_prefix: prefix.to_string(),
+        major,
+        minor,
+        python_version: python_version.to_string(),
+        implementation: "cpython".to_string(),
+        platform: platform.to_string(),
+        machine: machine.to_string(),
+        soabi,
+        ext_suffix,
+        framework: false,
+        universal2: false,
+        sysconfig_paths,
+    })
+}
+
+/// Patch fields of the queried interpreter info, an escape hatch for vendor pythons that report
+/// wrong values, e.g. a broken `base_prefix`.
+///
+/// The overrides are applied in order and must name existing fields.
+pub fn override_interpreter_info(
+    info: InterpreterInfo,
+    overrides: &[(String, serde_json::Value)],
+) -> Result<InterpreterInfo, Error> {
+    if overrides.is_empty() {
+        return Ok(info);
+    }
+    let mut data = serde_json::to_value(info).map_err(io::Error::from)?;
+    let fields = data
+        .as_object_mut()
+        .expect("InterpreterInfo is serialized as object");
+    for (key, value) in overrides {
+        let Some(field) = fields.get_mut(key) else {
+            return Err(Error::InvalidInterpreterInfoOverride(format!(
+                "There is no interpreter info field named {key}"
+            )));
+        };
+        warn!("Overriding interpreter info {key}: {field} -> {value}");
+        *field = value.clone();
+    }
+    serde_json::from_value(data)
+        .map_err(|err| Error::InvalidInterpreterInfoOverride(err.to_string()))
+}
+
+/// Parse a `key=value` override for [`override_interpreter_info`]. The value is parsed as JSON if
+/// possible and used as string otherwise, so both `major=3` and `base_prefix=/usr` work.
+pub fn parse_interpreter_info_override(
+    override_: &str,
+) -> Result<(String, serde_json::Value), String> {
+    let (key, value) = override_
+        .split_once('=')
+        .ok_or_else(|| format!("Expected key=value, got {override_}"))?;
+    let value = serde_json::from_str(value)
+        .unwrap_or_else(|_| serde_json::Value::String(value.to_string()));
+    Ok((key.trim().to_string(), value))
+}
+
+/// Read overrides for [`override_interpreter_info`] from a JSON file with an object of fields.
+pub fn read_interpreter_info_overrides(
+    path: &Utf8Path,
+) -> Result<Vec<(String, serde_json::Value)>, Error> {
+    let overrides: serde_json::Map<String, serde_json::Value> =
+        serde_json::from_str(&fs::read_to_string(path)?).map_err(|err| {
+            Error::InvalidInterpreterInfoOverride(format!("{path} is not a JSON object: {err}"))
+        })?;
+    Ok(overrides.into_iter().collect())
+}
+
 /// Parse the value of the `-p`/`--python` option, which can be e.g. `3.11`, `python3.11`,
 /// `tools/bin/python3.11` or `/usr/bin/python3.11`.
-pub fn parse_python_cli(cli_python: Option<Utf8PathBuf>) -> Result<Utf8PathBuf, crate::Error> {
+///
+/// Names are looked up in the `PATH` of the context, paths are made absolute. With the
+/// `managed-python` feature, versions that aren't in `PATH` are looked up in or downloaded to the
+/// managed pythons.
+pub fn parse_python_cli(
+    ctx: &Context,
+    cli_python: Option<Utf8PathBuf>,
+) -> Result<Utf8PathBuf, crate::Error> {
+    let mut version = None;
     let python = if let Some(python) = cli_python {
         if let Some((major, minor)) = python
             .as_str()
@@ -166,6 +510,7 @@ pub fn parse_python_cli(cli_python: Option<Utf8PathBuf>) -> Result<Utf8PathBuf,
                 ));
             }
             info!("Looking for python {major}.{minor}");
+            version = Some((major, minor));
             Utf8PathBuf::from(format!("python{major}.{minor}"))
         } else {
             python
@@ -179,14 +524,24 @@ pub fn parse_python_cli(cli_python: Option<Utf8PathBuf>) -> Result<Utf8PathBuf,
         // Does this path contain a slash (unix) or backslash (windows)? In that case, assume it's
         // relative or absolute path that we don't need to resolve
         info!("Assuming {python} is a path");
-        python
+        ctx.absolute(&python)
     } else {
-        let python_in_path = which::which(python.as_std_path())
-            .map_err(|err| {
-                crate::Error::InvalidPythonInterpreter(
+        let python_in_path = match which::which_in(python.as_std_path(), ctx.var("PATH"), &ctx.cwd)
+        {
+            Ok(python_in_path) => python_in_path,
+            Err(err) => {
+                #[cfg(feature = "managed-python")]
+                if let Some((major, minor)) = version {
+                    return managed_python(ctx, major, minor);
+                }
+                #[cfg(not(feature = "managed-python"))]
+                let _ = version;
+                return Err(crate::Error::InvalidPythonInterpreter(
                     format!("Can't find {python} ({err})").into(),
-                )
-            })?
+                ));
+            }
+        };
+        let python_in_path: Utf8PathBuf = python_in_path
             .try_into()
             .map_err(|err: FromPathBufError| err.into_io_error())?;
         info!("Resolved {python} to {python_in_path}");
@@ -194,3 +549,81 @@ pub fn parse_python_cli(cli_python: Option<Utf8PathBuf>) -> Result<Utf8PathBuf,
     };
     Ok(python)
 }
+
+/// A previously downloaded python of the version, or a new download if allowed.
+#[cfg(feature = "managed-python")]
+fn managed_python(ctx: &Context, major: u8, minor: u8) -> Result<Utf8PathBuf, Error> {
+    if let Some(python) = crate::managed::find_managed_python(ctx, major, minor)? {
+        info!("Using the downloaded python {python}");
+        return Ok(python);
+    }
+    if !ctx.python_downloads {
+        return Err(Error::InvalidPythonInterpreter(
+            format!("Can't find python{major}.{minor} and python downloads are disabled").into(),
+        ));
+    }
+    crate::managed::download_python(ctx, major, minor)
+}
+
+/// The conda environment the interpreter belongs to, if any.
+///
+/// Conda environments have a `conda-meta` directory, and an activated conda environment sets
+/// `CONDA_PREFIX`.
+pub(crate) fn conda_prefix(ctx: &Context, info: &InterpreterInfo) -> Option<Utf8PathBuf> {
+    let base_prefix = Utf8Path::new(&info.base_prefix);
+    let is_active = ctx
+        .var("CONDA_PREFIX")
+        .is_some_and(|conda_prefix| Utf8Path::new(conda_prefix) == base_prefix);
+    (is_active || base_prefix.join("conda-meta").is_dir()).then(|| base_prefix.to_path_buf())
+}
+
+/// If `interpreter` is the python of a venv, the base interpreter the venv was created from.
+///
+/// Creating a venv from a venv python would link to that venv, so the new venv breaks when the
+/// other venv is deleted. Like python itself, we recognize a venv python by the `pyvenv.cfg` next
+/// to it or one directory up, and confirm that it's outside its `base_prefix`.
+pub fn venv_base_interpreter(
+    ctx: &Context,
+    interpreter: &Utf8Path,
+) -> Result<Option<Utf8PathBuf>, Error> {
+    let interpreter = &ctx.absolute(interpreter);
+    let Some(bin) = interpreter.parent() else {
+        return Ok(None);
+    };
+    let Some(pyvenv_cfg) = [Some(bin), bin.parent()]
+        .into_iter()
+        .flatten()
+        .map(|dir| dir.join("pyvenv.cfg"))
+        .find(|pyvenv_cfg| pyvenv_cfg.is_file())
+    else {
+        return Ok(None);
+    };
+    let info = get_interpreter_info(ctx, interpreter)?;
+    if interpreter.starts_with(&info.base_prefix) {
+        return Ok(None);
+    }
+    let cfg = PyVenvCfg::read(&pyvenv_cfg)?;
+    // `base-executable` is written by us and virtualenv, `executable` by venv on python 3.11+
+    if let Some(base) = cfg.get("base-executable").or_else(|| cfg.get("executable")) {
+        return Ok(Some(Utf8PathBuf::from(base)));
+    }
+    let Some(home) = cfg.get("home").map(Utf8Path::new) else {
+        warn!("{pyvenv_cfg} has no base interpreter, using {interpreter}");
+        return Ok(None);
+    };
+    let names = [
+        format!("python{}.{}", info.major, info.minor),
+        format!("python{}", info.major),
+        "python".to_string(),
+    ];
+    let base = names
+        .iter()
+        .map(|name| home.join(name))
+        .find(|base| base.is_file());
+    if base.is_none() {
+        warn!(
+            "Couldn't find the base interpreter in {home} from {pyvenv_cfg}, using {interpreter}"
+        );
+    }
+    Ok(base)
+}
@@ -1,19 +1,109 @@
-use crate::bare::create_bare_venv;
+use crate::bare::{
+    base_executable, check_active, check_dangerous, create_bare_venv, interpreter_names,
+    matches_existing, prepare_location, replace_with_staging, staging_dir, write_aliases,
+};
+use crate::interpreter::conda_prefix;
+use crate::paths::venv_root;
+use crate::scripts::{rewrite_shebangs, RELOCATABLE_SHEBANG};
 use camino::{Utf8Path, Utf8PathBuf};
-use dirs::cache_dir;
-use interpreter::InterpreterInfo;
+use serde::{Deserialize, Serialize};
 use std::io;
+use std::path::PathBuf;
+use std::process::ExitStatus;
+use std::time::Instant;
 use tempfile::PersistError;
 use thiserror::Error;
+use tracing::{debug, info, warn};
 
-pub use interpreter::{get_interpreter_info, parse_python_cli};
+pub use bare::{
+    check_alias, check_pyvenv_cfg_entry, check_removable, is_active_venv, parse_alias,
+    parse_pyvenv_cfg_entry, read_pyvenv_cfg, remove_venv, repair_venv, seed_packages,
+    set_system_site_packages, stale_venv, upgrade_venv, VenvPaths, SEED_PACKAGES,
+};
+pub use bench::{bench, BenchResult};
+pub use cache::{
+    cache_size, clean_cache, export_cache, format_bytes, gc_cache, gc_cache_periodically,
+    import_cache, CacheBucket, CacheGc,
+};
+pub use config::{project_config_path, user_config_path, Config, CONFIG_KEYS, PROJECT_CONFIG_FILE};
+pub use context::{Context, DEFAULT_INDEX_URL};
+pub use daemon::{create_via_daemon, run_daemon, socket_path, DaemonRequest};
+pub use diagnostics::hints;
+pub use diff::{diff_upgrade, FileChange, LinkChange, SeedChange, VenvDiff};
+pub use discovery::{find_pythons, FoundPython, PythonSource};
+pub use doctor::{doctor, Finding, Severity};
+pub use freeze::{check_frozen, freeze_venv, is_frozen, unfreeze_venv, FROZEN_MARKER};
+pub use info::{venv_info, SeedPackageVersion, VenvInfo};
+pub use interpreter::{
+    assumed_interpreter_info, get_interpreter_info, override_interpreter_info,
+    parse_interpreter_info_override, parse_python_cli, read_interpreter_info_overrides,
+    venv_base_interpreter, InterpreterCacheMode, InterpreterInfo, SysconfigPaths,
+};
+pub use link::LinkMode;
+#[cfg(feature = "managed-python")]
+pub use managed::{download_python, find_managed_python, managed_pythons_dir};
+pub use matrix::create_matrix;
+pub use metadata::{read_metadata, CreationMetadata, METADATA_FILE};
+pub use paths::utf8_path;
+pub use permissions::FileModes;
+pub use plan::{plan_venv, PlannedAction, PlannedLink, PlannedSeed, VenvPlan};
+pub use pyvenv_cfg::PyVenvCfg;
+pub use registry::{
+    check_venv_name, named_venvs_dir, read_registry, register_venv, registry_path, resolve_venv,
+    unregister_venv, RegistryEntry,
+};
+pub use run::{activated_env, install_requirements, run_in_venv, spawn_shell};
+pub use sbom::{SbomFormat, SbomOptions, SeededPackage};
+pub use scripts::{normalize_name, ScriptNaming};
+pub use shell::{
+    activate_snippet, allow_venv, disallow_venv, read_allowlist, shell_hook, write_envrc, Shell,
+};
+pub use universal2::Universal2;
+pub use verify::verify_venv;
+pub use workspace::{sync_workspace, MemberStatus, WorkspaceMember};
 
 mod bare;
+mod bench;
+mod cache;
+mod codesign;
+mod config;
+mod context;
+mod daemon;
+mod diagnostics;
+mod diff;
+mod discovery;
+mod doctor;
+mod durable;
+mod entry_points;
+mod freeze;
+mod info;
 mod interpreter;
+mod link;
+#[cfg(feature = "managed-python")]
+mod managed;
+mod matrix;
+mod metadata;
 #[cfg(feature = "install")]
 mod packages;
+mod paths;
+mod permissions;
+mod plan;
+mod pyvenv_cfg;
+mod registry;
+mod reproducible;
+mod run;
+mod sbom;
+mod scripts;
+mod shell;
+#[cfg(feature = "install")]
+mod store;
+mod universal2;
+mod verify;
 #[cfg(not(feature = "install"))]
 mod virtualenv_cache;
+#[cfg(not(feature = "install"))]
+mod wheel_data;
+mod workspace;
 
 #[derive(Debug, Error)]
 pub enum Error {
@@ -32,15 +122,46 @@ pub enum Error {
     },
     #[error("Failed to determine python interpreter to use")]
     InvalidPythonInterpreter(#[source] Box<dyn std::error::Error + Sync + Send>),
+    #[error("Invalid python version {0}, expected a python 3 version such as 3.11.7")]
+    InvalidPythonVersion(String),
+    #[error("Invalid venv name `{0}`, names may only contain letters, digits, `-`, `_` and `.` and must not start with `.`")]
+    InvalidVenvName(String),
+    #[error("Invalid config file {path}: {message}")]
+    InvalidConfig { path: Utf8PathBuf, message: String },
+    #[error("Invalid cache archive {path}: {message}")]
+    InvalidCacheArchive { path: Utf8PathBuf, message: String },
+    #[error("Invalid interpreter info override: {0}")]
+    InvalidInterpreterInfoOverride(String),
     #[error("Failed to query python interpreter at {interpreter}")]
     PythonSubcommand {
         interpreter: Utf8PathBuf,
         #[source]
         err: io::Error,
     },
-    #[cfg(feature = "install")]
+    #[cfg(any(feature = "install", feature = "managed-python"))]
     #[error("Failed to contact pypi")]
     MinReq(#[from] minreq::Error),
+    #[cfg(any(feature = "install", feature = "managed-python"))]
+    #[error("Request to {url} failed with status {status}")]
+    HttpStatus { url: String, status: i32 },
+    #[cfg(feature = "install")]
+    #[error("No release of {name} has a py3-none-any wheel for python {python_version} (the latest release is {version})")]
+    NoUniversalWheel {
+        name: String,
+        version: String,
+        python_version: String,
+    },
+    #[cfg(feature = "install")]
+    #[error(
+        "The wheel downloaded from {url} has the sha256 {actual}, but the index lists {expected}"
+    )]
+    WheelHashMismatch {
+        url: String,
+        expected: String,
+        actual: String,
+    },
+    #[error("{0} requires gourgeist to be built with the `install` feature")]
+    RequiresFeature(&'static str),
     #[cfg(feature = "install")]
     #[error("Failed to install {package}")]
     InstallWheel {
@@ -48,38 +169,640 @@ pub enum Error {
         #[source]
         err: install_wheel_rs::Error,
     },
+    #[error("Invalid entry points in {path}: {message}")]
+    InvalidEntryPoints { path: Utf8PathBuf, message: String },
+    #[error("The venv at {0} is not frozen")]
+    NotFrozen(Utf8PathBuf),
+    #[error("The venv at {0} is frozen, unfreeze it first to replace it")]
+    Frozen(Utf8PathBuf),
+    #[error("{0} is not a virtualenv")]
+    NotAVenv(Utf8PathBuf),
+    #[error("{0} is the active virtualenv, replacing it breaks the shells and processes using it (deactivate it first or pass `--force` to replace it anyway)")]
+    ActiveVenv(Utf8PathBuf),
+    #[error("Refusing to delete {location}, which is {reason} (pass `--allow-dangerous-location` if you really mean it)")]
+    DangerousLocation {
+        location: Utf8PathBuf,
+        reason: &'static str,
+    },
+    #[error("{} is not valid UTF-8, which is only supported for the directories containing the venv or the interpreter", .0.display())]
+    NonUtf8Path(PathBuf),
+    #[error("Can't upgrade {location} from python {old} to {new}, only patch upgrades are supported, please recreate the venv instead")]
+    UpgradeVersionMismatch {
+        location: Utf8PathBuf,
+        old: String,
+        new: String,
+    },
+    #[error("{0} is not a virtualenv created by gourgeist")]
+    NotAGourgeistVenv(Utf8PathBuf),
+    #[error("{location} is stale, {reason} (fix it with `gourgeist --upgrade`, `gourgeist repair` or by recreating it)")]
+    StaleVenv {
+        location: Utf8PathBuf,
+        reason: String,
+    },
+    #[error("Invalid SOURCE_DATE_EPOCH {0:?}, expected a unix timestamp")]
+    InvalidSourceDateEpoch(String),
+    #[error("A daemon is already listening on {0}")]
+    DaemonRunning(Utf8PathBuf),
+    #[error("The daemon failed: {0}")]
+    Daemon(String),
+    #[error("{interpreter} belongs to the conda environment {prefix}: venvs don't see its conda packages and copies of the interpreter don't find conda's libraries. Use a python that isn't from conda or create a conda environment instead (pass `--allow-conda` to create the venv anyway)")]
+    CondaInterpreter {
+        interpreter: Utf8PathBuf,
+        prefix: Utf8PathBuf,
+    },
+    #[cfg(feature = "managed-python")]
+    #[error("There is no python-build-standalone download of python {version} for {platform}")]
+    NoPythonDownload { version: String, platform: String },
+    #[cfg(feature = "managed-python")]
+    #[error("Failed to download python from {url} to {path}")]
+    PythonDownload {
+        url: String,
+        path: Utf8PathBuf,
+        #[source]
+        err: io::Error,
+    },
+    #[cfg(feature = "managed-python")]
+    #[error(
+        "The release of {url} publishes no SHA256 for it, refusing to install an unverified python"
+    )]
+    PythonDownloadNoHash { url: String },
+    #[cfg(feature = "managed-python")]
+    #[error("The python download from {url} has the SHA256 {actual}, expected {expected}")]
+    PythonDownloadHash {
+        url: String,
+        expected: String,
+        actual: String,
+    },
+    #[error("Invalid pyvenv.cfg entry: {0}")]
+    InvalidPyvenvCfgEntry(String),
+    #[error("Invalid alias: {0}")]
+    InvalidAlias(String),
+    #[error("{python} would also create {location}, which {other} creates, pass only one python per version")]
+    DuplicateMatrixPython {
+        python: Utf8PathBuf,
+        other: Utf8PathBuf,
+        location: Utf8PathBuf,
+    },
+    #[error("Failed to install the requirements into {location} ({status})")]
+    InstallRequirements {
+        location: Utf8PathBuf,
+        status: ExitStatus,
+    },
+    #[error("Failed to run {command}")]
+    Run {
+        command: String,
+        #[source]
+        err: io::Error,
+    },
+    #[cfg(not(feature = "install"))]
+    #[error("{name} is missing from virtualenv's app data at {path}")]
+    MissingSeedPackage { name: String, path: Utf8PathBuf },
+    #[error("Can't detect your shell from `$SHELL` ({0}), pass `--shell`")]
+    UnknownShell(String),
+    #[error("{0} is not supported on this platform")]
+    Unsupported(&'static str),
+    #[error(transparent)]
+    Batch(#[from] BatchError),
 }
 
-pub(crate) fn crate_cache_dir() -> io::Result<Utf8PathBuf> {
-    Ok(cache_dir()
-        .and_then(|path| Utf8PathBuf::from_path_buf(path).ok())
-        .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "Couldn't detect cache dir"))?
-        .join(env!("CARGO_PKG_NAME")))
+/// The failures of a batch operation, each with the path it failed for.
+#[derive(Debug, Error)]
+#[error("{} of {total} virtualenvs failed", failures.len())]
+pub struct BatchError {
+    /// The number of attempted operations, including the successful ones
+    pub total: usize,
+    pub failures: Vec<(Utf8PathBuf, Error)>,
+}
+
+/// Options for [`create_venv`]
+///
+/// Missing fields deserialize to their defaults, so metadata written before an option existed
+/// still reads.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VenvOptions {
+    /// Don't install `wheel`, `pip` and `setuptools`
+    pub bare: bool,
+    /// How to materialize `bin/python` and the seed packages, `None` for the platform defaults
+    /// ([`LinkMode::interpreter_default`] and [`LinkMode::packages_default`]). Only the interpreter
+    /// is affected with the `install` feature since the wheels are unpacked directly, unless
+    /// [`VenvOptions::store`] is set.
+    pub link_mode: Option<LinkMode>,
+    /// Install each seed package version once into a content-addressed store in the cache and
+    /// link its files into the venvs, so many venvs cost almost no extra disk. With hardlinks,
+    /// editing a package file in place changes it in all venvs. Ignored for reproducible venvs,
+    /// requires the `install` feature
+    pub store: bool,
+    /// Prefix, suffix or skip the console scripts of the seed packages
+    pub script_naming: ScriptNaming,
+    /// Make all files read-only after creation, for environments that must not be modified at
+    /// runtime, see [`freeze_venv`]
+    pub freeze: bool,
+    /// Make the venv root accessible only to its owner (0700). Unix only
+    pub private: bool,
+    /// Keep the file modes of the base interpreter and the cache or normalize them. Unix only
+    pub file_modes: FileModes,
+    /// Keep both architectures of a macOS universal2 base interpreter or copy only the native one.
+    /// Thinning always copies the interpreter
+    pub universal2: Universal2,
+    /// Replace the target directory even if it's neither empty nor a venv
+    pub force: bool,
+    /// Recreate the venv even if the existing one matches the interpreter and options
+    pub rebuild: bool,
+    /// Make the venv work after moving it, e.g. between the stages of a container build: The
+    /// console scripts find `python` relative to themselves and the activators determine the venv
+    /// from their own location. The base interpreter must still exist at the same path
+    pub relocatable: bool,
+    /// Make two runs with the same inputs produce bit-identical venvs: Files are written in a
+    /// stable order, all timestamps (including those in `.pyc` headers) are set to
+    /// `SOURCE_DATE_EPOCH` (or 1980-01-01) and the venv is relocatable to avoid absolute paths
+    /// where possible. Seed packages are always copied, since normalizing the timestamps of
+    /// hardlinks would change the cache
+    pub reproducible: bool,
+    /// Write a manifest of the seeded packages with their hashes and sources
+    pub sbom: Option<SbomOptions>,
+    /// Create the venv even if the base interpreter belongs to a conda environment
+    pub allow_conda: bool,
+    /// Give the venv access to the packages of the base interpreter, like
+    /// `python -m venv --system-site-packages`. The packages in the venv take precedence
+    pub system_site_packages: bool,
+    /// Additional `pyvenv.cfg` entries, e.g. markers for other tools, written after the standard
+    /// keys. See [`check_pyvenv_cfg_entry`]
+    pub extra_pyvenv_cfg: Vec<(String, String)>,
+    /// The command line that created the venv, recorded as `command` in `pyvenv.cfg` like
+    /// virtualenv does, so the venv can be recreated from its own metadata
+    pub command: Option<String>,
+    /// Don't write a `CACHEDIR.TAG`, which makes backup tools and `tar --exclude-caches` skip the
+    /// venv
+    pub no_cachedir_tag: bool,
+    /// Don't write a `.gitignore` that excludes the whole venv from git. An existing `.gitignore`
+    /// in a venv that is recreated is always kept
+    pub no_gitignore: bool,
+    /// The name the activators show in the shell prompt and export as `VIRTUAL_ENV_PROMPT`, the
+    /// venv directory name by default
+    pub prompt: Option<String>,
+    /// A directory with activator templates that replace the embedded ones of the same name (e.g.
+    /// `activate.fish`) or are added as additional activators. The placeholders
+    /// `{{ VIRTUAL_ENV_DIR }}`, `{{ VIRTUAL_PROMPT }}` and `{{ RELATIVE_SITE_PACKAGES }}` are
+    /// substituted like in the embedded templates
+    pub activator_templates: Option<Utf8PathBuf>,
+    /// Keep the venv location as given instead of resolving the symlinks in it, so `pyvenv.cfg`,
+    /// the activators and the script shebangs use the logical path, e.g. `/tmp` instead of
+    /// `/private/tmp` on macOS. The venv breaks if a symlink in its path is changed
+    pub no_resolve_symlinks: bool,
+    /// Extra symlinks in the scripts directory as (name, target), e.g. `python-dbg` to
+    /// `python3.12d`, created after the seed packages. Relative targets are relative to the
+    /// scripts directory. See [`check_alias`]
+    pub aliases: Vec<(String, String)>,
+    /// Allow replacing the filesystem root, the home directory or the prefix of the base
+    /// interpreter, which is otherwise refused since it's almost certainly a mistake
+    pub allow_dangerous_location: bool,
+}
+
+impl VenvOptions {
+    /// A hash of the options that affect the venv contents, recorded in `pyvenv.cfg` to detect
+    /// whether an existing venv can be reused.
+    ///
+    /// Only the options that change the files of the venv are included, each as a `key=value`
+    /// line and only if it differs from the default, so new options don't invalidate existing
+    /// venvs. Options that only affect how we get to the venv (e.g. `force`), its permissions
+    /// (`freeze`, `private`) or files outside it (an `sbom` output path) are left out. The
+    /// contents of custom activator templates are included, so editing them invalidates too.
+    pub fn fingerprint(&self) -> String {
+        // The kebab-case name of a `ValueEnum`, which is part of the CLI and therefore stable
+        fn name(value: &impl Serialize) -> String {
+            serde_json::to_value(value)
+                .ok()
+                .and_then(|value| value.as_str().map(str::to_string))
+                .unwrap_or_default()
+        }
+
+        let mut entries: Vec<(&str, String)> = Vec::new();
+        let mut flag = |key, enabled: bool| {
+            if enabled {
+                entries.push((key, "true".to_string()));
+            }
+        };
+        flag("bare", self.bare);
+        flag("relocatable", self.relocatable);
+        flag("reproducible", self.reproducible);
+        flag("no-cachedir-tag", self.no_cachedir_tag);
+        flag("no-gitignore", self.no_gitignore);
+        flag("no-resolve-symlinks", self.no_resolve_symlinks);
+        if let Some(link_mode) = self.link_mode {
+            entries.push(("link-mode", link_mode.to_string()));
+        }
+        if self.file_modes != FileModes::default() {
+            entries.push(("file-modes", name(&self.file_modes)));
+        }
+        if self.universal2 != Universal2::default() {
+            entries.push(("universal2", name(&self.universal2)));
+        }
+        let ScriptNaming {
+            prefix,
+            suffix,
+            skip,
+        } = &self.script_naming;
+        if !prefix.is_empty() {
+            entries.push(("script-prefix", prefix.clone()));
+        }
+        if !suffix.is_empty() {
+            entries.push(("script-suffix", suffix.clone()));
+        }
+        for package in skip {
+            entries.push(("no-scripts-for", package.clone()));
+        }
+        // Only a manifest inside the venv is part of it
+        if let Some(sbom) = self.sbom.as_ref().filter(|sbom| sbom.output.is_none()) {
+            entries.push(("sbom", name(&sbom.format)));
+        }
+        for (key, value) in &self.extra_pyvenv_cfg {
+            entries.push(("pyvenv-cfg", format!("{key}={value}")));
+        }
+        if let Some(prompt) = &self.prompt {
+            entries.push(("prompt", prompt.clone()));
+        }
+        for (alias, target) in &self.aliases {
+            entries.push(("alias", format!("{alias}={target}")));
+        }
+        if let Some(dir) = &self.activator_templates {
+            let mut templates: Vec<(String, u64)> = fs_err::read_dir(dir)
+                .into_iter()
+                .flatten()
+                .flatten()
+                .filter_map(|entry| {
+                    let contents = fs_err::read(entry.path()).ok()?;
+                    let name = entry.file_name().to_string_lossy().to_string();
+                    Some((name, seahash::hash(&contents)))
+                })
+                .collect();
+            templates.sort();
+            entries.push(("activator-templates", dir.to_string()));
+            for (name, hash) in templates {
+                entries.push(("activator-template", format!("{name}={hash:016x}")));
+            }
+        }
+
+        let encoded: String = entries
+            .iter()
+            .map(|(key, value)| format!("{key}={value}\n"))
+            .collect();
+        format!("{:016x}", seahash::hash(encoded.as_bytes()))
+    }
+}
+
+/// Validate the options against the interpreter and resolve the implied options and relative
+/// paths.
+pub(crate) fn check_options(
+    ctx: &Context,
+    base_python: &Utf8Path,
+    info: &InterpreterInfo,
+    options: &VenvOptions,
+) -> Result<VenvOptions, Error> {
+    if !options.allow_conda {
+        if let Some(prefix) = conda_prefix(ctx, info) {
+            return Err(Error::CondaInterpreter {
+                interpreter: base_python.to_path_buf(),
+                prefix,
+            });
+        }
+    }
+    for (key, value) in &options.extra_pyvenv_cfg {
+        check_pyvenv_cfg_entry(key, value).map_err(Error::InvalidPyvenvCfgEntry)?;
+    }
+    let reserved = interpreter_names(info);
+    for (name, target) in &options.aliases {
+        check_alias(name, target).map_err(Error::InvalidAlias)?;
+        if reserved.contains(name) || name.starts_with("activate") {
+            return Err(Error::InvalidAlias(format!(
+                "`{name}` is created by gourgeist"
+            )));
+        }
+    }
+    #[cfg(not(feature = "install"))]
+    if options.store && !options.bare {
+        return Err(Error::RequiresFeature("The package store"));
+    }
+    Ok(VenvOptions {
+        relocatable: options.relocatable || options.reproducible,
+        activator_templates: options
+            .activator_templates
+            .as_ref()
+            .map(|dir| ctx.absolute(dir)),
+        ..options.clone()
+    })
 }
 
 /// Create a virtualenv and if not bare, install `wheel`, `pip` and `setuptools`.
+///
+/// If there already is a venv created from the same interpreter with the same options, it's reused
+/// unless [`VenvOptions::rebuild`] is set.
 pub fn create_venv(
+    ctx: &Context,
     location: &Utf8Path,
     base_python: &Utf8Path,
     info: &InterpreterInfo,
-    bare: bool,
-) -> Result<(), Error> {
-    let paths = create_bare_venv(location, base_python, info)?;
+    options: &VenvOptions,
+) -> Result<VenvPaths, Error> {
+    let location = &ctx.absolute(location);
+    let base_python = &base_executable(&ctx.absolute(base_python), info);
+    let options = &check_options(ctx, base_python, info, options)?;
+    // Fail early on an invalid `SOURCE_DATE_EPOCH`
+    let epoch = if options.reproducible {
+        Some(reproducible::source_date_epoch(ctx)?)
+    } else {
+        None
+    };
+    let start = Instant::now();
+    // We only know the hashes of the seeded packages when we install them
+    if !options.rebuild
+        && options.sbom.is_none()
+        && matches_existing(location, base_python, info, options)
+    {
+        info!("Reusing existing venv at {location}");
+        debug!(
+            step = "reuse",
+            path = %location,
+            cached = true,
+            duration_ms = start.elapsed().as_millis() as u64,
+            "Checked the existing venv"
+        );
+        return Ok(VenvPaths::new(
+            &venv_root(location, options.no_resolve_symlinks)?,
+            info,
+        ));
+    }
+    if is_frozen(location) {
+        return Err(Error::Frozen(location.to_path_buf()));
+    }
+    check_active(ctx, location, options.force)?;
+    check_dangerous(ctx, location, Some(info), options.allow_dangerous_location)?;
+    let location = prepare_location(location, options)?;
+    // Build the venv next to its final location and move it into place once it's complete, so a
+    // failure doesn't leave a broken venv behind
+    let staging = staging_dir(&location)?;
+    let staging_root = Utf8Path::from_path(staging.path()).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Non-utf8 temporary directory {}", staging.path().display()),
+        )
+    })?;
+    let paths = step("bare", &location, || {
+        Ok(create_bare_venv(
+            &location,
+            staging_root,
+            base_python,
+            info,
+            options,
+        )?)
+    })?;
+    let staging_paths = VenvPaths::new(staging_root, info);
 
-    if !bare {
+    let seeded = step("seed", &location, || {
+        if options.bare {
+            return Ok(Vec::new());
+        }
         #[cfg(feature = "install")]
         {
-            packages::install_base_packages(location, info, &paths)?;
+            // Normalizing the timestamps would change the shared files
+            let store = (options.store && !options.reproducible)
+                .then(|| options.link_mode.unwrap_or_else(LinkMode::packages_default));
+            packages::install_base_packages(
+                ctx,
+                &staging_paths,
+                &paths,
+                info,
+                &options.script_naming,
+                options.reproducible,
+                store,
+            )
         }
         #[cfg(not(feature = "install"))]
         {
             virtualenv_cache::install_base_packages(
-                &paths.bin,
+                ctx,
+                &staging_paths,
                 &paths.interpreter,
-                &paths.site_packages,
-            )?;
+                info,
+                if options.reproducible {
+                    LinkMode::Copy
+                } else {
+                    options.link_mode.unwrap_or_else(LinkMode::packages_default)
+                },
+                &options.script_naming,
+            )
+        }
+    })?;
+    if let Some(sbom) = &options.sbom {
+        let output = match &sbom.output {
+            Some(output) => ctx.absolute(output),
+            None => staging_root.join("sbom.json"),
+        };
+        sbom::write_sbom(&output, &seeded, sbom.format)?;
+    }
+    metadata::write_metadata(staging_root, base_python, info, options, &seeded, epoch)?;
+
+    step("finalize", &location, || {
+        write_aliases(&staging_paths.bin, &options.aliases)?;
+        if options.relocatable {
+            rewrite_shebangs(&staging_paths.bin, &paths.interpreter, RELOCATABLE_SHEBANG)?;
+        }
+        if let Some(epoch) = epoch {
+            reproducible::normalize_venv(staging_root, epoch)?;
+        }
+        if options.file_modes == FileModes::Normalize {
+            permissions::normalize_modes(staging_root)?;
+        }
+        if options.private {
+            permissions::make_private(staging_root)?;
+        }
+        Ok(())
+    })?;
+
+    if ctx.durable {
+        durable::sync_tree(staging_root)?;
+    }
+    replace_with_staging(staging, &location)?;
+    // A read-only directory can't be moved, so we freeze after moving
+    if options.freeze {
+        freeze_venv(&paths::extended(&location))?;
+    }
+    if ctx.durable {
+        // Persist the rename
+        durable::sync_dir(location.parent().unwrap_or(Utf8Path::new(".")))?;
+    }
+
+    Ok(paths)
+}
+
+/// Run one step of the venv creation and log its duration with the `step`, `path` and
+/// `duration_ms` fields for `--log-format json`.
+fn step<T>(
+    name: &'static str,
+    location: &Utf8Path,
+    operation: impl FnOnce() -> Result<T, Error>,
+) -> Result<T, Error> {
+    let start = Instant::now();
+    let result = operation();
+    debug!(
+        step = name,
+        path = %location,
+        duration_ms = start.elapsed().as_millis() as u64,
+        "Finished {name} in {}ms",
+        start.elapsed().as_millis()
+    );
+    result
+}
+
+/// Replace the seed packages (`pip`, `setuptools` and `wheel`) of an existing venv with their latest
+/// versions from pypi, including their console scripts, without touching other packages.
+///
+/// Returns the packages that were upgraded as (name, old version, new version).
+pub fn upgrade_deps(
+    ctx: &Context,
+    location: &Utf8Path,
+    script_naming: &ScriptNaming,
+) -> Result<Vec<(String, String, String)>, Error> {
+    let location = &ctx.absolute(location);
+    if !location.join("pyvenv.cfg").is_file() {
+        return Err(Error::NotAVenv(location.to_path_buf()));
+    }
+    if is_frozen(location) {
+        return Err(Error::Frozen(location.to_path_buf()));
+    }
+    #[cfg(feature = "install")]
+    {
+        // The layout depends on the venv's python, not the one we'd create a new venv with
+        let root = venv_root(location, bare::keeps_symlinks(location))?;
+        let info = get_interpreter_info(ctx, &bare::venv_python(&root))?;
+        let paths = VenvPaths::new(&root, &info);
+        let upgrades = packages::upgrade_base_packages(ctx, &paths, &info, script_naming)?;
+        if bare::is_relocatable(&root) {
+            rewrite_shebangs(&paths.bin, &paths.interpreter, RELOCATABLE_SHEBANG)?;
+        }
+        Ok(upgrades
+            .into_iter()
+            .map(|upgrade| (upgrade.name, upgrade.old_version, upgrade.new_version))
+            .collect())
+    }
+    #[cfg(not(feature = "install"))]
+    {
+        let _ = (ctx, script_naming);
+        Err(Error::RequiresFeature("Upgrading the seed packages"))
+    }
+}
+
+/// Download the seed wheels into the cache without creating a venv, so later creations work
+/// without network access. Returns the paths of the cached wheels.
+pub fn fetch_seeds(ctx: &Context) -> Result<Vec<Utf8PathBuf>, Error> {
+    #[cfg(feature = "install")]
+    {
+        // Filling the cache is the point
+        let ctx = &Context {
+            no_cache: false,
+            ..ctx.clone()
+        };
+        packages::fetch_seed_wheels(ctx)
+    }
+    #[cfg(not(feature = "install"))]
+    {
+        let _ = ctx;
+        Err(Error::RequiresFeature("Fetching the seed packages"))
+    }
+}
+
+/// Install a wheel into an existing venv, e.g. to seed a tool's own packages into a fresh venv
+/// without running pip. Only the wheel itself is installed, not its dependencies.
+pub fn install_wheel(paths: &VenvPaths, wheel: &Utf8Path) -> Result<(), Error> {
+    if is_frozen(&paths.root) {
+        return Err(Error::Frozen(paths.root.clone()));
+    }
+    #[cfg(feature = "install")]
+    {
+        let cfg = read_pyvenv_cfg(&paths.root)?;
+        let version_info = cfg.get("version_info").unwrap_or_default();
+        let mut parts = version_info.split('.').map(str::parse::<u8>);
+        let python_version = match (parts.next(), parts.next()) {
+            (Some(Ok(major)), Some(Ok(minor))) => (major, minor),
+            _ => {
+                return Err(Error::InvalidPyvenvCfgEntry(format!(
+                    "version_info = {version_info}"
+                )))
+            }
+        };
+        packages::install_wheel_file(paths, python_version, wheel)?;
+        if bare::is_relocatable(&paths.root) {
+            rewrite_shebangs(&paths.bin, &paths.interpreter, RELOCATABLE_SHEBANG)?;
         }
+        Ok(())
     }
+    #[cfg(not(feature = "install"))]
+    {
+        let _ = wheel;
+        Err(Error::RequiresFeature("Installing wheels"))
+    }
+}
 
-    Ok(())
+/// Create a virtualenv at each location with the same base interpreter and options.
+///
+/// See [`run_batch`] for the error handling.
+pub fn create_venvs(
+    ctx: &Context,
+    locations: &[Utf8PathBuf],
+    base_python: &Utf8Path,
+    info: &InterpreterInfo,
+    options: &VenvOptions,
+    fail_fast: bool,
+) -> Result<Vec<VenvPaths>, Error> {
+    run_batch(locations, fail_fast, |location| {
+        create_venv(ctx, location, base_python, info, options)
+    })
+}
+
+/// Run an operation for each location.
+///
+/// Unless `fail_fast` is set, all locations are attempted and the failures are collected into a
+/// [`BatchError`], so one bad target path doesn't throw away the progress on the others. With a
+/// single location, its error is returned as is.
+pub fn run_batch<T>(
+    locations: &[Utf8PathBuf],
+    fail_fast: bool,
+    mut operation: impl FnMut(&Utf8Path) -> Result<T, Error>,
+) -> Result<Vec<T>, Error> {
+    let mut results = Vec::new();
+    for location in locations {
+        let result = operation(location);
+        if fail_fast {
+            results.push((location.clone(), Ok(result?)));
+        } else {
+            results.push((location.clone(), result));
+        }
+    }
+    collect_batch(results)
+}
+
+/// Collect the results of a batch keyed by location (or another name for the operation) into
+/// the successes or a [`BatchError`] with all failures. A single operation's error is returned
+/// as is.
+pub(crate) fn collect_batch<T>(
+    results: Vec<(Utf8PathBuf, Result<T, Error>)>,
+) -> Result<Vec<T>, Error> {
+    let total = results.len();
+    let mut successes = Vec::new();
+    let mut failures = Vec::new();
+    for (location, result) in results {
+        match result {
+            Ok(result) => successes.push(result),
+            Err(err) if total == 1 => return Err(err),
+            Err(err) => {
+                warn!("Failed for {location}: {err}");
+                failures.push((location, err));
+            }
+        }
+    }
+    if failures.is_empty() {
+        Ok(successes)
+    } else {
+        Err(BatchError { total, failures }.into())
+    }
 }
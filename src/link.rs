@@ -0,0 +1,114 @@
+//! Materialize files in the venv by copying or linking them
+
+use clap::ValueEnum;
+use fs_err as fs;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::path::Path;
+use tracing::debug;
+
+/// How files get into the venv, both the seed packages from the cache and `bin/python`.
+///
+/// If the selected mode fails for a file, e.g. hardlinks across devices or reflinks on a filesystem
+/// without copy-on-write support, we fall back to copying.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LinkMode {
+    /// Copy the file contents
+    Copy,
+    /// Hardlink the file, falling back to copying
+    Hardlink,
+    /// Symlink to the original file, falling back to copying
+    Symlink,
+    /// Clone the file on copy-on-write filesystems (btrfs, xfs, apfs), falling back to copying,
+    /// since a clone is an independent copy that a hardlink isn't
+    Reflink,
+}
+
+impl LinkMode {
+    /// The default for packages from the cache: Reflinks on unix, which degrade to copies on
+    /// filesystems without copy-on-write, and copies on windows.
+    pub fn packages_default() -> Self {
+        if cfg!(windows) {
+            LinkMode::Copy
+        } else {
+            LinkMode::Reflink
+        }
+    }
+
+    /// The default for `bin/python`: Symlinks on unix like `python -m venv`, copies on windows.
+    pub fn interpreter_default() -> Self {
+        if cfg!(windows) {
+            LinkMode::Copy
+        } else {
+            LinkMode::Symlink
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LinkMode::Copy => "copy",
+            LinkMode::Hardlink => "hardlink",
+            LinkMode::Symlink => "symlink",
+            LinkMode::Reflink => "reflink",
+        }
+    }
+
+    /// The mode to try after this one failed
+    fn fallback(self) -> Option<Self> {
+        match self {
+            LinkMode::Reflink | LinkMode::Hardlink | LinkMode::Symlink => Some(LinkMode::Copy),
+            LinkMode::Copy => None,
+        }
+    }
+
+    /// Link or copy without falling back to other modes
+    pub(crate) fn try_link(self, src: &Path, dst: &Path) -> io::Result<()> {
+        match self {
+            LinkMode::Copy => fs::copy(src, dst).map(|_| ()),
+            LinkMode::Hardlink => fs::hard_link(src, dst),
+            LinkMode::Symlink => {
+                #[cfg(unix)]
+                {
+                    fs_err::os::unix::fs::symlink(src, dst)
+                }
+                #[cfg(windows)]
+                {
+                    fs_err::os::windows::fs::symlink_file(src, dst)
+                }
+            }
+            LinkMode::Reflink => reflink_copy::reflink(src, dst),
+        }
+    }
+
+    /// Materialize `src` at `dst`, falling back to cheaper modes on failure.
+    ///
+    /// Returns the mode that succeeded, so callers processing many files can skip the modes that
+    /// are known to fail on this filesystem.
+    pub fn link_file(self, src: &Path, dst: &Path) -> io::Result<LinkMode> {
+        let mut mode = self;
+        loop {
+            match mode.try_link(src, dst) {
+                Ok(()) => return Ok(mode),
+                Err(err) => match mode.fallback() {
+                    Some(next) => {
+                        debug!(
+                            "Failed to {mode} {} to {} ({err}), falling back to {next}",
+                            src.display(),
+                            dst.display()
+                        );
+                        mode = next;
+                    }
+                    None => return Err(err),
+                },
+            }
+        }
+    }
+}
+
+impl Display for LinkMode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
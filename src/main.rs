@@ -1,51 +1,1606 @@
-use camino::Utf8PathBuf;
-use clap::Parser;
-use gourgeist::{create_venv, get_interpreter_info, parse_python_cli};
+use crate::json_log::JsonLayer;
+use crate::timings::{StepFilter, Timing, TimingsLayer};
+use anstream::{eprintln, println};
+use anstyle::{AnsiColor, Style};
+use camino::{Utf8Path, Utf8PathBuf};
+use clap::{ArgAction, Args, Parser, Subcommand, ValueEnum};
+use gourgeist::{
+    activate_snippet, allow_venv, assumed_interpreter_info, bench, cache_size, check_frozen,
+    check_removable, check_venv_name, clean_cache, create_matrix, create_venv, create_via_daemon,
+    diff_upgrade, disallow_venv, doctor, export_cache, fetch_seeds, find_pythons, format_bytes,
+    gc_cache, gc_cache_periodically, get_interpreter_info, hints, import_cache,
+    install_requirements, is_active_venv, named_venvs_dir, override_interpreter_info, parse_alias,
+    parse_interpreter_info_override, parse_python_cli, parse_pyvenv_cfg_entry, plan_venv,
+    project_config_path, read_interpreter_info_overrides, read_pyvenv_cfg, read_registry,
+    register_venv, remove_venv, repair_venv, resolve_venv, run_batch, run_daemon, run_in_venv,
+    seed_packages, set_system_site_packages, shell_hook, socket_path, spawn_shell, stale_venv,
+    sync_workspace, unfreeze_venv, unregister_venv, upgrade_deps, upgrade_venv, user_config_path,
+    utf8_path, venv_base_interpreter, venv_info, verify_venv, write_envrc, BenchResult,
+    CacheBucket, Config, Context, DaemonRequest, FileModes, Finding, FoundPython,
+    InterpreterCacheMode, LinkMode, MemberStatus, PlannedAction, SbomFormat, SbomOptions,
+    ScriptNaming, Severity, Shell, Universal2, VenvDiff, VenvInfo, VenvOptions, VenvPaths,
+    VenvPlan, WorkspaceMember, PROJECT_CONFIG_FILE,
+};
+use serde_json::json;
 use std::error::Error;
+use std::path::PathBuf;
 use std::process::ExitCode;
-use std::time::Instant;
-use tracing::info;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::Layer;
 use tracing_subscriber::{fmt, EnvFilter};
 
+mod json_log;
+mod timings;
+
+const ERROR: Style = AnsiColor::Red.on_default().bold();
+const WARNING: Style = AnsiColor::Yellow.on_default().bold();
+const SUCCESS: Style = AnsiColor::Green.on_default().bold();
+const HINT: Style = AnsiColor::Cyan.on_default().bold();
+const BOLD: Style = Style::new().bold();
+const DIMMED: Style = Style::new().dimmed();
+
 #[derive(Parser, Debug)]
+#[command(args_conflicts_with_subcommands = true)]
 struct Cli {
-    path: Option<Utf8PathBuf>,
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[command(flatten)]
+    create: CreateArgs,
+    /// How cached interpreter info is invalidated
+    #[clap(long, global = true, value_enum, default_value_t = InterpreterCacheMode::Metadata)]
+    interpreter_cache: InterpreterCacheMode,
+    /// Don't download a standalone python if the requested version isn't installed
+    #[clap(long, global = true)]
+    no_python_downloads: bool,
+    /// The base URL of the pypi JSON API to upgrade the seed packages from, for mirrors
+    #[clap(long, global = true)]
+    index_url: Option<String>,
+    /// The directory for the interpreter info, the wheels and the daemon socket instead of the
+    /// platform cache directory
+    #[clap(long, global = true)]
+    cache_dir: Option<Utf8PathBuf>,
+    /// The directory for the named venvs, the shell hook allowlist and virtualenv's seed wheels
+    /// instead of the platform data directory
+    #[clap(long, global = true)]
+    data_dir: Option<Utf8PathBuf>,
+    /// Query the interpreters and download the wheels without reading or writing the cache, for
+    /// debugging cache problems
+    #[clap(long, global = true)]
+    no_cache: bool,
+    /// Fsync the venvs and the cache entries before reporting success, for machines prone to
+    /// power loss and network filesystems with write-back caching. Slower, especially on spinning
+    /// disks
+    #[clap(long, global = true)]
+    durable: bool,
+    /// Seconds to wait for the interpreter to report its version and prefixes
+    #[clap(long, global = true, default_value_t = 30)]
+    query_timeout: u64,
+    /// Show what gourgeist is doing, repeat for more details (`-vv` for debug output, `-vvv` for
+    /// everything including dependencies). Overrides `RUST_LOG`
+    #[clap(short, long, global = true, action = ArgAction::Count, conflicts_with = "quiet")]
+    verbose: u8,
+    /// Don't log anything, not even errors of steps that don't fail the command
+    #[clap(short, long, global = true)]
+    quiet: bool,
+    /// Whether to color the output. `auto` colors when writing to a terminal and respects
+    /// `NO_COLOR`, `CLICOLOR` and `CLICOLOR_FORCE`
+    #[clap(long, global = true, value_enum, default_value_t = ColorChoice::Auto)]
+    color: ColorChoice,
+    /// The format of the log messages on stderr, `json` writes one JSON object per line
+    #[clap(long, global = true, value_enum, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum LogFormat {
+    Text,
+    Json,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Check that a venv created with `--freeze` is still read-only
+    Check { path: Utf8PathBuf },
+    /// Make a venv created with `--freeze` writable again
+    Unfreeze { path: Utf8PathBuf },
+    /// Print a shell function that activates `.venv` when entering its directory and deactivates
+    /// it when leaving, to be evaluated in your shell's rc file, e.g.
+    /// `eval "$(gourgeist hook bash)"`. Only venvs on the allowlist (see `gourgeist allow`) are
+    /// activated
+    #[command(alias = "hook")]
+    ShellHook { shell: Shell },
+    /// Print the shell code that activates a venv in the current shell, e.g.
+    /// `eval "$(gourgeist activate tools)"` for a venv created with `--name tools`
+    Activate {
+        /// The name or path of the venv
+        #[clap(default_value = ".venv")]
+        path: Utf8PathBuf,
+        /// The shell to activate the venv in, detected from `$SHELL` by default
+        #[clap(long, value_enum)]
+        shell: Option<Shell>,
+    },
+    /// Allow the shell hook to activate this venv
+    Allow {
+        #[clap(default_value = ".venv")]
+        path: Utf8PathBuf,
+        /// Fail instead of warning if the venv's base interpreter changed since its creation
+        #[clap(long)]
+        strict: bool,
+    },
+    /// Remove a venv from the shell hook allowlist
+    Disallow {
+        #[clap(default_value = ".venv")]
+        path: Utf8PathBuf,
+    },
+    /// Show the base interpreter, python version, prompt, link mode and seed packages of a venv
+    Info {
+        #[clap(default_value = ".venv")]
+        path: Utf8PathBuf,
+        /// Print the details as JSON
+        #[clap(long)]
+        json: bool,
+    },
+    /// List the interpreters found in `PATH`, pyenv, the windows registry and the managed
+    /// downloads
+    Pythons {
+        /// Print the interpreters as a JSON array
+        #[clap(long)]
+        json: bool,
+    },
+    /// Change the settings of an existing venv without recreating it
+    ConfigVenv {
+        #[clap(default_value = ".venv")]
+        path: Utf8PathBuf,
+        /// Whether the venv can see the packages installed for the base interpreter
+        #[clap(long, value_enum)]
+        system_site_packages: Option<Switch>,
+    },
+    /// Read and edit the config files, see `gourgeist.toml` and `config.toml`
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+    /// Show or purge the cached interpreter info and wheels
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommand,
+    },
+    /// List the venvs created with `--name`
+    List {
+        /// Print the venvs with their python version and creation options as a JSON array
+        #[clap(long)]
+        json: bool,
+    },
+    /// Delete a venv, including read-only files. Refuses to delete directories that aren't venvs
+    Remove {
+        path: Utf8PathBuf,
+        /// Also remove the venv from the shell hook allowlist. Named venvs are always removed from
+        /// the registry
+        #[clap(long)]
+        untrack: bool,
+        /// Delete the venv even if it's the filesystem root or the home directory
+        #[clap(long)]
+        allow_dangerous_location: bool,
+    },
+    /// Check that the interpreter links, pyvenv.cfg, activators, script shebangs and seed package
+    /// files of a venv are intact, exiting with an error if they aren't
+    Verify {
+        #[clap(default_value = ".venv")]
+        path: Utf8PathBuf,
+    },
+    /// Fix a venv whose base interpreter was removed or upgraded: Recreate dangling interpreter
+    /// links, remove links for other python versions and restore missing activators
+    Repair {
+        #[clap(default_value = ".venv")]
+        path: Utf8PathBuf,
+        /// The new base interpreter
+        #[clap(short, long)]
+        python: Option<Utf8PathBuf>,
+        #[clap(long, value_enum)]
+        link_mode: Option<LinkMode>,
+    },
+    /// Run a command with the venv activated, without activating it in the shell
+    Run {
+        /// The venv to run the command in
+        #[clap(long, default_value = ".venv")]
+        venv: Utf8PathBuf,
+        /// The command and its arguments, e.g. `gourgeist run -- pytest -x`
+        #[clap(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+        command: Vec<String>,
+    },
+    /// Create a venv for each python version in the output directory, named after the version,
+    /// e.g. `.venvs/py311` for python 3.11 or `.venvs/pypy310` for PyPy 3.10
+    Matrix {
+        /// A python version such as `3.11` or the path to an interpreter. Can be repeated
+        #[clap(short, long, required = true)]
+        python: Vec<Utf8PathBuf>,
+        /// The directory to create the venvs in
+        #[clap(long, default_value = ".venvs")]
+        out: Utf8PathBuf,
+        #[clap(long)]
+        bare: bool,
+        /// Recreate the venvs even if they match the interpreters and options
+        #[clap(long)]
+        rebuild: bool,
+    },
+    /// Create or update a `.venv` in every directory with a `pyproject.toml` below the workspace
+    /// root, using the newest installed python that matches the project's `requires-python`
+    Workspace {
+        #[clap(default_value = ".")]
+        root: Utf8PathBuf,
+        #[clap(long)]
+        bare: bool,
+        /// Recreate the venvs even if they match the interpreters and options
+        #[clap(long)]
+        rebuild: bool,
+        /// Print the projects and what happened to their venvs as a JSON array
+        #[clap(long)]
+        json: bool,
+    },
+    /// Measure how long creating bare and seeded venvs takes
+    Bench {
+        #[clap(short, long)]
+        python: Option<Utf8PathBuf>,
+        /// How many venvs to create per configuration, after one warmup run
+        #[clap(short = 'n', long, default_value_t = 10)]
+        iterations: usize,
+        /// Also measure `python -m venv` and `virtualenv` (if installed)
+        #[clap(long)]
+        compare: bool,
+        /// Print the durations of all runs in milliseconds as JSON
+        #[clap(long)]
+        json: bool,
+    },
+    /// Download the seed packages into the cache without creating a venv, e.g. to prepare CI
+    /// images or machines without network access
+    FetchSeeds {
+        /// Also cache the interpreter info of this python
+        #[clap(short, long)]
+        python: Option<Utf8PathBuf>,
+    },
+    /// Check the cache, the interpreters, the link modes of the filesystem and the index for
+    /// problems, exiting with an error if creating venvs would fail
+    Doctor {
+        /// Where the venvs will be created, for checking the link modes
+        #[clap(default_value = ".")]
+        target: Utf8PathBuf,
+        /// Print the findings as a JSON array
+        #[clap(long)]
+        json: bool,
+    },
+    /// Show what `--upgrade` would change in an existing venv: the interpreter links, pyvenv.cfg
+    /// and the activators, and with `--upgrade-deps` the seed package versions
+    Diff {
+        #[clap(default_value = ".venv")]
+        path: Utf8PathBuf,
+        /// The interpreter to upgrade to
+        #[clap(short, long)]
+        python: Option<Utf8PathBuf>,
+        #[clap(long, value_enum)]
+        link_mode: Option<LinkMode>,
+        /// Also show the seed packages that have a newer version on the index
+        #[clap(long)]
+        upgrade_deps: bool,
+        /// Print the changes as JSON
+        #[clap(long)]
+        json: bool,
+    },
+    /// Start your shell (`$SHELL`) with the venv activated, exit the shell to leave the venv
+    Shell {
+        #[clap(default_value = ".venv")]
+        path: Utf8PathBuf,
+    },
+    /// Keep the interpreter info warm in a long-lived process that creates venvs on request.
+    /// While it's running, creating venvs goes through the daemon unless `--no-daemon` is passed
+    Daemon,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Switch {
+    On,
+    Off,
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigCommand {
+    /// Print the value of a key, by default the one in effect for the current directory
+    Get {
+        key: String,
+        #[command(flatten)]
+        scope: ConfigScope,
+    },
+    /// Set a key, by default in the user config
+    Set {
+        key: String,
+        value: String,
+        #[command(flatten)]
+        scope: ConfigScope,
+    },
+    /// Remove a key, by default from the user config
+    Unset {
+        key: String,
+        #[command(flatten)]
+        scope: ConfigScope,
+    },
+    /// Print the keys that are set, by default the ones in effect for the current directory
+    List {
+        #[command(flatten)]
+        scope: ConfigScope,
+    },
+}
+
+#[derive(Args, Debug)]
+struct ConfigScope {
+    /// Use the `gourgeist.toml` in the current directory or the closest parent that has one
+    #[clap(long, conflicts_with = "global")]
+    project: bool,
+    /// Use the user config, `gourgeist/config.toml` in the platform config directory
+    #[clap(long)]
+    global: bool,
+}
+
+#[derive(Subcommand, Debug)]
+enum CacheCommand {
+    /// Print the cache directory
+    Dir,
+    /// Print the size of the cache, per kind of entry and in total
+    Size,
+    /// Remove the info of interpreters that don't exist anymore and wheels that weren't used for a
+    /// while
+    Gc {
+        /// Remove the wheels that weren't used for this many days
+        #[clap(long, default_value_t = 30)]
+        max_age_days: u64,
+    },
+    /// Write the interpreter info and wheels into a `.tar.gz` (or `.tar`) archive, e.g. for CI cache
+    /// steps or to move the cache to another machine
+    Export { archive: Utf8PathBuf },
+    /// Add the entries of an archive from `gourgeist cache export` to the cache
+    Import { archive: Utf8PathBuf },
+    /// Remove cache entries, all of them unless `--wheels`, `--interpreters` or `--store` is passed
+    Clean {
+        /// Remove the downloaded seed wheels
+        #[clap(long)]
+        wheels: bool,
+        /// Remove the cached interpreter info
+        #[clap(long)]
+        interpreters: bool,
+        /// Remove the shared package store. Venvs with hardlinks or reflinks into it keep working
+        #[clap(long)]
+        store: bool,
+    },
+}
+
+/// Create one or more virtualenvs, the default command
+#[derive(Args, Debug)]
+struct CreateArgs {
+    /// The virtualenvs to create, `.venv` by default
+    paths: Vec<PathBuf>,
+    /// Create the venv in the central venvs directory and register it under this name, so
+    /// subcommands such as `gourgeist run --venv <name>` can refer to it by name
+    #[clap(long, conflicts_with = "paths")]
+    name: Option<String>,
     #[clap(short, long)]
-    python: Option<Utf8PathBuf>,
+    python: Option<PathBuf>,
+    /// Patch a field of the queried interpreter info, e.g. `base_prefix=/usr`, for vendor pythons
+    /// that report wrong values. Can be repeated
+    #[clap(long, value_parser = parse_interpreter_info_override)]
+    override_interpreter_info: Vec<(String, serde_json::Value)>,
+    /// Like `--override-interpreter-info`, but read the fields from a JSON object in this file
+    #[clap(long)]
+    override_interpreter_info_file: Option<Utf8PathBuf>,
+    /// Skip running the interpreter and assume it's a CPython of this version (e.g. `3.11.7`)
+    /// with the standard layout, with its prefix being the parent of its `bin` directory. Use
+    /// `--override-interpreter-info` for the fields that differ
+    #[clap(long)]
+    assume_python_version: Option<String>,
     #[clap(long)]
     bare: bool,
+    /// How to get `bin/python` and the seed packages into the venv. Defaults to symlinking python
+    /// and reflinking packages on unix and to copying on windows
+    #[clap(long, value_enum)]
+    link_mode: Option<LinkMode>,
+    /// Keep one copy of each seed package version in a store in the cache and link it into the
+    /// venvs with `--link-mode`, so many venvs cost almost no extra disk
+    #[clap(long, conflicts_with = "bare")]
+    store: bool,
+    /// When creating multiple virtualenvs, stop at the first failure instead of reporting all
+    /// failures at the end
+    #[clap(long)]
+    fail_fast: bool,
+    /// Prefix for the console scripts of the seed packages, e.g. `g-` for `g-pip`
+    #[clap(long, default_value = "")]
+    script_prefix: String,
+    /// Suffix for the console scripts of the seed packages, e.g. `-g` for `pip-g`
+    #[clap(long, default_value = "")]
+    script_suffix: String,
+    /// Don't generate console scripts for this seed package, can be repeated
+    #[clap(long)]
+    no_scripts_for: Vec<String>,
+    /// Make all files in the venv read-only after creation, check with `gourgeist check` and
+    /// revert with `gourgeist unfreeze`
+    #[clap(long)]
+    freeze: bool,
+    /// Make the venvs accessible only to their owner (mode 0700)
+    #[clap(long)]
+    private: bool,
+    /// Whether the files keep the modes of the cache and the base interpreter or get 0644 (0755
+    /// for executables and directories)
+    #[clap(long, value_enum, default_value_t = FileModes::Inherit)]
+    file_modes: FileModes,
+    /// Whether the venvs of a macOS universal2 python keep both architectures or get a copy of the
+    /// python with only the architecture of this machine
+    #[clap(long, value_enum, default_value_t = Universal2::Preserve)]
+    universal2: Universal2,
+    /// Replace the target directory even if it is neither empty nor a virtualenv
+    #[clap(long)]
+    force: bool,
+    /// Recreate the venv even if the existing one was created from the same interpreter with the
+    /// same options
+    #[clap(long)]
+    rebuild: bool,
+    /// Point existing venvs to the (patch-upgraded) interpreter, rewriting the interpreter links,
+    /// the activators and `pyvenv.cfg` but keeping the installed packages
+    #[clap(long, conflicts_with_all = ["bare", "rebuild", "force"])]
+    upgrade: bool,
+    /// Replace `pip`, `setuptools` and `wheel` with their latest versions from pypi after creating
+    /// (or reusing) the venvs, without touching other installed packages
+    #[clap(long, conflicts_with = "bare")]
+    upgrade_deps: bool,
+    /// Install the packages of a requirements file with the venv's pip after creating (or reusing)
+    /// the venvs. Can be repeated
+    #[clap(short, long, conflicts_with = "bare")]
+    requirements: Vec<Utf8PathBuf>,
+    /// Fail instead of warning if an existing venv's base interpreter changed since its creation,
+    /// e.g. after a system python upgrade
+    #[clap(long)]
+    strict: bool,
+    /// Make the venvs work after moving them, e.g. between container build stages, by resolving
+    /// `python` and the venv relative to the scripts and activators
+    #[clap(long)]
+    relocatable: bool,
+    /// Make two runs with the same inputs produce bit-identical venvs, with all timestamps set to
+    /// `SOURCE_DATE_EPOCH` (or 1980-01-01). Implies `--relocatable`
+    #[clap(long)]
+    reproducible: bool,
+    /// Write a manifest of the seeded packages (names, versions, wheels, hashes and sources) to
+    /// `sbom.json` in the venv
+    #[clap(long)]
+    sbom: bool,
+    /// Write the seed package manifest to this path instead of into the venv
+    #[clap(long)]
+    sbom_output: Option<Utf8PathBuf>,
+    #[clap(long, value_enum, default_value_t = SbomFormat::Json)]
+    sbom_format: SbomFormat,
+    /// Print the root, interpreter, bin dir, site-packages, python version and seeded packages of
+    /// the venvs as a JSON array on stdout
+    #[clap(long)]
+    json: bool,
+    /// Print how long each step of the creation took, e.g. querying the interpreter and
+    /// installing each seed package. With `--json`, the timings are added to the output
+    #[clap(long)]
+    timings: bool,
+    /// After creating the venvs, remove cache entries that weren't used for this many days, see
+    /// `gourgeist cache gc`. This checks the cache at most once a day
+    #[clap(long)]
+    cache_gc_days: Option<u64>,
+    /// If `--python` is the python of another venv, link to it instead of to its base interpreter,
+    /// e.g. to build on a template venv. The new venv breaks when the other venv is deleted
+    #[clap(long)]
+    allow_venv_python: bool,
+    /// Add a `key=value` entry to `pyvenv.cfg`, e.g. a marker for another tool. Can be repeated
+    #[clap(long, value_parser = parse_pyvenv_cfg_entry)]
+    pyvenv_cfg: Vec<(String, String)>,
+    /// Add a `name=target` symlink to the scripts directory besides `python`, e.g.
+    /// `python-dbg=python3.12d`, with the target relative to the scripts directory. Can be repeated
+    #[clap(long, value_parser = parse_alias)]
+    alias: Vec<(String, String)>,
+    /// The name shown in the shell prompt when the venv is activated, the venv directory name by
+    /// default. `.` uses the name of the current directory
+    #[clap(long)]
+    prompt: Option<String>,
+    /// A directory with activator templates that replace the built-in ones of the same name or
+    /// are added as additional activators
+    #[clap(long)]
+    activator_templates: Option<Utf8PathBuf>,
+    /// Don't write a `.gitignore` that excludes the venvs from git
+    #[clap(long)]
+    no_gitignore: bool,
+    /// Keep the venv paths as given instead of resolving symlinks in them, so the venvs refer to
+    /// themselves by the path the user sees, e.g. through a symlinked home directory
+    #[clap(long)]
+    no_resolve_symlinks: bool,
+    /// Don't mark the venvs as cache directories, so backup tools include them
+    #[clap(long)]
+    no_cachedir_tag: bool,
+    /// Give the venvs access to the packages installed for the base interpreter
+    #[clap(long)]
+    system_site_packages: bool,
+    /// Create the venvs even if the python belongs to a conda environment
+    #[clap(long)]
+    allow_conda: bool,
+    /// Replace the venvs even if they are the filesystem root, the home directory or the prefix of
+    /// the base interpreter
+    #[clap(long)]
+    allow_dangerous_location: bool,
+    /// Add the venvs to the `.envrc` in their parent directory, so direnv activates them
+    #[clap(long)]
+    envrc: bool,
+    /// Create the venvs in this process even if a daemon (`gourgeist daemon`) is running
+    #[clap(long)]
+    no_daemon: bool,
+    /// Only print what would be created, linked, replaced and installed, without touching the
+    /// filesystem. With `--json`, print the plans as a JSON array
+    #[clap(long, conflicts_with_all = ["upgrade", "upgrade_deps", "requirements", "envrc"])]
+    dry_run: bool,
 }
 
-fn run() -> Result<(), gourgeist::Error> {
-    let cli = Cli::parse();
-    let location = cli.path.unwrap_or(Utf8PathBuf::from(".venv"));
-    let python = parse_python_cli(cli.python)?;
-    let data = get_interpreter_info(&python)?;
-    create_venv(&location, &python, &data, cli.bare)?;
+/// Run the command, leaving the context in `configured_ctx` as soon as it's configured so the
+/// error hints can use it.
+fn run(cli: Cli, configured_ctx: &mut Option<Context>) -> Result<ExitCode, gourgeist::Error> {
+    let mut ctx = Context::from_process()?;
+    if let Some(cache_dir) = cli.cache_dir {
+        let cache_dir = ctx.absolute(&cache_dir);
+        ctx = ctx.with_cache_dir(cache_dir);
+    }
+    if let Some(data_dir) = cli.data_dir {
+        let data_dir = ctx.absolute(&data_dir);
+        ctx = ctx.with_data_dir(data_dir);
+    }
+    ctx.interpreter_cache = cli.interpreter_cache;
+    ctx.no_cache = cli.no_cache;
+    ctx.durable = cli.durable;
+    ctx.query_timeout = Duration::from_secs(cli.query_timeout);
+    let ctx = configured_ctx.insert(ctx);
+    // The config files set the defaults for the options. `gourgeist config` reads only the files
+    // it needs, so one broken file doesn't block editing the other
+    let config = if matches!(cli.command, Some(Command::Config { .. })) {
+        Config::default()
+    } else {
+        Config::load(ctx)?
+    };
+    ctx.python_downloads = !cli.no_python_downloads && config.python_downloads.unwrap_or(true);
+    if let Some(index_url) = cli.index_url.or(config.index_url.clone()) {
+        ctx.index_url = index_url;
+    }
+    let ctx: &Context = ctx;
+    let mut command = cli.command;
+    if let Some(command) = &mut command {
+        resolve_venv_names(ctx, command)?;
+    }
+    match command {
+        None => create(ctx, cli.create, &config, cli.quiet),
+        Some(Command::Check { path }) => check(&ctx.absolute(&path)),
+        Some(Command::Unfreeze { path }) => {
+            unfreeze_venv(&ctx.absolute(&path))?;
+            Ok(ExitCode::SUCCESS)
+        }
+        Some(Command::Activate { path, shell }) => {
+            let shell = match shell.or_else(|| Shell::detect(ctx)) {
+                Some(shell) => shell,
+                None => {
+                    return Err(gourgeist::Error::UnknownShell(
+                        ctx.var("SHELL").unwrap_or("unset").to_string(),
+                    ))
+                }
+            };
+            print!("{}", activate_snippet(ctx, &path, shell)?);
+            Ok(ExitCode::SUCCESS)
+        }
+        Some(Command::ShellHook { shell }) => {
+            print!("{}", shell_hook(ctx, shell)?);
+            Ok(ExitCode::SUCCESS)
+        }
+        Some(Command::Allow { path, strict }) => {
+            check_stale(ctx, &path, strict, cli.quiet)?;
+            let venv = allow_venv(ctx, &path)?;
+            println!("Allowed {venv}");
+            Ok(ExitCode::SUCCESS)
+        }
+        Some(Command::Disallow { path }) => {
+            if disallow_venv(ctx, &path)? {
+                println!("Removed {path} from the allowlist");
+            } else {
+                println!("{path} was not on the allowlist");
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        Some(Command::Info { path, json }) => {
+            let info = venv_info(ctx, &path)?;
+            if json {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&info).expect("JSON values are serializable")
+                );
+            } else {
+                print_venv_info(&info);
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        Some(Command::Pythons { json }) => {
+            let pythons = find_pythons(ctx)?;
+            if json {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&pythons).expect("JSON values are serializable")
+                );
+            } else {
+                print_pythons(&pythons);
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        Some(Command::ConfigVenv {
+            path,
+            system_site_packages,
+        }) => {
+            if let Some(switch) = system_site_packages {
+                set_system_site_packages(ctx, &path, matches!(switch, Switch::On))?;
+            }
+            let cfg = read_pyvenv_cfg(&ctx.absolute(&path))?;
+            println!(
+                "include-system-site-packages = {}",
+                cfg.get("include-system-site-packages").unwrap_or("false")
+            );
+            Ok(ExitCode::SUCCESS)
+        }
+        Some(Command::Config { command }) => edit_config(ctx, command),
+        Some(Command::Cache { command }) => {
+            cache(ctx, command)?;
+            Ok(ExitCode::SUCCESS)
+        }
+        Some(Command::List { json }) => {
+            let entries = read_registry(ctx)?;
+            if json {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&entries).expect("JSON values are serializable")
+                );
+            } else {
+                for entry in entries {
+                    println!(
+                        "{BOLD}{}{BOLD:#} {} (python {})",
+                        entry.name, entry.path, entry.python_version
+                    );
+                }
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        Some(Command::Remove {
+            path,
+            untrack,
+            allow_dangerous_location,
+        }) => {
+            // Only untrack a venv we're going to delete, but before deleting, so symlinks in the
+            // path still resolve to the allowlisted path
+            check_removable(ctx, &path, allow_dangerous_location)?;
+            if untrack && disallow_venv(ctx, &path)? {
+                println!("Removed {path} from the allowlist");
+            }
+            let venv = remove_venv(ctx, &path, allow_dangerous_location)?;
+            println!("Removed {venv}");
+            for name in unregister_venv(ctx, &venv)? {
+                println!("Removed {name} from the registry");
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        Some(Command::Verify { path }) => {
+            let problems = verify_venv(ctx, &path)?;
+            if problems.is_empty() {
+                println!("{SUCCESS}{path} is intact{SUCCESS:#}");
+                Ok(ExitCode::SUCCESS)
+            } else {
+                println!("{ERROR}{path} has {} problems:{ERROR:#}", problems.len());
+                for problem in problems {
+                    println!("  {problem}");
+                }
+                Ok(ExitCode::FAILURE)
+            }
+        }
+        Some(Command::Repair {
+            path,
+            python,
+            link_mode,
+        }) => {
+            let python = base_python(ctx, parse_python_cli(ctx, python)?)?;
+            let info = get_interpreter_info(ctx, &python)?;
+            let options = VenvOptions {
+                link_mode,
+                ..VenvOptions::default()
+            };
+            let repairs = repair_venv(ctx, &path, &python, &info, &options)?;
+            if repairs.is_empty() {
+                println!("{SUCCESS}{path} is intact{SUCCESS:#}");
+            }
+            for repair in repairs {
+                println!("{repair}");
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        Some(Command::Run { venv, command }) => {
+            let status = run_in_venv(ctx, &venv, &command[0], &command[1..])?;
+            Ok(exit_code(status))
+        }
+        Some(Command::Matrix {
+            python,
+            out,
+            bare,
+            rebuild,
+        }) => {
+            let options = VenvOptions {
+                bare,
+                rebuild,
+                ..VenvOptions::default()
+            };
+            for paths in create_matrix(ctx, &python, &out, &options)? {
+                println!("Created {}", paths.root);
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        Some(Command::Workspace {
+            root,
+            bare,
+            rebuild,
+            json,
+        }) => {
+            let options = VenvOptions {
+                bare,
+                rebuild,
+                ..VenvOptions::default()
+            };
+            let members = sync_workspace(ctx, &root, &options)?;
+            if json {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&members).expect("JSON values are serializable")
+                );
+            } else {
+                print_workspace(&members);
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        Some(Command::Bench {
+            python,
+            iterations,
+            compare,
+            json,
+        }) => {
+            let python = python.or(config.python.map(Utf8PathBuf::from));
+            let python = base_python(ctx, parse_python_cli(ctx, python)?)?;
+            let info = get_interpreter_info(ctx, &python)?;
+            eprintln!(
+                "Benchmarking with python {} at {python}",
+                info.python_version
+            );
+            let results = bench(ctx, &python, &info, iterations, compare)?;
+            if json {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&results).expect("JSON values are serializable")
+                );
+            } else {
+                print_bench(&results);
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        Some(Command::FetchSeeds { python }) => {
+            if let Some(python) = python.or(config.python.map(Utf8PathBuf::from)) {
+                let python = base_python(ctx, parse_python_cli(ctx, Some(python))?)?;
+                let info = get_interpreter_info(ctx, &python)?;
+                println!("Cached python {} at {python}", info.python_version);
+            }
+            for wheel in fetch_seeds(ctx)? {
+                println!("Cached {wheel}");
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        Some(Command::Diff {
+            path,
+            python,
+            link_mode,
+            upgrade_deps,
+            json,
+        }) => {
+            let python = python.or(config.python.map(Utf8PathBuf::from));
+            let python = base_python(ctx, parse_python_cli(ctx, python)?)?;
+            let info = get_interpreter_info(ctx, &python)?;
+            let options = VenvOptions {
+                link_mode: link_mode.or(config.link_mode),
+                ..VenvOptions::default()
+            };
+            let diff = diff_upgrade(ctx, &path, &python, &info, &options, upgrade_deps)?;
+            if json {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&diff).expect("JSON values are serializable")
+                );
+            } else {
+                print_diff(&diff);
+            }
+            Ok(ExitCode::SUCCESS)
+        }
+        Some(Command::Doctor { target, json }) => {
+            let findings = doctor(ctx, &target)?;
+            if json {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&findings).expect("JSON values are serializable")
+                );
+            } else {
+                print_findings(&findings);
+            }
+            if findings
+                .iter()
+                .any(|finding| finding.severity == Severity::Error)
+            {
+                Ok(ExitCode::FAILURE)
+            } else {
+                Ok(ExitCode::SUCCESS)
+            }
+        }
+        Some(Command::Shell { path }) => {
+            eprintln!("Starting a shell in {path}, exit it to leave the venv");
+            let status = spawn_shell(ctx, &path)?;
+            Ok(exit_code(status))
+        }
+        Some(Command::Daemon) => {
+            eprintln!("Starting the daemon on {}", socket_path(ctx)?);
+            run_daemon(ctx)?;
+            Ok(ExitCode::SUCCESS)
+        }
+    }
+}
+
+fn create(
+    ctx: &Context,
+    cli: CreateArgs,
+    config: &Config,
+    quiet: bool,
+) -> Result<ExitCode, gourgeist::Error> {
+    let locations = if let Some(name) = &cli.name {
+        check_venv_name(name)?;
+        vec![named_venvs_dir(ctx)?.join(name)]
+    } else if cli.paths.is_empty() {
+        vec![Utf8PathBuf::from(".venv")]
+    } else {
+        // Directories with non-UTF-8 names are reached through an alias
+        cli.paths
+            .iter()
+            .map(|path| utf8_path(ctx, path))
+            .collect::<Result<_, _>>()?
+    };
+    let python = match cli.python {
+        Some(python) => Some(utf8_path(ctx, &python)?),
+        None => config.python.as_ref().map(Utf8PathBuf::from),
+    };
+    let python = parse_python_cli(ctx, python)?;
+    let python = if cli.allow_venv_python {
+        python
+    } else {
+        base_python(ctx, python)?
+    };
+    let mut overrides = match &cli.override_interpreter_info_file {
+        Some(path) => read_interpreter_info_overrides(&ctx.absolute(path))?,
+        None => Vec::new(),
+    };
+    overrides.extend(cli.override_interpreter_info);
+    if !quiet {
+        for (key, value) in &overrides {
+            eprintln!(
+                "{WARNING}⚠️ Overriding the interpreter info field {key} with {value}{WARNING:#}"
+            );
+        }
+    }
+    let options = VenvOptions {
+        bare: cli.bare,
+        link_mode: cli.link_mode.or(config.link_mode),
+        store: cli.store,
+        script_naming: ScriptNaming {
+            prefix: cli.script_prefix,
+            suffix: cli.script_suffix,
+            skip: cli.no_scripts_for,
+        },
+        freeze: cli.freeze,
+        private: cli.private,
+        file_modes: cli.file_modes,
+        universal2: cli.universal2,
+        force: cli.force,
+        rebuild: cli.rebuild,
+        relocatable: cli.relocatable,
+        reproducible: cli.reproducible,
+        allow_conda: cli.allow_conda,
+        allow_dangerous_location: cli.allow_dangerous_location,
+        system_site_packages: cli.system_site_packages,
+        extra_pyvenv_cfg: cli.pyvenv_cfg,
+        aliases: cli.alias,
+        command: Some(command_line(&python)),
+        no_cachedir_tag: cli.no_cachedir_tag,
+        no_gitignore: cli.no_gitignore,
+        no_resolve_symlinks: cli.no_resolve_symlinks,
+        activator_templates: cli.activator_templates,
+        prompt: cli.prompt.or(config.prompt.clone()).map(|prompt| {
+            if prompt == "." {
+                ctx.cwd.file_name().unwrap_or_default().to_string()
+            } else {
+                prompt
+            }
+        }),
+        sbom: (cli.sbom || cli.sbom_output.is_some()).then_some(SbomOptions {
+            output: cli.sbom_output,
+            format: cli.sbom_format,
+        }),
+    };
+    if cli.dry_run {
+        let info = match &cli.assume_python_version {
+            Some(version) => assumed_interpreter_info(ctx, &python, version)?,
+            None => get_interpreter_info(ctx, &python)?,
+        };
+        let info = override_interpreter_info(info, &overrides)?;
+        let plans = run_batch(&locations, cli.fail_fast, |location| {
+            let plan = plan_venv(ctx, location, &python, &info, &options)?;
+            if !cli.json {
+                print_plan(&plan);
+            }
+            Ok(serde_json::to_value(&plan).expect("JSON values are serializable"))
+        })?;
+        if cli.json {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&plans).expect("JSON values are serializable")
+            );
+        }
+        return Ok(ExitCode::SUCCESS);
+    }
+    // The daemon only creates venvs, upgrades run in-process
+    // The timings are collected in this process
+    let use_daemon = !cli.no_daemon
+        && !cli.upgrade
+        && !cli.upgrade_deps
+        && cli.requirements.is_empty()
+        && !cli.timings
+        // The daemon keeps the interpreter info in memory
+        && !ctx.no_cache
+        // The daemon would query the interpreter
+        && cli.assume_python_version.is_none();
+    // Only query the interpreter if we have to create a venv ourselves
+    let mut data = None;
+    let created = run_batch(&locations, cli.fail_fast, |location| {
+        let start = Instant::now();
+        // With `--upgrade`, the venv is expected to be stale, with `--force` and `--rebuild` it
+        // gets replaced anyway
+        if !cli.upgrade
+            && !cli.force
+            && !cli.rebuild
+            && ctx.absolute(location).join("pyvenv.cfg").is_file()
+        {
+            check_stale(ctx, location, cli.strict, quiet)?;
+        }
+        // The warning about replacing the active venv would end up in the daemon log
+        if use_daemon && !(cli.force && is_active_venv(ctx, location)) {
+            let request = DaemonRequest {
+                context: ctx.clone(),
+                location: location.to_path_buf(),
+                base_python: python.clone(),
+                interpreter_overrides: overrides.clone(),
+                options: options.clone(),
+            };
+            if let Some(result) = create_via_daemon(&request) {
+                return venv_json(&result?);
+            }
+        }
+        if data.is_none() {
+            let info = match &cli.assume_python_version {
+                Some(version) => assumed_interpreter_info(ctx, &python, version)?,
+                None => get_interpreter_info(ctx, &python)?,
+            };
+            data = Some(override_interpreter_info(info, &overrides)?);
+        }
+        let data = data.as_ref().unwrap();
+        let paths = if cli.upgrade {
+            upgrade_venv(ctx, location, &python, data, &options)?
+        } else {
+            create_venv(ctx, location, &python, data, &options)?
+        };
+        if cli.upgrade_deps {
+            for (name, old_version, new_version) in
+                upgrade_deps(ctx, location, &options.script_naming)?
+            {
+                // Keep stdout clean for the JSON
+                let message =
+                    format!("Upgraded {name} from {old_version} to {new_version} in {location}");
+                if cli.json {
+                    eprintln!("{message}");
+                } else {
+                    println!("{message}");
+                }
+            }
+        }
+        if !cli.requirements.is_empty() {
+            install_requirements(ctx, location, &cli.requirements)?;
+        }
+        let mut json = venv_json(&paths)?;
+        if cli.timings {
+            // Steps before the first venv, such as the interpreter query, count towards it
+            let mut timings = timings::take();
+            timings.push(Timing {
+                step: "total".to_string(),
+                duration_ms: start.elapsed().as_millis() as u64,
+                ..Timing::default()
+            });
+            eprintln!("{BOLD}Timings for {location}:{BOLD:#}");
+            for timing in &timings {
+                eprintln!("  {timing}");
+            }
+            json["timings"] = json!(timings);
+        }
+        Ok(json)
+    })?;
+    if let Some(name) = &cli.name {
+        let location = locations[0].canonicalize_utf8()?;
+        let cfg = read_pyvenv_cfg(&location)?;
+        let python_version = cfg.get("version_info").unwrap_or_default();
+        register_venv(ctx, name, &location, &python, python_version, &options)?;
+        if !quiet {
+            // Keep stdout clean for the JSON
+            eprintln!("Registered {location} as {name}");
+        }
+    }
+    if cli.envrc {
+        for location in &locations {
+            let envrc = write_envrc(ctx, location)?;
+            if let (Some(envrc), false) = (envrc, quiet) {
+                // Keep stdout clean for the JSON
+                eprintln!("Added {location} to {envrc}, run `direnv allow` to activate it");
+            }
+        }
+    }
+    if cli.json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&created).expect("JSON values are serializable")
+        );
+    }
+    if let Some(max_age_days) = cli.cache_gc_days {
+        // The venvs are already created, a failing cleanup shouldn't fail the command
+        match gc_cache_periodically(ctx, days(max_age_days)) {
+            Ok(Some(gc)) => info!("Removed {} unused cache entries", gc.removed),
+            Ok(None) => {}
+            Err(err) => warn!("Failed to clean up the cache: {err}"),
+        }
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+fn print_diff(diff: &VenvDiff) {
+    if diff.is_empty() {
+        println!(
+            "{SUCCESS}Upgrading {} changes nothing{SUCCESS:#}",
+            diff.location
+        );
+        return;
+    }
+    println!("{BOLD}Upgrading {} changes:{BOLD:#}", diff.location);
+    for link in &diff.links {
+        println!(
+            "{} {} -> {}",
+            link.path,
+            link.old.as_deref().unwrap_or("(missing)"),
+            link.new
+        );
+    }
+    for file in &diff.files {
+        for line in file.diff.lines() {
+            if line.starts_with("+++") || line.starts_with("---") {
+                println!("{BOLD}{line}{BOLD:#}");
+            } else if line.starts_with('+') {
+                println!("{SUCCESS}{line}{SUCCESS:#}");
+            } else if line.starts_with('-') {
+                println!("{ERROR}{line}{ERROR:#}");
+            } else {
+                println!("{line}");
+            }
+        }
+    }
+    for seed in &diff.seeds {
+        println!(
+            "{} {} -> {}",
+            seed.name,
+            seed.old_version.as_deref().unwrap_or("(missing)"),
+            seed.new_version
+        );
+    }
+}
+
+fn print_plan(plan: &VenvPlan) {
+    let action = match plan.action {
+        PlannedAction::ReplaceDirectory => format!("{ERROR}{}{ERROR:#}", plan.action),
+        PlannedAction::Replace => format!("{WARNING}{}{WARNING:#}", plan.action),
+        _ => plan.action.to_string(),
+    };
+    println!(
+        "{BOLD}Would {action} {}{BOLD:#} with python {} ({})",
+        plan.location, plan.python_version, plan.base_python
+    );
+    for link in &plan.links {
+        println!(
+            "  {:<8} {} -> {}",
+            link.link_mode.as_str(),
+            link.path,
+            link.target
+        );
+    }
+    for file in &plan.files {
+        println!("  {:<8} {file}", "write");
+    }
+    for seed in &plan.seeds {
+        let verb = if seed.download { "download" } else { "install" };
+        println!(
+            "  {verb:<8} {} {} {DIMMED}from {}{DIMMED:#}",
+            seed.name, seed.version, seed.source
+        );
+    }
+}
+
+/// Pass on the exit code of a child process, failing if it was killed by a signal.
+fn exit_code(status: std::process::ExitStatus) -> ExitCode {
+    match status.code() {
+        Some(0) => ExitCode::SUCCESS,
+        // Exit codes outside of 1..=255 (on windows) are reported as a generic failure
+        Some(code) => u8::try_from(code)
+            .ok()
+            .filter(|code| *code != 0)
+            .map_or(ExitCode::FAILURE, ExitCode::from),
+        None => ExitCode::FAILURE,
+    }
+}
+
+fn print_pythons(pythons: &[FoundPython]) {
+    if pythons.is_empty() {
+        println!("No python interpreters found");
+        return;
+    }
+    for python in pythons {
+        println!(
+            "{:<10} {:<8} {:<8} {:<8} {}",
+            python.python_version,
+            python.implementation,
+            python.machine,
+            python.source,
+            python.path
+        );
+    }
+}
+
+fn print_venv_info(info: &VenvInfo) {
+    println!("Venv:             {}", info.root);
+    println!(
+        "Python:           {}",
+        info.python_version.as_deref().unwrap_or("unknown")
+    );
+    match &info.base_interpreter {
+        Some(base_interpreter) if info.base_interpreter_exists => {
+            println!("Base interpreter: {base_interpreter}");
+        }
+        Some(base_interpreter) => {
+            println!("Base interpreter: {base_interpreter} {ERROR}(missing!){ERROR:#}");
+        }
+        None => println!("Base interpreter: unknown"),
+    }
+    println!("Prompt:           {}", info.prompt);
+    match info.link_mode {
+        Some(link_mode) => println!("Link mode:        {link_mode}"),
+        None => println!("Link mode:        unknown (bin/python is missing)"),
+    }
+    if let Some(version) = &info.gourgeist_version {
+        println!("Created by:       gourgeist {version}");
+    }
+    let seed_packages: Vec<_> = info
+        .seed_packages
+        .iter()
+        .map(|package| format!("{} {}", package.name, package.version))
+        .collect();
+    if seed_packages.is_empty() {
+        println!("Seed packages:    none");
+    } else {
+        println!("Seed packages:    {}", seed_packages.join(", "));
+    }
+}
+
+/// Replace the names of venvs created with `--name` by their paths.
+fn resolve_venv_names(ctx: &Context, command: &mut Command) -> Result<(), gourgeist::Error> {
+    let path = match command {
+        Command::Check { path }
+        | Command::Unfreeze { path }
+        | Command::Allow { path, .. }
+        | Command::Disallow { path }
+        | Command::Info { path, .. }
+        | Command::ConfigVenv { path, .. }
+        | Command::Remove { path, .. }
+        | Command::Verify { path }
+        | Command::Repair { path, .. }
+        | Command::Shell { path }
+        | Command::Activate { path, .. } => path,
+        Command::Run { venv, .. } => venv,
+        _ => return Ok(()),
+    };
+    *path = resolve_venv(ctx, path)?;
+    Ok(())
+}
+
+fn print_workspace(members: &[WorkspaceMember]) {
+    if members.is_empty() {
+        println!("No pyproject.toml found");
+    }
+    for member in members {
+        match &member.status {
+            MemberStatus::Created { python_version, .. } => println!(
+                "{SUCCESS}{:<8}{SUCCESS:#} {} (python {python_version})",
+                member.status, member.path
+            ),
+            MemberStatus::Reused { python_version, .. } => println!(
+                "{:<8} {} (python {python_version})",
+                member.status, member.path
+            ),
+            MemberStatus::Skipped { reason } => println!(
+                "{WARNING}{:<8}{WARNING:#} {}: {reason}",
+                member.status, member.path
+            ),
+        }
+    }
+}
+
+fn print_findings(findings: &[Finding]) {
+    for finding in findings {
+        let style = match finding.severity {
+            Severity::Ok => SUCCESS,
+            Severity::Warning => WARNING,
+            Severity::Error => ERROR,
+        };
+        println!(
+            "{style}{:>7}{style:#} {BOLD}{:<11}{BOLD:#} {}",
+            finding.severity, finding.check, finding.message
+        );
+        if let Some(hint) = &finding.hint {
+            println!("{:>7} {:<11} {HINT}hint:{HINT:#} {hint}", "", "");
+        }
+    }
+}
+
+fn print_bench(results: &[BenchResult]) {
+    let width = results
+        .iter()
+        .map(|result| result.name.len())
+        .max()
+        .unwrap_or_default();
+    println!(
+        "{BOLD}{:<width$} {:>9} {:>9} {:>9} {:>9}{BOLD:#}",
+        "", "mean", "p50", "p90", "max"
+    );
+    let millis = |duration: Duration| format!("{:.1}ms", duration.as_secs_f64() * 1000.0);
+    for result in results {
+        println!(
+            "{:<width$} {:>9} {:>9} {:>9} {:>9}",
+            result.name,
+            millis(result.mean()),
+            millis(result.percentile(50.0)),
+            millis(result.percentile(90.0)),
+            millis(result.percentile(100.0)),
+        );
+    }
+}
 
+/// This invocation with the resolved interpreter as `--python`, for recreating the venv later.
+fn command_line(python: &Utf8Path) -> String {
+    let binary = std::env::current_exe()
+        .ok()
+        .and_then(|path| Utf8PathBuf::try_from(path).ok())
+        .map_or_else(
+            || env!("CARGO_PKG_NAME").to_string(),
+            |path| path.to_string(),
+        );
+    let mut command = vec![binary, "--python".to_string(), python.to_string()];
+    // Non-UTF-8 arguments are replaced by their alias in the venv files anyway
+    let mut args = std::env::args_os()
+        .skip(1)
+        .map(|arg| arg.to_string_lossy().into_owned());
+    while let Some(arg) = args.next() {
+        if arg == "-p" || arg == "--python" {
+            args.next();
+        } else if !(arg.starts_with("--python=") || arg.starts_with("-p") && arg != "-p") {
+            command.push(arg);
+        }
+    }
+    command
+        .iter()
+        .map(|arg| shell_quote(arg))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Quote `arg` for a POSIX shell if it contains anything but safe characters.
+fn shell_quote(arg: &str) -> String {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c);
+    if !arg.is_empty() && arg.chars().all(safe) {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', r"'\''"))
+    }
+}
+
+/// The `--json` output for a venv
+fn venv_json(paths: &VenvPaths) -> Result<serde_json::Value, gourgeist::Error> {
+    let cfg = read_pyvenv_cfg(&paths.root)?;
+    let seeded_packages: Vec<_> = seed_packages(paths)?
+        .into_iter()
+        .map(|(name, version)| json!({"name": name, "version": version}))
+        .collect();
+    Ok(json!({
+        "root": paths.root,
+        "interpreter": paths.interpreter,
+        "bin": paths.bin,
+        "site_packages": paths.site_packages,
+        "python_version": cfg.get("version_info"),
+        "seeded_packages": seeded_packages,
+    }))
+}
+
+/// Use the base interpreter instead of the python of another venv, e.g. of the active venv
+fn base_python(ctx: &Context, python: Utf8PathBuf) -> Result<Utf8PathBuf, gourgeist::Error> {
+    match venv_base_interpreter(ctx, &python)? {
+        Some(base) => {
+            info!("{python} is in a venv, using its base interpreter {base}");
+            Ok(base)
+        }
+        None => Ok(python),
+    }
+}
+
+/// Warn (unless `quiet`) if the venv's base interpreter changed since its creation, or fail
+/// with `strict`.
+fn check_stale(
+    ctx: &Context,
+    location: &Utf8Path,
+    strict: bool,
+    quiet: bool,
+) -> Result<(), gourgeist::Error> {
+    let Some(reason) = stale_venv(ctx, location)? else {
+        return Ok(());
+    };
+    if strict {
+        return Err(gourgeist::Error::StaleVenv {
+            location: location.to_path_buf(),
+            reason,
+        });
+    }
+    if !quiet {
+        eprintln!("{WARNING}⚠️ {location} is stale, {reason}{WARNING:#}");
+    }
     Ok(())
 }
 
+fn edit_config(ctx: &Context, command: ConfigCommand) -> Result<ExitCode, gourgeist::Error> {
+    // Reading without a scope uses the merged config, writing uses the user config
+    let path = |scope: &ConfigScope, write: bool| -> std::io::Result<Option<Utf8PathBuf>> {
+        Ok(if scope.project {
+            Some(project_config_path(ctx).unwrap_or_else(|| ctx.cwd.join(PROJECT_CONFIG_FILE)))
+        } else if scope.global || write {
+            Some(user_config_path(ctx)?)
+        } else {
+            None
+        })
+    };
+    let read = |path: &Option<Utf8PathBuf>| match path {
+        Some(path) => Config::read(path),
+        None => Config::load(ctx),
+    };
+    let invalid =
+        |path: Utf8PathBuf, message: String| gourgeist::Error::InvalidConfig { path, message };
+    match command {
+        ConfigCommand::Get { key, scope } => {
+            let path = path(&scope, false)?;
+            let config = read(&path)?;
+            let value = match config.get(&key) {
+                Ok(value) => value,
+                Err(message) => {
+                    let path = match path {
+                        Some(path) => path,
+                        None => user_config_path(ctx)?,
+                    };
+                    return Err(invalid(path, message));
+                }
+            };
+            match value {
+                Some(value) => println!("{value}"),
+                // Like `git config`, a missing key is an error without a message
+                None => return Ok(ExitCode::FAILURE),
+            }
+        }
+        ConfigCommand::Set { key, value, scope } => {
+            let path = path(&scope, true)?.expect("writing always has a path");
+            let mut config = Config::read(&path)?;
+            config
+                .set(&key, &value)
+                .map_err(|message| invalid(path.clone(), message))?;
+            config.write(&path)?;
+            info!("Set `{key}` in {path}");
+        }
+        ConfigCommand::Unset { key, scope } => {
+            let path = path(&scope, true)?.expect("writing always has a path");
+            let mut config = Config::read(&path)?;
+            config
+                .unset(&key)
+                .map_err(|message| invalid(path.clone(), message))?;
+            config.write(&path)?;
+            info!("Removed `{key}` from {path}");
+        }
+        ConfigCommand::List { scope } => {
+            for (key, value) in read(&path(&scope, false)?)?.entries() {
+                println!("{key} = {value}");
+            }
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn cache(ctx: &Context, command: CacheCommand) -> Result<(), gourgeist::Error> {
+    match command {
+        CacheCommand::Dir => println!("{}", ctx.cache_dir()?),
+        CacheCommand::Size => {
+            let mut total = 0;
+            for bucket in CacheBucket::ALL {
+                let size = cache_size(ctx, bucket)?;
+                println!("{bucket:<16} {}", format_bytes(size));
+                total += size;
+            }
+            println!("{:<16} {}", "total", format_bytes(total));
+        }
+        CacheCommand::Gc { max_age_days } => {
+            let gc = gc_cache(ctx, days(max_age_days))?;
+            println!(
+                "Removed {} cache entries ({})",
+                gc.removed,
+                format_bytes(gc.freed)
+            );
+        }
+        CacheCommand::Export { archive } => {
+            let archive = ctx.absolute(&archive);
+            let entries = export_cache(ctx, &archive)?;
+            println!("Exported {entries} cache entries to {archive}");
+        }
+        CacheCommand::Import { archive } => {
+            let archive = ctx.absolute(&archive);
+            let entries = import_cache(ctx, &archive)?;
+            println!("Imported {entries} cache entries from {archive}");
+        }
+        CacheCommand::Clean {
+            wheels,
+            interpreters,
+            store,
+        } => {
+            let buckets: Vec<CacheBucket> = if wheels || interpreters || store {
+                CacheBucket::ALL
+                    .into_iter()
+                    .filter(|bucket| match bucket {
+                        CacheBucket::Interpreters => interpreters,
+                        CacheBucket::Wheels => wheels,
+                        CacheBucket::Store => store,
+                    })
+                    .collect()
+            } else {
+                CacheBucket::ALL.to_vec()
+            };
+            for bucket in buckets {
+                let freed = clean_cache(ctx, bucket)?;
+                println!("Removed {bucket} ({})", format_bytes(freed));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn days(days: u64) -> Duration {
+    Duration::from_secs(days * 24 * 60 * 60)
+}
+
+fn check(path: &Utf8Path) -> Result<ExitCode, gourgeist::Error> {
+    let writable = check_frozen(path)?;
+    if writable.is_empty() {
+        println!("{SUCCESS}{path} is frozen{SUCCESS:#}");
+        Ok(ExitCode::SUCCESS)
+    } else {
+        println!(
+            "{ERROR}{path} is frozen, but {} paths are writable:{ERROR:#}",
+            writable.len()
+        );
+        for path in writable {
+            println!("  {path}");
+        }
+        Ok(ExitCode::FAILURE)
+    }
+}
+
+/// `-q` and `-v` override `RUST_LOG`, which is used otherwise.
+fn log_filter(verbose: u8, quiet: bool) -> EnvFilter {
+    let directive = match (quiet, verbose) {
+        (true, _) => "off",
+        (false, 0) => return EnvFilter::from_default_env(),
+        (false, 1) => "gourgeist=info",
+        (false, 2) => "gourgeist=debug",
+        (false, _) => "trace",
+    };
+    EnvFilter::new(directive)
+}
+
 fn main() -> ExitCode {
+    let cli = Cli::parse();
+    // `anstream::ColorChoice::Auto` checks for a terminal and the color env vars
+    anstream::ColorChoice::write_global(match cli.color {
+        ColorChoice::Auto => anstream::ColorChoice::Auto,
+        ColorChoice::Always => anstream::ColorChoice::Always,
+        ColorChoice::Never => anstream::ColorChoice::Never,
+    });
+    let log_color =
+        anstream::AutoStream::choice(&std::io::stderr()) != anstream::ColorChoice::Never;
+    let log_layer = match cli.log_format {
+        // Keep stdout for the output, e.g. `--json`
+        LogFormat::Text => fmt::layer()
+            .with_writer(std::io::stderr)
+            .with_ansi(log_color)
+            .boxed(),
+        LogFormat::Json => JsonLayer.boxed(),
+    };
     tracing_subscriber::registry()
-        .with(fmt::layer())
-        .with(EnvFilter::from_default_env())
+        .with(log_layer.with_filter(log_filter(cli.verbose, cli.quiet)))
+        // The timings are recorded regardless of the log level
+        .with(
+            cli.create
+                .timings
+                .then(|| TimingsLayer.with_filter(StepFilter)),
+        )
         .init();
 
     let start = Instant::now();
-    let result = run();
-    info!("Took {}ms", start.elapsed().as_millis());
-    if let Err(err) = result {
-        eprintln!("💥 virtualenv creator failed");
-        let mut last_error: Option<&(dyn Error + 'static)> = Some(&err);
-        while let Some(err) = last_error {
-            eprintln!("  Caused by: {}", err);
-            last_error = err.source();
-        }
-        ExitCode::FAILURE
-    } else {
-        ExitCode::SUCCESS
+    let mut ctx = None;
+    let result = run(cli, &mut ctx);
+    let duration_ms = start.elapsed().as_millis() as u64;
+    info!(duration_ms, "Took {duration_ms}ms");
+    match result {
+        Ok(code) => code,
+        Err(err) => {
+            eprintln!("{ERROR}💥 virtualenv creator failed{ERROR:#}");
+            if let gourgeist::Error::Batch(batch) = &err {
+                eprintln!("  {batch}");
+                for (location, err) in &batch.failures {
+                    eprintln!("  {location}:");
+                    print_error_chain(err, "    ");
+                }
+            } else {
+                print_error_chain(&err, "  ");
+            }
+            if let Some(ctx) = &ctx {
+                for hint in hints(ctx, &err) {
+                    eprintln!("{HINT}hint:{HINT:#} {hint}");
+                }
+            }
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn print_error_chain(err: &(dyn Error + 'static), indent: &str) {
+    let mut last_error: Option<&(dyn Error + 'static)> = Some(err);
+    while let Some(err) = last_error {
+        eprintln!("{indent}{DIMMED}Caused by:{DIMMED:#} {}", err);
+        last_error = err.source();
     }
 }
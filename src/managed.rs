@@ -0,0 +1,238 @@
+//! Download standalone CPython builds from python-build-standalone when the requested version isn't
+//! installed
+//!
+//! <https://github.com/indygreg/python-build-standalone>
+
+use crate::sbom::sha256_file;
+use crate::{Context, Error};
+use camino::{FromPathBufError, Utf8Path, Utf8PathBuf};
+use flate2::read::GzDecoder;
+use fs_err as fs;
+use serde::Deserialize;
+use std::io;
+use std::io::BufWriter;
+use tempfile::NamedTempFile;
+use tracing::{debug, info};
+
+const LATEST_RELEASE: &str =
+    "https://api.github.com/repos/indygreg/python-build-standalone/releases/latest";
+
+#[derive(Deserialize)]
+struct Release {
+    assets: Vec<Asset>,
+}
+
+#[derive(Deserialize)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+}
+
+/// The python-build-standalone target triple of this platform
+fn target_triple() -> Option<&'static str> {
+    let triple = match (std::env::consts::ARCH, std::env::consts::OS) {
+        ("x86_64", "linux") => "x86_64-unknown-linux-gnu",
+        ("aarch64", "linux") => "aarch64-unknown-linux-gnu",
+        ("x86_64", "macos") => "x86_64-apple-darwin",
+        ("aarch64", "macos") => "aarch64-apple-darwin",
+        ("x86_64", "windows") => "x86_64-pc-windows-msvc-shared",
+        _ => return None,
+    };
+    Some(triple)
+}
+
+/// Downloaded pythons are in `<data dir>/pythons/cpython-<version>-<triple>`
+pub fn managed_pythons_dir(ctx: &Context) -> io::Result<Utf8PathBuf> {
+    Ok(ctx.data_dir()?.join("pythons"))
+}
+
+/// The interpreter in an extracted `install_only` archive
+fn interpreter(install: &Utf8Path) -> Utf8PathBuf {
+    if cfg!(windows) {
+        install.join("python").join("python.exe")
+    } else {
+        install.join("python").join("bin").join("python3")
+    }
+}
+
+/// The interpreters of all downloaded pythons for this platform.
+pub(crate) fn managed_pythons(ctx: &Context) -> io::Result<Vec<Utf8PathBuf>> {
+    // Without a data dir, nothing can have been downloaded
+    let Ok(dir) = managed_pythons_dir(ctx) else {
+        return Ok(Vec::new());
+    };
+    let Some(triple) = target_triple() else {
+        return Ok(Vec::new());
+    };
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut pythons = Vec::new();
+    for entry in fs::read_dir(&dir)? {
+        let name = entry?.file_name().to_string_lossy().to_string();
+        if !name.starts_with("cpython-") || !name.ends_with(&format!("-{triple}")) {
+            continue;
+        }
+        let python = interpreter(&dir.join(&name));
+        if python.is_file() {
+            pythons.push(python);
+        }
+    }
+    pythons.sort();
+    Ok(pythons)
+}
+
+/// The latest downloaded python with the given minor version, if any.
+pub fn find_managed_python(ctx: &Context, major: u8, minor: u8) -> io::Result<Option<Utf8PathBuf>> {
+    let Ok(dir) = managed_pythons_dir(ctx) else {
+        return Ok(None);
+    };
+    let Some(triple) = target_triple() else {
+        return Ok(None);
+    };
+    if !dir.is_dir() {
+        return Ok(None);
+    }
+    let mut found: Option<(u32, Utf8PathBuf)> = None;
+    for entry in fs::read_dir(&dir)? {
+        let name = entry?.file_name().to_string_lossy().to_string();
+        let Some(patch) = name
+            .strip_prefix(&format!("cpython-{major}.{minor}."))
+            .and_then(|rest| rest.strip_suffix(&format!("-{triple}")))
+            .and_then(|patch| patch.parse::<u32>().ok())
+        else {
+            continue;
+        };
+        let python = interpreter(&dir.join(&name));
+        if python.is_file() && found.as_ref().is_none_or(|(best, _)| patch > *best) {
+            found = Some((patch, python));
+        }
+    }
+    Ok(found.map(|(_, python)| python))
+}
+
+/// The published SHA256 of the asset called `name`, from its `<name>.sha256` file or else from the
+/// release's `SHA256SUMS` manifest, `None` if the release has neither.
+fn expected_sha256(release: &Release, name: &str) -> Result<Option<String>, Error> {
+    let fetch = |asset: &Asset| -> Result<String, Error> {
+        let response = minreq::get(&asset.browser_download_url).send()?;
+        if response.status_code != 200 {
+            return Err(Error::HttpStatus {
+                url: asset.browser_download_url.clone(),
+                status: response.status_code,
+            });
+        }
+        Ok(response.as_str()?.to_string())
+    };
+    let sidecar = format!("{name}.sha256");
+    if let Some(checksum) = release.assets.iter().find(|asset| asset.name == sidecar) {
+        let checksum = fetch(checksum)?;
+        return Ok(checksum.split_whitespace().next().map(str::to_string));
+    }
+    if let Some(manifest) = release
+        .assets
+        .iter()
+        .find(|asset| asset.name == "SHA256SUMS")
+    {
+        // `<hash>  <filename>` per line, like `sha256sum` writes it
+        let manifest = fetch(manifest)?;
+        return Ok(manifest.lines().find_map(|line| {
+            let (hash, file) = line.split_once(char::is_whitespace)?;
+            (file.trim_start().trim_start_matches('*') == name).then(|| hash.to_string())
+        }));
+    }
+    Ok(None)
+}
+
+/// Download the latest python-build-standalone build of the given minor version into
+/// [`managed_pythons_dir`] and return its interpreter.
+pub fn download_python(ctx: &Context, major: u8, minor: u8) -> Result<Utf8PathBuf, Error> {
+    let no_download = || Error::NoPythonDownload {
+        version: format!("{major}.{minor}"),
+        platform: format!("{} {}", std::env::consts::OS, std::env::consts::ARCH),
+    };
+    let triple = target_triple().ok_or_else(no_download)?;
+
+    debug!("Querying {LATEST_RELEASE}");
+    // The GitHub API rejects requests without a user agent
+    let response = minreq::get(LATEST_RELEASE)
+        .with_header(
+            "User-Agent",
+            concat!("gourgeist/", env!("CARGO_PKG_VERSION")),
+        )
+        .send()?;
+    if response.status_code != 200 {
+        return Err(Error::HttpStatus {
+            url: LATEST_RELEASE.to_string(),
+            status: response.status_code,
+        });
+    }
+    let release: Release = serde_json::from_str(response.as_str()?).map_err(io::Error::from)?;
+    // e.g. `cpython-3.12.1+20240107-x86_64-unknown-linux-gnu-install_only.tar.gz`
+    let suffix = format!("-{triple}-install_only.tar.gz");
+    let asset = release
+        .assets
+        .iter()
+        .filter_map(|asset| {
+            let (version, _date) = asset
+                .name
+                .strip_prefix("cpython-")?
+                .strip_suffix(&suffix)?
+                .split_once('+')?;
+            let patch = version
+                .strip_prefix(&format!("{major}.{minor}."))?
+                .parse::<u32>()
+                .ok()?;
+            Some((patch, version, asset))
+        })
+        .max_by_key(|(patch, _, _)| *patch);
+    let Some((_, version, asset)) = asset else {
+        return Err(no_download());
+    };
+    let expected =
+        expected_sha256(&release, &asset.name)?.ok_or_else(|| Error::PythonDownloadNoHash {
+            url: asset.browser_download_url.clone(),
+        })?;
+
+    let dir = managed_pythons_dir(ctx)?;
+    fs::create_dir_all(&dir)?;
+    info!(
+        "Downloading python {version} from {}",
+        asset.browser_download_url
+    );
+    let mut archive = NamedTempFile::new_in(&dir)?;
+    let archive_path: Utf8PathBuf = archive
+        .path()
+        .to_path_buf()
+        .try_into()
+        .map_err(|err: FromPathBufError| err.into_io_error())?;
+    let mut response = minreq::get(&asset.browser_download_url).send_lazy()?;
+    io::copy(&mut response, &mut BufWriter::new(&mut archive)).map_err(|err| {
+        Error::PythonDownload {
+            url: asset.browser_download_url.clone(),
+            path: archive_path.clone(),
+            err,
+        }
+    })?;
+    let actual = sha256_file(&archive_path)?;
+    if !actual.eq_ignore_ascii_case(&expected) {
+        return Err(Error::PythonDownloadHash {
+            url: asset.browser_download_url.clone(),
+            expected,
+            actual,
+        });
+    }
+
+    // Extract next to the final location and move it into place, so a failed extraction doesn't
+    // leave a broken python behind
+    let extracted = tempfile::Builder::new()
+        .prefix(".extract-")
+        .tempdir_in(&dir)?;
+    tar::Archive::new(GzDecoder::new(fs::File::open(&archive_path)?)).unpack(extracted.path())?;
+    let install = dir.join(format!("cpython-{version}-{triple}"));
+    if install.exists() {
+        fs::remove_dir_all(&install)?;
+    }
+    fs::rename(extracted.path(), &install)?;
+    Ok(interpreter(&install))
+}
@@ -0,0 +1,104 @@
+//! Create one venv per python version, e.g. for testing a project against all supported versions
+
+use crate::bare::VenvPaths;
+use crate::interpreter::{get_interpreter_info, parse_python_cli, venv_base_interpreter};
+use crate::{collect_batch, create_venv, Context, Error, InterpreterInfo, VenvOptions};
+use camino::{Utf8Path, Utf8PathBuf};
+use std::thread;
+
+/// Create a venv for each python in `out`, named after the python version, e.g. `out/py311` for
+/// python 3.11 or `out/pypy310` for PyPy 3.10. The pythons are resolved like `--python`, so they
+/// can be versions such as `3.11` or paths.
+///
+/// All pythons are resolved first, and pythons that would create the same venv as an earlier one
+/// are rejected. The venvs are then created concurrently. Like [`crate::run_batch`], all pythons
+/// are attempted and the failures are collected into a [`crate::BatchError`], keyed by the
+/// requested python.
+pub fn create_matrix(
+    ctx: &Context,
+    pythons: &[Utf8PathBuf],
+    out: &Utf8Path,
+    options: &VenvOptions,
+) -> Result<Vec<VenvPaths>, Error> {
+    let out = ctx.absolute(out);
+    let resolved = in_parallel(pythons, |python| {
+        let base_python = parse_python_cli(ctx, Some(python.to_path_buf()))?;
+        let base_python = venv_base_interpreter(ctx, &base_python)?.unwrap_or(base_python);
+        let info = get_interpreter_info(ctx, &base_python)?;
+        Ok((base_python, info))
+    });
+
+    // The location of each python, or why it can't be created
+    let mut planned: Vec<Result<(Utf8PathBuf, Utf8PathBuf, InterpreterInfo), Error>> = Vec::new();
+    for (python, result) in pythons.iter().zip(resolved) {
+        let planned_python = result.and_then(|(base_python, info)| {
+            let location = out.join(venv_name(&info));
+            let earlier = pythons.iter().zip(&planned).find_map(|(other, planned)| {
+                let (_, other_location, _) = planned.as_ref().ok()?;
+                (*other_location == location).then_some(other)
+            });
+            if let Some(other) = earlier {
+                return Err(Error::DuplicateMatrixPython {
+                    python: python.clone(),
+                    other: other.clone(),
+                    location,
+                });
+            }
+            Ok((base_python, location, info))
+        });
+        planned.push(planned_python);
+    }
+
+    let created = in_parallel(&planned, |planned| match planned {
+        Ok((base_python, location, info)) => {
+            create_venv(ctx, location, base_python, info, options).map(Some)
+        }
+        // Reported with the planning error below
+        Err(_) => Ok(None),
+    });
+    let results = pythons
+        .iter()
+        .cloned()
+        .zip(planned.into_iter().zip(created))
+        .map(|(python, (planned, created))| {
+            let created = planned
+                .and(created)
+                .map(|paths| paths.expect("The venv was planned"));
+            (python, created)
+        })
+        .collect();
+    collect_batch(results)
+}
+
+/// The name of the venv for an interpreter in the output directory
+fn venv_name(info: &InterpreterInfo) -> String {
+    let prefix = match info.implementation.as_str() {
+        "cpython" => "py",
+        implementation => implementation,
+    };
+    format!("{prefix}{}{}", info.major, info.minor)
+}
+
+/// Run `f` for each item on its own thread, returning the results in order
+fn in_parallel<T: Sync, R: Send>(
+    items: &[T],
+    f: impl Fn(&T) -> Result<R, Error> + Sync,
+) -> Vec<Result<R, Error>> {
+    thread::scope(|scope| {
+        let handles: Vec<_> = items
+            .iter()
+            .map(|item| {
+                let f = &f;
+                scope.spawn(move || f(item))
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| {
+                handle
+                    .join()
+                    .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+            })
+            .collect()
+    })
+}
@@ -0,0 +1,74 @@
+//! `gourgeist.json`, the machine-readable record of how a venv was created, for tools that need
+//! more than `pyvenv.cfg`
+
+use crate::interpreter::InterpreterInfo;
+use crate::paths::canonicalize;
+use crate::sbom::{sha256_file, SeededPackage};
+use crate::{Error, VenvOptions};
+use camino::{Utf8Path, Utf8PathBuf};
+use fs_err as fs;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::time::SystemTime;
+
+/// The file in the venv root
+pub const METADATA_FILE: &str = "gourgeist.json";
+
+/// How a venv was created. This is a record of the creation, later changes such as
+/// `--upgrade` or `--upgrade-deps` are not reflected.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreationMetadata {
+    /// Seconds since the unix epoch, `SOURCE_DATE_EPOCH` for reproducible venvs
+    pub created: i64,
+    pub gourgeist_version: String,
+    /// The base interpreter
+    pub interpreter: Utf8PathBuf,
+    /// The hex SHA256 of the base interpreter binary
+    pub interpreter_sha256: String,
+    pub python_version: String,
+    pub options: VenvOptions,
+    pub seeded_packages: Vec<SeededPackage>,
+}
+
+/// Write the [`CreationMetadata`] to `root`.
+pub(crate) fn write_metadata(
+    root: &Utf8Path,
+    base_python: &Utf8Path,
+    info: &InterpreterInfo,
+    options: &VenvOptions,
+    seeded: &[SeededPackage],
+    epoch: Option<i64>,
+) -> io::Result<()> {
+    let created = epoch.unwrap_or_else(|| {
+        SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs() as i64)
+    });
+    let metadata = CreationMetadata {
+        created,
+        gourgeist_version: env!("CARGO_PKG_VERSION").to_string(),
+        interpreter: base_python.to_path_buf(),
+        interpreter_sha256: sha256_file(&canonicalize(base_python)?)?,
+        python_version: info.python_version.clone(),
+        options: options.clone(),
+        seeded_packages: seeded.to_vec(),
+    };
+    let json = serde_json::to_string_pretty(&metadata).map_err(io::Error::from)?;
+    fs::write(root.join(METADATA_FILE), json + "\n")
+}
+
+/// The [`CreationMetadata`] of the venv at `location`, `None` if it was created without one (e.g.
+/// by an older gourgeist or another tool).
+pub fn read_metadata(location: &Utf8Path) -> Result<Option<CreationMetadata>, Error> {
+    if !location.join("pyvenv.cfg").is_file() {
+        return Err(Error::NotAVenv(location.to_path_buf()));
+    }
+    let path = location.join(METADATA_FILE);
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    let metadata = serde_json::from_str(&contents).map_err(io::Error::from)?;
+    Ok(Some(metadata))
+}
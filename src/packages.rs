@@ -1,29 +1,82 @@
 use crate::bare::VenvPaths;
+#[cfg(feature = "embed-seeds")]
+use crate::cache::write_atomic;
+use crate::cache::CacheBucket;
+use crate::durable::sync_file;
 use crate::interpreter::InterpreterInfo;
-use crate::{crate_cache_dir, Error};
+use crate::link::LinkMode;
+use crate::plan::PlannedSeed;
+use crate::sbom::{sha256_file, SeededPackage};
+use crate::scripts::{
+    apply_script_naming, normalize_name, rewrite_shebangs, shebang, ScriptNaming,
+};
+use crate::store::PackageStore;
+use crate::workspace::{matches_requires_python, release};
+use crate::{Context, Error};
 use camino::{FromPathBufError, Utf8Path, Utf8PathBuf};
+use filetime::FileTime;
 use fs_err as fs;
 use fs_err::File;
-use install_wheel_rs::{install_wheel, InstallLocation, WheelFilename};
+use install_wheel_rs::{install_wheel, InstallLocation, LockedDir, WheelFilename};
 #[cfg(feature = "parallel")]
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use serde::Deserialize;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet};
 use std::io;
 use std::io::BufWriter;
 use std::str::FromStr;
-use tempfile::NamedTempFile;
-use tracing::info;
+use std::time::Instant;
+use tempfile::{NamedTempFile, TempDir};
+use tracing::{debug, info};
 
-pub fn download_wheel_cached(filename: &str, url: &str) -> Result<Utf8PathBuf, Error> {
-    let wheels_cache = crate_cache_dir()?.join("wheels");
-    let cached_wheel = wheels_cache.join(filename);
-    if cached_wheel.is_file() {
+/// Download a wheel into `wheels_dir`, the wheel cache unless [`Context::no_cache`] is set.
+pub fn download_wheel_cached(
+    ctx: &Context,
+    wheels_dir: &Utf8Path,
+    filename: &str,
+    url: &str,
+) -> Result<Utf8PathBuf, Error> {
+    let cached_wheel = wheels_dir.join(filename);
+    let start = Instant::now();
+    if !ctx.no_cache && cached_wheel.is_file() {
         info!("Using cached wheel at {cached_wheel}");
+        // The cache garbage collection removes the wheels that weren't used for a while
+        filetime::set_file_mtime(&cached_wheel, FileTime::now())?;
+        debug!(
+            step = "download",
+            package = name_and_version(filename).0,
+            path = %cached_wheel,
+            cached = true,
+            duration_ms = start.elapsed().as_millis() as u64,
+            "Found {filename} in the cache"
+        );
+        return Ok(cached_wheel);
+    }
+
+    #[cfg(feature = "embed-seeds")]
+    if let Some((_, contents)) = EMBEDDED_WHEELS.iter().find(|(name, _)| *name == filename) {
+        if !ctx.no_cache {
+            CacheBucket::Wheels.create(ctx)?;
+        }
+        write_atomic(&cached_wheel, contents)?;
+        sync_file(ctx, &cached_wheel)?;
+        debug!(
+            step = "download",
+            package = name_and_version(filename).0,
+            path = %cached_wheel,
+            cached = true,
+            duration_ms = start.elapsed().as_millis() as u64,
+            "Wrote the embedded {filename}"
+        );
         return Ok(cached_wheel);
     }
 
     info!("Downloading wheel from {url} to {cached_wheel}");
-    fs::create_dir_all(&wheels_cache)?;
-    let mut tempfile = NamedTempFile::new_in(wheels_cache)?;
+    if !ctx.no_cache {
+        CacheBucket::Wheels.create(ctx)?;
+    }
+    let mut tempfile = NamedTempFile::new_in(wheels_dir)?;
     let tempfile_path: Utf8PathBuf = tempfile
         .path()
         .to_path_buf()
@@ -38,52 +91,585 @@ pub fn download_wheel_cached(filename: &str, url: &str) -> Result<Utf8PathBuf, E
         }
     })?;
     tempfile.persist(&cached_wheel)?;
+    sync_file(ctx, &cached_wheel)?;
+    debug!(
+        step = "download",
+        package = name_and_version(filename).0,
+        path = %cached_wheel,
+        cached = false,
+        duration_ms = start.elapsed().as_millis() as u64,
+        "Downloaded {filename}"
+    );
     Ok(cached_wheel)
 }
 
+/// The pinned seed packages as wheel filename and url
+// TODO: Only check the json API so often (monthly? daily?)
+const SEED_WHEELS: [(&str, &str); 3] = [
+    ("pip-23.2.1-py3-none-any.whl", "https://files.pythonhosted.org/packages/50/c2/e06851e8cc28dcad7c155f4753da8833ac06a5c704c109313b8d5a62968a/pip-23.2.1-py3-none-any.whl"),
+    ("setuptools-68.2.2-py3-none-any.whl", "https://files.pythonhosted.org/packages/bb/26/7945080113158354380a12ce26873dd6c1ebd88d47f5bc24e2c5bb38c16a/setuptools-68.2.2-py3-none-any.whl"),
+    ("wheel-0.41.2-py3-none-any.whl", "https://files.pythonhosted.org/packages/b8/8b/31273bf66016be6ad22bb7345c37ff350276cfd46e389a0c2ac5da9d9073/wheel-0.41.2-py3-none-any.whl"),
+];
+
+/// The [`SEED_WHEELS`] compiled into the binary
+#[cfg(feature = "embed-seeds")]
+const EMBEDDED_WHEELS: [(&str, &[u8]); 3] = [
+    (
+        "pip-23.2.1-py3-none-any.whl",
+        include_bytes!(concat!(
+            env!(
+                "GOURGEIST_SEED_WHEELS",
+                "The `embed-seeds` feature requires `GOURGEIST_SEED_WHEELS` to be set to a directory with the pinned seed wheels"
+            ),
+            "/pip-23.2.1-py3-none-any.whl"
+        )),
+    ),
+    (
+        "setuptools-68.2.2-py3-none-any.whl",
+        include_bytes!(concat!(
+            env!("GOURGEIST_SEED_WHEELS"),
+            "/setuptools-68.2.2-py3-none-any.whl"
+        )),
+    ),
+    (
+        "wheel-0.41.2-py3-none-any.whl",
+        include_bytes!(concat!(
+            env!("GOURGEIST_SEED_WHEELS"),
+            "/wheel-0.41.2-py3-none-any.whl"
+        )),
+    ),
+];
+
+/// Download the pinned seed wheels into the wheel cache, returning their paths.
+pub fn fetch_seed_wheels(ctx: &Context) -> Result<Vec<Utf8PathBuf>, Error> {
+    let wheels_dir = CacheBucket::Wheels.path(ctx)?;
+    SEED_WHEELS
+        .into_iter()
+        .map(|(filename, url)| download_wheel_cached(ctx, &wheels_dir, filename, url))
+        .collect()
+}
+
+/// Where the seed wheels would come from, for [`plan_venv`](crate::plan_venv)
+pub(crate) fn planned_seeds(ctx: &Context) -> Vec<PlannedSeed> {
+    let wheels_dir = CacheBucket::Wheels.path(ctx).ok();
+    SEED_WHEELS
+        .into_iter()
+        .map(|(filename, url)| {
+            let (name, version) = name_and_version(filename);
+            let cached_wheel = wheels_dir
+                .as_ref()
+                .map(|wheels_dir| wheels_dir.join(filename))
+                .filter(|cached_wheel| !ctx.no_cache && cached_wheel.is_file());
+            let (source, download) = if let Some(cached_wheel) = cached_wheel {
+                (cached_wheel.to_string(), false)
+            } else if cfg!(feature = "embed-seeds") {
+                ("embedded".to_string(), false)
+            } else {
+                (url.to_string(), true)
+            };
+            PlannedSeed {
+                name: name.to_string(),
+                version: version.to_string(),
+                source,
+                download,
+            }
+        })
+        .collect()
+}
+
 /// Install pip, setuptools and wheel from cache pypi with atm fixed wheels
+///
+/// The files are installed into `staging`, while the launchers point to the interpreter in `paths`.
 pub fn install_base_packages(
-    location: &Utf8Path,
-    info: &InterpreterInfo,
+    ctx: &Context,
+    staging: &VenvPaths,
     paths: &VenvPaths,
-) -> Result<(), Error> {
+    info: &InterpreterInfo,
+    script_naming: &ScriptNaming,
+    reproducible: bool,
+    store: Option<LinkMode>,
+) -> Result<Vec<SeededPackage>, Error> {
     let install_location = InstallLocation::Venv {
-        venv_base: location.canonicalize()?,
+        venv_base: staging.root.clone().into_std_path_buf(),
         python_version: (info.major, info.minor),
     };
     let install_location = install_location.acquire_lock()?;
+    let wheels_dir = WheelsDir::new(ctx)?;
+    let store = store
+        .map(|link_mode| PackageStore::new(ctx, link_mode))
+        .transpose()?;
 
-    // TODO: Use the json api instead
-    // TODO: Only check the json API so often (monthly? daily?)
-    let packages = [
-        ("pip-23.2.1-py3-none-any.whl", "https://files.pythonhosted.org/packages/50/c2/e06851e8cc28dcad7c155f4753da8833ac06a5c704c109313b8d5a62968a/pip-23.2.1-py3-none-any.whl"),
-        ("setuptools-68.2.2-py3-none-any.whl", "https://files.pythonhosted.org/packages/bb/26/7945080113158354380a12ce26873dd6c1ebd88d47f5bc24e2c5bb38c16a/setuptools-68.2.2-py3-none-any.whl"),
-        ("wheel-0.41.2-py3-none-any.whl", "https://files.pythonhosted.org/packages/b8/8b/31273bf66016be6ad22bb7345c37ff350276cfd46e389a0c2ac5da9d9073/wheel-0.41.2-py3-none-any.whl"),
-    ];
-    #[cfg(feature = "rayon")]
-    let iterator = packages.into_par_iter();
-    #[cfg(not(feature = "rayon"))]
-    let iterator = packages.into_iter();
-    iterator
-        .map(|(filename, url)| {
-            let wheel_file = download_wheel_cached(filename, url)?;
-            let parsed_filename = WheelFilename::from_str(filename).unwrap();
-            install_wheel(
-                &install_location,
-                File::open(wheel_file)?,
-                parsed_filename,
-                false,
-                &[],
-                // Only relevant for monotrail style installation
-                "",
-                paths.interpreter.as_std_path(),
+    let install = |(filename, url): (&str, &str)| {
+        let (name, version) = name_and_version(filename);
+        let dist_info = staging
+            .site_packages
+            .join(format!("{name}-{version}.dist-info"));
+        let sha256 = match &store {
+            Some(store) => {
+                let wheel = download_wheel_cached(ctx, wheels_dir.path(), filename, url)?;
+                let sha256 = sha256_file(&wheel)?;
+                let key = PackageStore::key(&sha256, staging);
+                let start = Instant::now();
+                if store.materialize(&key, staging, &shebang(&paths.interpreter))? {
+                    debug!(
+                        step = "install",
+                        package = name,
+                        path = %paths.interpreter,
+                        cached = true,
+                        duration_ms = start.elapsed().as_millis() as u64,
+                        "Linked {filename} from the store"
+                    );
+                } else {
+                    install_seed_wheel(
+                        ctx,
+                        &install_location,
+                        wheels_dir.path(),
+                        filename,
+                        url,
+                        &paths.interpreter,
+                    )?;
+                    // Replace the installed files with links, so this venv shares them too
+                    store.ingest(ctx, &key, staging, &dist_info, &paths.interpreter)?;
+                    store.materialize(&key, staging, &shebang(&paths.interpreter))?;
+                }
+                sha256
+            }
+            None => {
+                let wheel = install_seed_wheel(
+                    ctx,
+                    &install_location,
+                    wheels_dir.path(),
+                    filename,
+                    url,
+                    &paths.interpreter,
+                )?;
+                sha256_file(&wheel)?
+            }
+        };
+        Ok(SeededPackage {
+            name: name.to_string(),
+            version: version.to_string(),
+            filename: filename.to_string(),
+            sha256: Some(sha256),
+            url: Some(url.to_string()),
+        })
+    };
+    // Parallel installation writes the files in a different order each time
+    #[cfg(feature = "parallel")]
+    let seeded = if reproducible {
+        SEED_WHEELS
+            .into_iter()
+            .map(install)
+            .collect::<Result<Vec<_>, Error>>()?
+    } else {
+        SEED_WHEELS
+            .into_par_iter()
+            .map(install)
+            .collect::<Result<Vec<_>, Error>>()?
+    };
+    #[cfg(not(feature = "parallel"))]
+    let seeded = {
+        let _ = reproducible;
+        SEED_WHEELS
+            .into_iter()
+            .map(install)
+            .collect::<Result<Vec<_>, Error>>()?
+    };
+
+    // install-wheel-rs writes a plain shebang even for paths with spaces or very long paths
+    rewrite_shebangs(
+        &staging.bin,
+        &paths.interpreter,
+        &shebang(&paths.interpreter),
+    )?;
+    // install-wheel-rs always uses the original script names
+    for (filename, _url) in SEED_WHEELS {
+        let (name, version) = name_and_version(filename);
+        let dist_info = staging
+            .site_packages
+            .join(format!("{name}-{version}.dist-info"));
+        apply_script_naming(&staging.bin, &dist_info, name, script_naming)?;
+    }
+    Ok(seeded)
+}
+
+/// The wheel cache, or a temporary directory that is removed when dropped with
+/// [`Context::no_cache`]
+struct WheelsDir {
+    path: Utf8PathBuf,
+    _temp_dir: Option<TempDir>,
+}
+
+impl WheelsDir {
+    fn new(ctx: &Context) -> io::Result<Self> {
+        if !ctx.no_cache {
+            return Ok(Self {
+                path: CacheBucket::Wheels.path(ctx)?,
+                _temp_dir: None,
+            });
+        }
+        let temp_dir = TempDir::new()?;
+        let path = Utf8PathBuf::from_path_buf(temp_dir.path().to_path_buf()).map_err(|path| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Non-utf8 temporary directory {}", path.display()),
             )
-            .map_err(|err| Error::InstallWheel {
-                package: filename.to_string(),
-                err,
-            })?;
-            Ok(())
+        })?;
+        Ok(Self {
+            path,
+            _temp_dir: Some(temp_dir),
         })
-        .collect::<Result<Vec<()>, Error>>()?;
+    }
+
+    fn path(&self) -> &Utf8Path {
+        &self.path
+    }
+}
+
+fn install_seed_wheel(
+    ctx: &Context,
+    install_location: &InstallLocation<LockedDir>,
+    wheels_dir: &Utf8Path,
+    filename: &str,
+    url: &str,
+    interpreter: &Utf8Path,
+) -> Result<Utf8PathBuf, Error> {
+    let wheel_file = download_wheel_cached(ctx, wheels_dir, filename, url)?;
+    install_downloaded_wheel(install_location, &wheel_file, filename, interpreter)?;
+    Ok(wheel_file)
+}
+
+/// Install the already downloaded `wheel_file` called `filename`.
+fn install_downloaded_wheel(
+    install_location: &InstallLocation<LockedDir>,
+    wheel_file: &Utf8Path,
+    filename: &str,
+    interpreter: &Utf8Path,
+) -> Result<(), Error> {
+    let start = Instant::now();
+    let install_error = |err| Error::InstallWheel {
+        package: filename.to_string(),
+        err,
+    };
+    // The filenames of upgrades come from the index
+    let parsed_filename = WheelFilename::from_str(filename).map_err(install_error)?;
+    install_wheel(
+        install_location,
+        File::open(wheel_file)?,
+        parsed_filename,
+        false,
+        &[],
+        // Only relevant for monotrail style installation
+        "",
+        interpreter.as_std_path(),
+    )
+    .map_err(install_error)?;
+    debug!(
+        step = "install",
+        package = name_and_version(filename).0,
+        path = %interpreter,
+        duration_ms = start.elapsed().as_millis() as u64,
+        "Installed {filename}"
+    );
     Ok(())
 }
+
+/// Install a wheel file into an existing venv of the given python version, with the script
+/// shebangs pointing to the venv's interpreter.
+pub fn install_wheel_file(
+    paths: &VenvPaths,
+    python_version: (u8, u8),
+    wheel: &Utf8Path,
+) -> Result<(), Error> {
+    let filename = wheel.file_name().unwrap_or(wheel.as_str());
+    let install_error = |err| Error::InstallWheel {
+        package: filename.to_string(),
+        err,
+    };
+    let parsed_filename = WheelFilename::from_str(filename).map_err(install_error)?;
+    let install_location = InstallLocation::Venv {
+        venv_base: paths.root.clone().into_std_path_buf(),
+        python_version,
+    };
+    let install_location = install_location.acquire_lock()?;
+    install_wheel(
+        &install_location,
+        File::open(wheel)?,
+        parsed_filename,
+        false,
+        &[],
+        // Only relevant for monotrail style installation
+        "",
+        paths.interpreter.as_std_path(),
+    )
+    .map_err(install_error)?;
+    // install-wheel-rs writes a plain shebang even for paths with spaces or very long paths
+    rewrite_shebangs(&paths.bin, &paths.interpreter, &shebang(&paths.interpreter))?;
+    Ok(())
+}
+
+/// The distribution name and version from a wheel filename
+fn name_and_version(filename: &str) -> (&str, &str) {
+    let mut parts = filename.split('-');
+    (
+        parts.next().unwrap_or_default(),
+        parts.next().unwrap_or_default(),
+    )
+}
+
+/// The seed packages in `site_packages` that [`upgrade_base_packages`] would replace, including
+/// missing ones with an empty old version
+pub(crate) fn outdated_seeds(
+    ctx: &Context,
+    site_packages: &Utf8Path,
+    python_version: &str,
+) -> Result<Vec<SeedUpgrade>, Error> {
+    let mut outdated = Vec::new();
+    for (seed_filename, _url) in SEED_WHEELS {
+        let (name, _) = name_and_version(seed_filename);
+        let wheel = latest_wheel(ctx, name, python_version)?;
+        let (_, new_version) = name_and_version(&wheel.filename);
+        let old_version = find_dist_info(site_packages, name)?
+            .map(|(_, version)| version)
+            .unwrap_or_default();
+        if old_version != new_version {
+            outdated.push(SeedUpgrade {
+                name: name.to_string(),
+                old_version,
+                new_version: new_version.to_string(),
+            });
+        }
+    }
+    Ok(outdated)
+}
+
+/// A seed package that was replaced by a newer version
+#[derive(Debug, Clone)]
+pub struct SeedUpgrade {
+    pub name: String,
+    pub old_version: String,
+    pub new_version: String,
+}
+
+#[derive(Deserialize)]
+struct PypiProject {
+    info: PypiInfo,
+    /// The files of the latest release
+    urls: Vec<PypiFile>,
+    /// The files of all releases by version, which some mirrors omit
+    #[serde(default)]
+    releases: BTreeMap<String, Vec<PypiFile>>,
+}
+
+#[derive(Deserialize)]
+struct PypiInfo {
+    version: String,
+    requires_python: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct PypiFile {
+    filename: String,
+    url: String,
+    requires_python: Option<String>,
+    #[serde(default)]
+    yanked: bool,
+    #[serde(default)]
+    digests: PypiDigests,
+}
+
+#[derive(Default, Deserialize)]
+struct PypiDigests {
+    sha256: Option<String>,
+}
+
+impl PypiFile {
+    fn is_universal_wheel(&self) -> bool {
+        self.filename.ends_with("-py3-none-any.whl")
+    }
+
+    /// Whether the file can be installed for `python_version`. Unparsable specifiers count as
+    /// compatible, like a missing `requires_python`.
+    fn supports(&self, python_version: &str) -> bool {
+        self.requires_python.as_deref().is_none_or(|specifiers| {
+            matches_requires_python(specifiers, python_version).unwrap_or(true)
+        })
+    }
+}
+
+/// Whether a version is a pre-release or a development release, e.g. `24.0b1` or `69.0.0.dev0`
+fn is_prerelease(version: &str) -> bool {
+    ["a", "b", "rc", "dev", "c"].iter().any(|marker| {
+        version
+            .split(char::is_numeric)
+            .any(|part| part.trim_matches('.') == *marker)
+    })
+}
+
+/// Find the universal wheel of the newest release of `name` on pypi (or the
+/// [`Context::index_url`] mirror) that supports `python_version` according to its
+/// `requires_python`, skipping pre-releases and yanked files.
+fn latest_wheel(ctx: &Context, name: &str, python_version: &str) -> Result<PypiFile, Error> {
+    let url = format!("{}/{name}/json", ctx.index_url.trim_end_matches('/'));
+    debug!("Querying {url}");
+    let response = minreq::get(&url).send()?;
+    if response.status_code != 200 {
+        return Err(Error::HttpStatus {
+            url,
+            status: response.status_code,
+        });
+    }
+    let project: PypiProject = serde_json::from_str(response.as_str()?).map_err(io::Error::from)?;
+    let mut releases: Vec<(String, Vec<PypiFile>)> = project
+        .releases
+        .into_iter()
+        .filter(|(version, _)| !is_prerelease(version))
+        .collect();
+    if releases.is_empty() {
+        // Without the release list, the latest release is all we have
+        let requires_python = project.info.requires_python;
+        let files = project
+            .urls
+            .into_iter()
+            .map(|file| PypiFile {
+                requires_python: file.requires_python.or_else(|| requires_python.clone()),
+                ..file
+            })
+            .collect();
+        releases.push((project.info.version.clone(), files));
+    }
+    releases.sort_by_cached_key(|(version, _)| Reverse(release(version)));
+    releases
+        .into_iter()
+        .flat_map(|(_, files)| files)
+        .find(|file| file.is_universal_wheel() && !file.yanked && file.supports(python_version))
+        .ok_or_else(|| Error::NoUniversalWheel {
+            name: name.to_string(),
+            version: project.info.version,
+            python_version: python_version.to_string(),
+        })
+}
+
+/// Find the `.dist-info` directory of an installed package and its version.
+fn find_dist_info(
+    site_packages: &Utf8Path,
+    name: &str,
+) -> Result<Option<(Utf8PathBuf, String)>, Error> {
+    for entry in fs::read_dir(site_packages)? {
+        let entry = entry?;
+        let file_name = entry.file_name();
+        let Some(stem) = file_name
+            .to_str()
+            .and_then(|file_name| file_name.strip_suffix(".dist-info"))
+        else {
+            continue;
+        };
+        let Some((dist_name, version)) = stem.split_once('-') else {
+            continue;
+        };
+        if normalize_name(dist_name) == normalize_name(name) {
+            return Ok(Some((
+                site_packages.join(&*file_name.to_string_lossy()),
+                version.to_string(),
+            )));
+        }
+    }
+    Ok(None)
+}
+
+/// Remove an installed package with all the files listed in its `RECORD`.
+fn uninstall(site_packages: &Utf8Path, dist_info: &Utf8Path) -> Result<(), Error> {
+    let record = fs::read_to_string(dist_info.join("RECORD"))?;
+    let mut parents = BTreeSet::new();
+    for line in record.lines() {
+        // The path may be quoted if it contains a comma, but our seed packages don't do that
+        let Some(path) = line.split(',').next().filter(|path| !path.is_empty()) else {
+            continue;
+        };
+        let path = site_packages.join(path);
+        if path.symlink_metadata().is_ok() {
+            fs::remove_file(&path)?;
+        }
+        if let Some(parent) = path.parent() {
+            parents.insert(parent.to_path_buf());
+        }
+    }
+    // Remove the directories that are empty now, deepest first
+    for parent in parents.iter().rev() {
+        let mut dir = Some(parent.as_path());
+        while let Some(current) =
+            dir.filter(|dir| dir.starts_with(site_packages) && *dir != site_packages)
+        {
+            if fs::remove_dir(current).is_err() {
+                break;
+            }
+            dir = current.parent();
+        }
+    }
+    if dist_info.exists() {
+        fs::remove_dir_all(dist_info)?;
+    }
+    Ok(())
+}
+
+/// Replace the seed packages in an existing venv with their latest versions from pypi, leaving all
+/// other packages alone.
+pub fn upgrade_base_packages(
+    ctx: &Context,
+    paths: &VenvPaths,
+    info: &InterpreterInfo,
+    script_naming: &ScriptNaming,
+) -> Result<Vec<SeedUpgrade>, Error> {
+    let install_location = InstallLocation::Venv {
+        venv_base: paths.root.clone().into_std_path_buf(),
+        python_version: (info.major, info.minor),
+    };
+    let install_location = install_location.acquire_lock()?;
+    let wheels_dir = WheelsDir::new(ctx)?;
+
+    let mut upgrades = Vec::new();
+    for (seed_filename, _url) in SEED_WHEELS {
+        let (name, _) = name_and_version(seed_filename);
+        let wheel = latest_wheel(ctx, name, &info.python_version)?;
+        let filename = &wheel.filename;
+        let (_, new_version) = name_and_version(filename);
+        let installed = find_dist_info(&paths.site_packages, name)?;
+        if let Some((_, old_version)) = &installed {
+            if old_version == new_version {
+                debug!("{name} {old_version} is up to date");
+                continue;
+            }
+        }
+        info!("Upgrading {name} to {new_version}");
+        // Only remove the old version once we have the new one, so a failed download doesn't
+        // leave the venv without the package
+        let wheel_file = download_wheel_cached(ctx, wheels_dir.path(), filename, &wheel.url)?;
+        if let Some(expected) = &wheel.digests.sha256 {
+            let actual = sha256_file(&wheel_file)?;
+            if !actual.eq_ignore_ascii_case(expected) {
+                // Don't keep the broken wheel in the cache
+                fs::remove_file(&wheel_file)?;
+                return Err(Error::WheelHashMismatch {
+                    url: wheel.url,
+                    expected: expected.clone(),
+                    actual,
+                });
+            }
+        }
+        if let Some((dist_info, _)) = &installed {
+            uninstall(&paths.site_packages, dist_info)?;
+        }
+        install_downloaded_wheel(&install_location, &wheel_file, filename, &paths.interpreter)?;
+        let dist_info = paths
+            .site_packages
+            .join(format!("{name}-{new_version}.dist-info"));
+        rewrite_shebangs(&paths.bin, &paths.interpreter, &shebang(&paths.interpreter))?;
+        apply_script_naming(&paths.bin, &dist_info, name, script_naming)?;
+        upgrades.push(SeedUpgrade {
+            name: name.to_string(),
+            old_version: installed.map(|(_, version)| version).unwrap_or_default(),
+            new_version: new_version.to_string(),
+        });
+    }
+    Ok(upgrades)
+}
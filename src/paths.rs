@@ -0,0 +1,276 @@
+//! Path conversions for windows, where paths longer than `MAX_PATH` (260 characters) need the
+//! `\\?\` extended-length prefix for filesystem operations, but shouldn't end up in the venv
+//! files, and network shares have UNC paths (`\\server\share\venv`). On other platforms, all
+//! functions leave the paths unchanged.
+
+use crate::{Context, Error};
+use camino::{Utf8Component, Utf8Path, Utf8PathBuf};
+use fs_err as fs;
+use std::io;
+use std::path::Path;
+use tracing::debug;
+
+/// Canonicalize `path`, without the `\\?\` prefix that windows adds when it isn't needed to
+/// represent the path, since many programs (including python) don't understand it.
+///
+/// On windows, `canonicalize` also resolves mapped network drives (`Z:\venv`) to their UNC path
+/// (`\\server\share\venv`). We keep the drive letter the user chose, since that's the path the
+/// user and their tools know.
+///
+/// If the canonical path isn't UTF-8, e.g. for an alias from [`utf8_path`], `path` is returned
+/// as is.
+pub(crate) fn canonicalize(path: &Utf8Path) -> io::Result<Utf8PathBuf> {
+    let canonical = match Utf8PathBuf::from_path_buf(fs::canonicalize(path)?) {
+        Ok(canonical) => simplified(&canonical),
+        Err(canonical) => {
+            debug!(
+                "Not canonicalizing {path}, its canonical path {} is not UTF-8",
+                canonical.display()
+            );
+            return Ok(path.to_path_buf());
+        }
+    };
+    #[cfg(windows)]
+    {
+        use camino::Utf8Prefix;
+
+        let is_drive_path = matches!(
+            path.components().next(),
+            Some(Utf8Component::Prefix(prefix)) if matches!(prefix.kind(), Utf8Prefix::Disk(_))
+        );
+        if is_drive_path && is_unc(&canonical) && is_normalized(path) {
+            return Ok(path.to_path_buf());
+        }
+    }
+    Ok(canonical)
+}
+
+/// The root of the venv at the absolute `location`: its canonical path, or with
+/// `no_resolve_symlinks` the path as given with only `.` and `..` removed, like python's
+/// `os.path.abspath`, so symlinks in it (e.g. `/tmp` on macOS or home directories on NFS) end up
+/// in the venv files as the user sees them.
+pub(crate) fn venv_root(location: &Utf8Path, no_resolve_symlinks: bool) -> io::Result<Utf8PathBuf> {
+    if no_resolve_symlinks {
+        normalized(location).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{location} has more `..` components than parent directories"),
+            )
+        })
+    } else {
+        canonicalize(location)
+    }
+}
+
+/// Remove the `.` and `..` components of `path` without looking at the filesystem, `None` if a
+/// `..` would go above the start of the path (or the root).
+pub(crate) fn normalized(path: &Utf8Path) -> Option<Utf8PathBuf> {
+    let mut normalized = Utf8PathBuf::new();
+    for component in path.components() {
+        match component {
+            Utf8Component::CurDir => {}
+            Utf8Component::ParentDir => {
+                if !normalized.pop() {
+                    return None;
+                }
+            }
+            component => normalized.push(component),
+        }
+    }
+    Some(normalized)
+}
+
+/// Whether `path` is on a network share, in the plain or the extended-length UNC form.
+pub(crate) fn is_unc(path: &Utf8Path) -> bool {
+    #[cfg(windows)]
+    {
+        let path = path.as_str();
+        path.starts_with(r"\\?\UNC\")
+            || (path.starts_with(r"\\") && !path.starts_with(r"\\?\") && !path.starts_with(r"\\.\"))
+    }
+    #[cfg(not(windows))]
+    {
+        let _ = path;
+        false
+    }
+}
+
+/// Whether `path` has no `.` or `..` components
+#[cfg(windows)]
+fn is_normalized(path: &Utf8Path) -> bool {
+    path.components()
+        .all(|component| !matches!(component, Utf8Component::CurDir | Utf8Component::ParentDir))
+}
+
+/// The extended-length form of an absolute, normalized path for filesystem operations, which
+/// doesn't have the `MAX_PATH` limit: `\\?\C:\...` or `\\?\UNC\server\share\...`.
+///
+/// Windows doesn't normalize extended-length paths, so paths with `.` or `..` components or
+/// relative paths are returned as is.
+pub(crate) fn extended(path: &Utf8Path) -> Utf8PathBuf {
+    #[cfg(windows)]
+    {
+        if path.as_str().starts_with(r"\\?\") || !path.is_absolute() || !is_normalized(path) {
+            return path.to_path_buf();
+        }
+        let path = path.as_str().replace('/', r"\");
+        match path.strip_prefix(r"\\") {
+            Some(unc) => Utf8PathBuf::from(format!(r"\\?\UNC\{unc}")),
+            None => Utf8PathBuf::from(format!(r"\\?\{path}")),
+        }
+    }
+    #[cfg(not(windows))]
+    {
+        path.to_path_buf()
+    }
+}
+
+/// Remove the extended-length prefix from a path, the inverse of [`extended`].
+pub(crate) fn simplified(path: &Utf8Path) -> Utf8PathBuf {
+    #[cfg(windows)]
+    {
+        if let Some(unc) = path.as_str().strip_prefix(r"\\?\UNC\") {
+            return Utf8PathBuf::from(format!(r"\\{unc}"));
+        }
+        // Only drive paths, `\\?\Volume{...}\` and other device paths have no simple form
+        if let Some(drive_path) = path.as_str().strip_prefix(r"\\?\") {
+            let bytes = drive_path.as_bytes();
+            if bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' {
+                return Utf8PathBuf::from(drive_path);
+            }
+        }
+        path.to_path_buf()
+    }
+    #[cfg(not(windows))]
+    {
+        path.to_path_buf()
+    }
+}
+
+/// A UTF-8 path for an interpreter or a venv location below a directory whose name isn't UTF-8,
+/// e.g. a latin-1 home directory.
+///
+/// The path up to its last non-UTF-8 component is replaced by a symlink to it in
+/// `<data dir>/aliases`, named after the hash of the path, so the venv files (`pyvenv.cfg`, the
+/// activators, the script shebangs) can refer to it. The venv keeps working as long as the alias
+/// exists. The last component itself, i.e. the venv directory name, must be UTF-8. Only
+/// supported on unix, windows paths are always valid unicode except for unpaired surrogates.
+pub fn utf8_path(ctx: &Context, path: &Path) -> Result<Utf8PathBuf, Error> {
+    if let Some(path) = path.to_str() {
+        return Ok(Utf8PathBuf::from(path));
+    }
+    let path = ctx.cwd.as_std_path().join(path);
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        use std::path::PathBuf;
+
+        let components: Vec<_> = path.components().collect();
+        let last_non_utf8 = components
+            .iter()
+            .rposition(|component| component.as_os_str().to_str().is_none())
+            .expect("The path is not UTF-8");
+        if last_non_utf8 == components.len() - 1 {
+            return Err(Error::NonUtf8Path(path));
+        }
+        let target: PathBuf = components[..=last_non_utf8].iter().collect();
+        let rest: Utf8PathBuf = components[last_non_utf8 + 1..]
+            .iter()
+            .map(|component| component.as_os_str().to_str().expect("Checked above"))
+            .collect();
+        let aliases = ctx.data_dir()?.join("aliases");
+        fs::create_dir_all(&aliases)?;
+        let alias = aliases.join(format!(
+            "{:016x}",
+            seahash::hash(target.as_os_str().as_bytes())
+        ));
+        match fs::read_link(&alias) {
+            Ok(existing) if existing == target => {}
+            Ok(_) => {
+                fs::remove_file(&alias)?;
+                fs_err::os::unix::fs::symlink(&target, &alias)?;
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                match fs_err::os::unix::fs::symlink(&target, &alias) {
+                    Ok(()) => {}
+                    // Another gourgeist process created it concurrently
+                    Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {}
+                    Err(err) => return Err(err.into()),
+                }
+            }
+            Err(err) => return Err(err.into()),
+        }
+        debug!("Using {alias} for {}", target.display());
+        Ok(alias.join(rest))
+    }
+    #[cfg(not(unix))]
+    {
+        Err(Error::NonUtf8Path(path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalized_removes_dot_components() {
+        assert_eq!(
+            normalized(Utf8Path::new("/home/user/./project/../venv")),
+            Some(Utf8PathBuf::from("/home/user/venv"))
+        );
+        assert_eq!(
+            normalized(Utf8Path::new("project/../.venv")),
+            Some(Utf8PathBuf::from(".venv"))
+        );
+    }
+
+    #[test]
+    fn normalized_rejects_escaping_parent_dirs() {
+        assert_eq!(normalized(Utf8Path::new("/..")), None);
+        assert_eq!(normalized(Utf8Path::new("/home/../../venv")), None);
+        assert_eq!(normalized(Utf8Path::new("../venv")), None);
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn unchanged_on_unix() {
+        let path = Utf8Path::new("/home/user/.venv");
+        assert_eq!(extended(path), path);
+        assert_eq!(simplified(path), path);
+        assert!(!is_unc(Utf8Path::new(r"\\server\share\venv")));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn extended_and_simplified() {
+        for (plain, extended_form) in [
+            (r"C:\Users\user\.venv", r"\\?\C:\Users\user\.venv"),
+            (r"\\server\share\venv", r"\\?\UNC\server\share\venv"),
+        ] {
+            assert_eq!(extended(Utf8Path::new(plain)), extended_form);
+            assert_eq!(simplified(Utf8Path::new(extended_form)), plain);
+        }
+        assert_eq!(
+            extended(Utf8Path::new("C:/Users/user/.venv")),
+            r"\\?\C:\Users\user\.venv"
+        );
+        // Already extended, relative or not normalized
+        for path in [r"\\?\C:\venv", r"venv\Scripts", r"C:\Users\..\venv"] {
+            assert_eq!(extended(Utf8Path::new(path)), path);
+        }
+        // Device paths have no simple form
+        let volume = r"\\?\Volume{12345678-1234-1234-1234-123456789abc}\venv";
+        assert_eq!(simplified(Utf8Path::new(volume)), volume);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn unc_paths() {
+        assert!(is_unc(Utf8Path::new(r"\\server\share\venv")));
+        assert!(is_unc(Utf8Path::new(r"\\?\UNC\server\share\venv")));
+        assert!(!is_unc(Utf8Path::new(r"\\?\C:\venv")));
+        assert!(!is_unc(Utf8Path::new(r"\\.\pipe\gourgeist")));
+        assert!(!is_unc(Utf8Path::new(r"C:\venv")));
+    }
+}
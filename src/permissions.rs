@@ -0,0 +1,82 @@
+//! Set the permissions of a venv independent of where its files were copied from
+
+use camino::Utf8Path;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::io;
+use tracing::debug;
+
+/// The modes of the files and directories in the venv
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FileModes {
+    /// Keep the modes of the files the venv was copied from (the base interpreter, the cache),
+    /// with the umask applied to new files
+    #[default]
+    Inherit,
+    /// 0755 for directories and executables, 0644 for all other files
+    Normalize,
+}
+
+/// Set the modes of all files and directories in `root` to 0755 or 0644, depending on whether
+/// they are executable (by anyone).
+///
+/// Files that are hardlinked elsewhere, e.g. from the cache or the base interpreter, are left
+/// alone, since changing their mode would change the original too. Symlinks don't have a mode.
+pub(crate) fn normalize_modes(root: &Utf8Path) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        debug!("Normalizing the file modes in {root}");
+        normalize_dir(root.as_std_path())
+    }
+    #[cfg(not(unix))]
+    {
+        debug!("Not normalizing the file modes in {root}, windows has no modes");
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+fn normalize_dir(dir: &std::path::Path) -> io::Result<()> {
+    use fs_err as fs;
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let metadata = fs::symlink_metadata(&path)?;
+        if metadata.is_symlink() {
+            continue;
+        }
+        if metadata.is_dir() {
+            normalize_dir(&path)?;
+            continue;
+        }
+        if metadata.nlink() > 1 {
+            continue;
+        }
+        let mode = if metadata.mode() & 0o111 != 0 {
+            0o755
+        } else {
+            0o644
+        };
+        if metadata.mode() & 0o7777 != mode {
+            fs::set_permissions(&path, std::fs::Permissions::from_mode(mode))?;
+        }
+    }
+    fs_err::set_permissions(dir, std::fs::Permissions::from_mode(0o755))
+}
+
+/// Make the venv root accessible only to its owner (0700), hiding the venv from other users.
+pub(crate) fn make_private(root: &Utf8Path) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs_err::set_permissions(root, std::fs::Permissions::from_mode(0o700))
+    }
+    #[cfg(not(unix))]
+    {
+        debug!("Not restricting {root}, private venvs are only supported on unix");
+        Ok(())
+    }
+}
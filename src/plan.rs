@@ -0,0 +1,177 @@
+//! Compute what creating a venv would do without touching the filesystem, for `--dry-run`
+
+use crate::bare::{
+    activator_templates, base_executable, check_active, check_dangerous, check_location,
+    interpreter_link_mode, interpreter_names, matches_existing, VenvPaths,
+};
+use crate::freeze::is_frozen;
+use crate::interpreter::InterpreterInfo;
+use crate::link::LinkMode;
+use crate::metadata::METADATA_FILE;
+use crate::paths::venv_root;
+use crate::{check_options, Context, Error, VenvOptions};
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::Serialize;
+use std::fmt;
+
+/// What happens to the venv location
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PlannedAction {
+    /// Nothing exists at the location yet
+    Create,
+    /// The existing venv matches the interpreter and options and is kept as is
+    Reuse,
+    /// An existing venv or empty directory is replaced
+    Replace,
+    /// A directory that is neither empty nor a venv is replaced because of `--force`
+    ReplaceDirectory,
+}
+
+impl fmt::Display for PlannedAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            PlannedAction::Create => "create",
+            PlannedAction::Reuse => "reuse",
+            PlannedAction::Replace => "replace",
+            PlannedAction::ReplaceDirectory => "replace (not a venv!)",
+        })
+    }
+}
+
+/// A link (or copy) of the base interpreter in `bin`
+#[derive(Debug, Clone, Serialize)]
+pub struct PlannedLink {
+    pub path: Utf8PathBuf,
+    pub target: Utf8PathBuf,
+    /// The mode that is tried first, the actual mode may be a fallback
+    pub link_mode: LinkMode,
+}
+
+/// A seed package and where it's installed from
+#[derive(Debug, Clone, Serialize)]
+pub struct PlannedSeed {
+    pub name: String,
+    pub version: String,
+    /// The cached wheel, the url or `embedded` with the `install` feature, otherwise the unpacked
+    /// wheel in virtualenv's app data
+    pub source: String,
+    /// Whether the wheel needs to be downloaded
+    pub download: bool,
+}
+
+/// Everything creating a venv would do, see [`plan_venv`]
+#[derive(Debug, Clone, Serialize)]
+pub struct VenvPlan {
+    pub location: Utf8PathBuf,
+    pub action: PlannedAction,
+    pub base_python: Utf8PathBuf,
+    pub python_version: String,
+    /// Empty when reusing
+    pub links: Vec<PlannedLink>,
+    /// The files written besides the links and the seed packages, relative to the venv unless
+    /// they are outside of it. Empty when reusing
+    pub files: Vec<Utf8PathBuf>,
+    /// Empty when reusing or bare
+    pub seeds: Vec<PlannedSeed>,
+}
+
+/// Compute what [`create_venv`](crate::create_venv) would do with the same arguments, failing
+/// with the same errors, but without writing anything.
+pub fn plan_venv(
+    ctx: &Context,
+    location: &Utf8Path,
+    base_python: &Utf8Path,
+    info: &InterpreterInfo,
+    options: &VenvOptions,
+) -> Result<VenvPlan, Error> {
+    let location = ctx.absolute(location);
+    let base_python = base_executable(&ctx.absolute(base_python), info);
+    let options = check_options(ctx, &base_python, info, options)?;
+    let mut plan = VenvPlan {
+        location: venv_root(&location, options.no_resolve_symlinks).unwrap_or(location.clone()),
+        action: PlannedAction::Create,
+        base_python: base_python.clone(),
+        python_version: info.python_version.clone(),
+        links: Vec::new(),
+        files: Vec::new(),
+        seeds: Vec::new(),
+    };
+    if !options.rebuild
+        && options.sbom.is_none()
+        && matches_existing(&location, &base_python, info, &options)
+    {
+        plan.action = PlannedAction::Reuse;
+        return Ok(plan);
+    }
+    if is_frozen(&location) {
+        return Err(Error::Frozen(location));
+    }
+    check_active(ctx, &location, options.force)?;
+    check_dangerous(ctx, &location, Some(info), options.allow_dangerous_location)?;
+    plan.action = if check_location(&location, options.force)? {
+        PlannedAction::ReplaceDirectory
+    } else if location.exists() {
+        PlannedAction::Replace
+    } else {
+        PlannedAction::Create
+    };
+
+    let paths = VenvPaths::new(&plan.location, info);
+    let link_mode = interpreter_link_mode(&options, info, &paths.root);
+    for (index, name) in interpreter_names(info).into_iter().enumerate() {
+        // The aliases are relative symlinks to `python` or links of the base interpreter
+        let target = if index > 0 && link_mode == LinkMode::Symlink {
+            Utf8PathBuf::from("python")
+        } else {
+            base_python.clone()
+        };
+        plan.links.push(PlannedLink {
+            path: paths.bin.join(name),
+            target,
+            link_mode,
+        });
+    }
+
+    for (name, target) in &options.aliases {
+        plan.links.push(PlannedLink {
+            path: paths.bin.join(name),
+            target: target.into(),
+            link_mode: LinkMode::Symlink,
+        });
+    }
+
+    let bin = &info.sysconfig_paths.scripts;
+    let site_packages = &info.sysconfig_paths.purelib;
+    if !options.no_gitignore || location.join(".gitignore").is_file() {
+        plan.files.push(".gitignore".into());
+    }
+    if !options.no_cachedir_tag {
+        plan.files.push("CACHEDIR.TAG".into());
+    }
+    plan.files.push("pyvenv.cfg".into());
+    plan.files.push(METADATA_FILE.into());
+    for (name, _template) in activator_templates(options.activator_templates.as_deref())? {
+        plan.files.push(bin.join(name));
+    }
+    plan.files.push(site_packages.join("_virtualenv.py"));
+    plan.files.push(site_packages.join("_virtualenv.pth"));
+    if let Some(sbom) = &options.sbom {
+        plan.files.push(match &sbom.output {
+            Some(output) => ctx.absolute(output),
+            None => "sbom.json".into(),
+        });
+    }
+
+    if !options.bare {
+        #[cfg(feature = "install")]
+        {
+            plan.seeds = crate::packages::planned_seeds(ctx);
+        }
+        #[cfg(not(feature = "install"))]
+        {
+            plan.seeds = crate::virtualenv_cache::planned_seeds(ctx)?;
+        }
+    }
+    Ok(plan)
+}
@@ -0,0 +1,139 @@
+//! Reading and writing `pyvenv.cfg`
+
+use camino::Utf8Path;
+use fs_err as fs;
+use std::fmt;
+use std::io;
+
+/// The entries of a `pyvenv.cfg` in file order.
+///
+/// The format is what `site.py` reads: One `key = value` entry per line, split at the first `=`,
+/// with surrounding whitespace removed. There is no escaping, `site.py` would read escape
+/// sequences literally and windows paths are full of backslashes, so instead we reject entries
+/// that can't be read back: keys containing `=`, and keys or values spanning multiple lines.
+/// A `=` in a value is fine since only the first `=` splits the line. Lines without `=` are
+/// ignored, like `site.py` does.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PyVenvCfg {
+    entries: Vec<(String, String)>,
+}
+
+impl PyVenvCfg {
+    /// Parse the contents of a `pyvenv.cfg`. A repeated key keeps the position of its first
+    /// occurrence and the value of its last one.
+    pub fn parse(contents: &str) -> Self {
+        let mut cfg = Self::default();
+        for (key, value) in contents.lines().filter_map(|line| line.split_once('=')) {
+            cfg.insert(key.trim(), value.trim());
+        }
+        cfg
+    }
+
+    pub fn read(path: &Utf8Path) -> io::Result<Self> {
+        Ok(Self::parse(&fs::read_to_string(path)?))
+    }
+
+    /// Check that the entry can be written such that [`PyVenvCfg::parse`] reads it back.
+    pub fn check_entry(key: &str, value: &str) -> Result<(), String> {
+        if key.trim().is_empty() || key.contains('=') {
+            return Err(format!("`{key}` is not a valid key"));
+        }
+        if key.contains(['\n', '\r']) || value.contains(['\n', '\r']) {
+            return Err(format!("`{key}` must be a single line"));
+        }
+        if key != key.trim() || value != value.trim() {
+            return Err(format!(
+                "`{key}` must not have leading or trailing whitespace"
+            ));
+        }
+        Ok(())
+    }
+
+    /// Set `key` to `value`, replacing the value of an existing entry in place and appending new
+    /// entries at the end.
+    pub fn set(&mut self, key: &str, value: impl Into<String>) -> io::Result<()> {
+        let value = value.into();
+        Self::check_entry(key, &value)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        self.insert(key, value);
+        Ok(())
+    }
+
+    fn insert(&mut self, key: &str, value: impl Into<String>) {
+        let value = value.into();
+        if let Some(entry) = self
+            .entries
+            .iter_mut()
+            .find(|(existing, _)| existing == key)
+        {
+            entry.1 = value;
+        } else {
+            self.entries.push((key.to_string(), value));
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(existing, _)| existing == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The entries in file order
+    pub fn entries(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+    }
+
+    pub fn write(&self, path: &Utf8Path) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+}
+
+impl fmt::Display for PyVenvCfg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (key, value) in &self.entries {
+            writeln!(f, "{key} = {value}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_display_roundtrip() {
+        let contents = "home = C:\\Program Files\\Python312\n\
+            include-system-site-packages=false\n\
+            no entry on this line\n\
+            version = 3.12.1\n\
+            prompt = a = b\n\
+            home = /usr/bin\n";
+        let cfg = PyVenvCfg::parse(contents);
+        assert_eq!(
+            cfg.entries().collect::<Vec<_>>(),
+            [
+                ("home", "/usr/bin"),
+                ("include-system-site-packages", "false"),
+                ("version", "3.12.1"),
+                ("prompt", "a = b"),
+            ]
+        );
+        assert_eq!(PyVenvCfg::parse(&cfg.to_string()), cfg);
+    }
+
+    #[test]
+    fn set_rejects_unreadable_entries() {
+        let mut cfg = PyVenvCfg::default();
+        cfg.set("home", r"C:\Python312").unwrap();
+        cfg.set("prompt", "a = b").unwrap();
+        assert!(cfg.set("a=b", "c").is_err());
+        assert!(cfg.set("prompt", "two\nlines").is_err());
+        assert!(cfg.set("prompt", " padded").is_err());
+        assert!(cfg.set(" ", "value").is_err());
+        assert_eq!(PyVenvCfg::parse(&cfg.to_string()), cfg);
+    }
+}
@@ -1,8 +1,34 @@
 import json
+import os
+import platform
 import sys
+import sysconfig
 from platform import python_version
 
 
+def venv_paths():
+    """The sysconfig install paths of a venv created by this interpreter, relative to its root."""
+    if "venv" in sysconfig.get_scheme_names():
+        # Python 3.11+, which distributors patch to always mean a venv
+        scheme = "venv"
+    elif os.name == "nt":
+        scheme = "nt"
+    else:
+        scheme = "posix_prefix"
+    root = os.path.abspath(os.sep + "venv")
+    config_vars = {
+        "base": root,
+        "platbase": root,
+        "installed_base": root,
+        "installed_platbase": root,
+    }
+    paths = sysconfig.get_paths(scheme=scheme, vars=config_vars)
+    return {
+        key: os.path.relpath(paths[key], root)
+        for key in ["purelib", "platlib", "scripts", "include"]
+    }
+
+
 def main():
     data = {
         "base_exec_prefix": sys.base_exec_prefix,
@@ -10,6 +36,14 @@ def main():
         "major": sys.version_info.major,
         "minor": sys.version_info.minor,
         "python_version": python_version(),
+        "implementation": sys.implementation.name,
+        "platform": sys.platform,
+        "machine": platform.machine(),
+        "soabi": sysconfig.get_config_var("SOABI"),
+        "ext_suffix": sysconfig.get_config_var("EXT_SUFFIX"),
+        "framework": bool(sysconfig.get_config_var("PYTHONFRAMEWORK")),
+        "universal2": sysconfig.get_platform().endswith("-universal2"),
+        "sysconfig_paths": venv_paths(),
     }
     print(json.dumps(data))
 
@@ -0,0 +1,127 @@
+//! Venvs created with `--name`, stored in a central directory and referred to by name
+
+use crate::cache::write_atomic;
+use crate::paths::canonicalize;
+use crate::{Context, Error, VenvOptions};
+use camino::{Utf8Path, Utf8PathBuf};
+use fs_err as fs;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::time::SystemTime;
+
+/// A named venv
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegistryEntry {
+    pub name: String,
+    pub path: Utf8PathBuf,
+    pub python_version: String,
+    /// The base interpreter
+    pub interpreter: Utf8PathBuf,
+    /// Seconds since the unix epoch
+    pub created: i64,
+    pub options: VenvOptions,
+}
+
+/// The JSON file with the [`RegistryEntry`]s
+pub fn registry_path(ctx: &Context) -> io::Result<Utf8PathBuf> {
+    Ok(ctx.data_dir()?.join("venvs.json"))
+}
+
+/// The directory the named venvs are created in, each in a directory of its name
+pub fn named_venvs_dir(ctx: &Context) -> io::Result<Utf8PathBuf> {
+    Ok(ctx.data_dir()?.join("venvs"))
+}
+
+/// Check that a name can be used as directory name and isn't mistaken for a path.
+pub fn check_venv_name(name: &str) -> Result<(), Error> {
+    let valid = !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if valid {
+        Ok(())
+    } else {
+        Err(Error::InvalidVenvName(name.to_string()))
+    }
+}
+
+/// The named venvs sorted by name, an empty list if there are none yet
+pub fn read_registry(ctx: &Context) -> Result<Vec<RegistryEntry>, Error> {
+    let contents = match fs::read_to_string(registry_path(ctx)?) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+    Ok(serde_json::from_str(&contents).map_err(io::Error::from)?)
+}
+
+fn write_registry(ctx: &Context, entries: &[RegistryEntry]) -> Result<(), Error> {
+    let registry = registry_path(ctx)?;
+    if let Some(parent) = registry.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let json = serde_json::to_string_pretty(entries).map_err(io::Error::from)?;
+    write_atomic(&registry, (json + "\n").as_bytes())?;
+    Ok(())
+}
+
+/// Record the venv under its name, replacing an existing entry of the same name.
+pub fn register_venv(
+    ctx: &Context,
+    name: &str,
+    path: &Utf8Path,
+    interpreter: &Utf8Path,
+    python_version: &str,
+    options: &VenvOptions,
+) -> Result<RegistryEntry, Error> {
+    check_venv_name(name)?;
+    let entry = RegistryEntry {
+        name: name.to_string(),
+        path: path.to_path_buf(),
+        python_version: python_version.to_string(),
+        interpreter: interpreter.to_path_buf(),
+        created: SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs() as i64),
+        options: options.clone(),
+    };
+    let mut entries = read_registry(ctx)?;
+    entries.retain(|existing| existing.name != name);
+    entries.push(entry.clone());
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    write_registry(ctx, &entries)?;
+    Ok(entry)
+}
+
+/// Remove the entries for the venv at `location`, returning their names.
+pub fn unregister_venv(ctx: &Context, location: &Utf8Path) -> Result<Vec<String>, Error> {
+    let location = ctx.absolute(location);
+    let location = canonicalize(&location).unwrap_or(location);
+    let mut entries = read_registry(ctx)?;
+    let mut removed = Vec::new();
+    entries.retain(|entry| {
+        let matches = entry.path == location;
+        if matches {
+            removed.push(entry.name.clone());
+        }
+        !matches
+    });
+    if !removed.is_empty() {
+        write_registry(ctx, &entries)?;
+    }
+    Ok(removed)
+}
+
+/// The location of the venv given to a subcommand: A path that exists is used as is, otherwise a
+/// registered name is resolved to its path. Unknown names are returned unchanged, so the error
+/// mentions the path the user passed.
+pub fn resolve_venv(ctx: &Context, path: &Utf8Path) -> Result<Utf8PathBuf, Error> {
+    if ctx.absolute(path).exists() || check_venv_name(path.as_str()).is_err() {
+        return Ok(path.to_path_buf());
+    }
+    Ok(read_registry(ctx)?
+        .into_iter()
+        .find(|entry| entry.name == path.as_str())
+        .map_or_else(|| path.to_path_buf(), |entry| entry.path))
+}
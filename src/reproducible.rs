@@ -0,0 +1,83 @@
+//! Normalize a venv so that two runs with the same inputs produce bit-identical venvs, e.g. for
+//! build caching
+
+use crate::{Context, Error};
+use camino::Utf8Path;
+use filetime::FileTime;
+use fs_err as fs;
+use std::io;
+use tracing::debug;
+
+/// The earliest timestamp a zip file (and so a wheel) can represent, 1980-01-01
+const DEFAULT_EPOCH: i64 = 315_532_800;
+
+/// The timestamp for all files, `SOURCE_DATE_EPOCH` if set.
+///
+/// <https://reproducible-builds.org/specs/source-date-epoch/>
+pub(crate) fn source_date_epoch(ctx: &Context) -> Result<i64, Error> {
+    match ctx.var("SOURCE_DATE_EPOCH") {
+        Some(epoch) => epoch
+            .trim()
+            .parse()
+            .map_err(|_| Error::InvalidSourceDateEpoch(epoch.to_string())),
+        None => Ok(DEFAULT_EPOCH),
+    }
+}
+
+/// Set the timestamps of all files and directories in `root` to `epoch` and the source timestamp
+/// in the headers of timestamp-based `.pyc` files along with them, so the `.pyc` files stay valid.
+///
+/// Files that are hardlinked elsewhere, e.g. a hardlinked base interpreter, are left alone, since
+/// changing their timestamp would change the original too.
+pub(crate) fn normalize_venv(root: &Utf8Path, epoch: i64) -> io::Result<()> {
+    debug!("Normalizing timestamps in {root} to {epoch}");
+    normalize_dir(root.as_std_path(), FileTime::from_unix_time(epoch, 0))
+}
+
+fn normalize_dir(dir: &std::path::Path, epoch: FileTime) -> io::Result<()> {
+    let mut entries = fs::read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let path = entry.path();
+        let file_type = entry.file_type()?;
+        if file_type.is_symlink() {
+            filetime::set_symlink_file_times(&path, epoch, epoch)?;
+        } else if file_type.is_dir() {
+            normalize_dir(&path, epoch)?;
+        } else if !is_shared_hardlink(&path)? {
+            if path.extension().is_some_and(|extension| extension == "pyc") {
+                normalize_pyc(&path, epoch)?;
+            }
+            filetime::set_file_times(&path, epoch, epoch)?;
+        }
+    }
+    // Only after the children, since creating or replacing files changes the directory timestamp
+    filetime::set_file_times(dir, epoch, epoch)
+}
+
+fn is_shared_hardlink(path: &std::path::Path) -> io::Result<bool> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        Ok(fs::metadata(path)?.nlink() > 1)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = path;
+        Ok(false)
+    }
+}
+
+/// Set the source mtime in the header of a timestamp-based `.pyc` file (PEP 552).
+///
+/// The header is the magic number, a flags field that is zero for timestamp-based `.pyc` files, the
+/// source mtime and the source size, each four bytes.
+fn normalize_pyc(path: &std::path::Path, epoch: FileTime) -> io::Result<()> {
+    let mut contents = fs::read(path)?;
+    let timestamp = (epoch.unix_seconds() as u32).to_le_bytes();
+    if contents.len() < 16 || contents[4..8] != [0; 4] || contents[8..12] == timestamp {
+        return Ok(());
+    }
+    contents[8..12].copy_from_slice(&timestamp);
+    fs::write(path, contents)
+}
@@ -0,0 +1,227 @@
+//! Run commands in a venv without activating it in the shell
+
+use crate::bare::{bin_dir, read_pyvenv_cfg, venv_python};
+use crate::paths::canonicalize;
+use crate::{Context, Error};
+use camino::{Utf8Path, Utf8PathBuf};
+use fs_err as fs;
+use std::collections::BTreeMap;
+use std::env;
+use std::io;
+use std::process::{Command, ExitStatus, Stdio};
+use tempfile::TempDir;
+
+/// The environment of [`Context::env`] with the venv at `location` activated, like after sourcing
+/// its `activate` script: `VIRTUAL_ENV` and `VIRTUAL_ENV_PROMPT` are set, the bin dir is prepended
+/// to `PATH` and `PYTHONHOME` is removed.
+pub fn activated_env(
+    ctx: &Context,
+    location: &Utf8Path,
+) -> Result<BTreeMap<String, String>, Error> {
+    let location = &ctx.absolute(location);
+    let cfg = read_pyvenv_cfg(location)?;
+    let root = canonicalize(location)?;
+    let prompt = cfg
+        .get("prompt")
+        .map(str::to_string)
+        .unwrap_or_else(|| root.file_name().unwrap_or_default().to_string());
+
+    let mut env = ctx.env.clone();
+    // `Path` on windows, where the variable names are case-insensitive
+    let path_key = env
+        .keys()
+        .find(|key| key.eq_ignore_ascii_case("PATH"))
+        .cloned()
+        .unwrap_or_else(|| "PATH".to_string());
+    let bin = bin_dir(&root).into_std_path_buf();
+    let old_path = env.remove(&path_key).unwrap_or_default();
+    let path = env::join_paths(std::iter::once(bin).chain(env::split_paths(&old_path)))
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+    env.insert(path_key, path.to_string_lossy().to_string());
+    env.insert("VIRTUAL_ENV".to_string(), root.to_string());
+    env.insert("VIRTUAL_ENV_PROMPT".to_string(), prompt);
+    env.remove("PYTHONHOME");
+    Ok(env)
+}
+
+/// Run `program` with `args` in the venv at `location`, see [`activated_env`].
+///
+/// The program is looked up in the `PATH` of the venv, so `python` and the console scripts of
+/// the installed packages are found.
+pub fn run_in_venv(
+    ctx: &Context,
+    location: &Utf8Path,
+    program: &str,
+    args: &[String],
+) -> Result<ExitStatus, Error> {
+    let env = activated_env(ctx, location)?;
+    let path = env
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case("PATH"))
+        .map(|(_, path)| path.clone());
+    let executable = which::which_in(program, path, &ctx.cwd).map_err(|err| Error::Run {
+        command: program.to_string(),
+        err: io::Error::new(io::ErrorKind::NotFound, err),
+    })?;
+    Command::new(executable)
+        .args(args)
+        .env_clear()
+        .envs(&env)
+        .current_dir(&ctx.cwd)
+        .status()
+        .map_err(|err| Error::Run {
+            command: program.to_string(),
+            err,
+        })
+}
+
+/// Install the requirements files into the venv at `location` with the venv's pip.
+///
+/// pip's output is streamed to stderr, so stdout stays clean for machine-readable output.
+pub fn install_requirements(
+    ctx: &Context,
+    location: &Utf8Path,
+    requirements: &[Utf8PathBuf],
+) -> Result<(), Error> {
+    let location = &ctx.absolute(location);
+    let env = activated_env(ctx, location)?;
+    let python = venv_python(&canonicalize(location)?);
+    let mut command = Command::new(&python);
+    command.args(["-m", "pip", "install", "--disable-pip-version-check"]);
+    for requirement in requirements {
+        command.arg("-r").arg(ctx.absolute(requirement));
+    }
+    let status = command
+        .env_clear()
+        .envs(&env)
+        .current_dir(&ctx.cwd)
+        .stdout(Stdio::from(io::stderr()))
+        .status()
+        .map_err(|err| Error::Run {
+            command: format!("{python} -m pip"),
+            err,
+        })?;
+    if !status.success() {
+        return Err(Error::InstallRequirements {
+            location: location.to_path_buf(),
+            status,
+        });
+    }
+    Ok(())
+}
+
+/// Start an interactive shell with the venv at `location` activated and wait for it to exit.
+///
+/// The shell is `$SHELL` (`%COMSPEC%` on windows). For bash, zsh, fish, nushell and PowerShell,
+/// the shell sources the venv's activator after the user's own configuration, so the prompt shows
+/// the venv and `deactivate` works. Other shells get the activated environment without a prompt
+/// change. Exiting the shell returns to the unchanged parent shell.
+pub fn spawn_shell(ctx: &Context, location: &Utf8Path) -> Result<ExitStatus, Error> {
+    let location = &ctx.absolute(location);
+    read_pyvenv_cfg(location)?;
+    let root = canonicalize(location)?;
+    let bin = bin_dir(&root);
+    let shell = if cfg!(windows) {
+        ctx.var("COMSPEC").unwrap_or("cmd.exe")
+    } else {
+        ctx.var("SHELL").unwrap_or("sh")
+    };
+    let shell_name = Utf8Path::new(shell)
+        .file_stem()
+        .unwrap_or_default()
+        .to_lowercase();
+    let mut command = Command::new(shell);
+    command.env_clear().current_dir(&ctx.cwd);
+    // Keeps the generated rc files alive while the shell is running
+    let _rc_dir = match shell_name.as_str() {
+        "bash" => {
+            let rc_dir = TempDir::new()?;
+            let rcfile = rc_dir.path().join("bashrc");
+            fs::write(
+                &rcfile,
+                format!(
+                    "[ -f ~/.bashrc ] && . ~/.bashrc\n. {}\n",
+                    sh_quote(bin.join("activate").as_str())
+                ),
+            )?;
+            command.arg("--rcfile").arg(rcfile).arg("-i").envs(&ctx.env);
+            Some(rc_dir)
+        }
+        "zsh" => {
+            // zsh reads its rc files from `$ZDOTDIR`, so we point it to files that source the
+            // user's files and then activate the venv
+            let user_dir = ctx
+                .var("ZDOTDIR")
+                .or_else(|| ctx.var("HOME"))
+                .unwrap_or_default()
+                .to_string();
+            let rc_dir = TempDir::new()?;
+            for name in [".zshenv", ".zprofile", ".zlogin"] {
+                fs::write(
+                    rc_dir.path().join(name),
+                    format!("[ -f \"$USER_ZDOTDIR/{name}\" ] && . \"$USER_ZDOTDIR/{name}\"\n"),
+                )?;
+            }
+            fs::write(
+                rc_dir.path().join(".zshrc"),
+                format!(
+                    "ZDOTDIR=\"$USER_ZDOTDIR\"\nunset USER_ZDOTDIR\n\
+                    [ -f \"$ZDOTDIR/.zshrc\" ] && . \"$ZDOTDIR/.zshrc\"\n. {}\n",
+                    sh_quote(bin.join("activate").as_str())
+                ),
+            )?;
+            command
+                .envs(&ctx.env)
+                .env("USER_ZDOTDIR", user_dir)
+                .env("ZDOTDIR", rc_dir.path())
+                .arg("-i");
+            Some(rc_dir)
+        }
+        "fish" => {
+            let activate = bin.join("activate.fish");
+            let activate = activate.as_str().replace('\\', r"\\").replace('\'', r"\'");
+            command
+                .arg("--init-command")
+                .arg(format!("source '{activate}'"))
+                .envs(&ctx.env);
+            None
+        }
+        "nu" => {
+            command
+                .arg("--execute")
+                .arg(format!(
+                    "overlay use r#'{}'#",
+                    bin.join("activate.nu").as_str()
+                ))
+                .envs(&ctx.env);
+            None
+        }
+        "pwsh" | "powershell" => {
+            let activate = bin.join("activate.ps1").as_str().replace('\'', "''");
+            command
+                .arg("-NoExit")
+                .arg("-Command")
+                .arg(format!(". '{activate}'"))
+                .envs(&ctx.env);
+            None
+        }
+        _ => {
+            let mut env = activated_env(ctx, &root)?;
+            if let Some(ps1) = env.get("PS1") {
+                let ps1 = format!("({}) {ps1}", env["VIRTUAL_ENV_PROMPT"]);
+                env.insert("PS1".to_string(), ps1);
+            }
+            command.envs(&env);
+            None
+        }
+    };
+    command.status().map_err(|err| Error::Run {
+        command: shell.to_string(),
+        err,
+    })
+}
+
+/// Quote `arg` for a POSIX shell.
+fn sh_quote(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', r"'\''"))
+}
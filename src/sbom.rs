@@ -0,0 +1,98 @@
+//! A manifest of the seeded packages for supply-chain auditing
+
+use camino::{Utf8Path, Utf8PathBuf};
+use clap::ValueEnum;
+use fs_err as fs;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::io;
+
+/// The format of the seed package manifest
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SbomFormat {
+    /// A plain list of the packages
+    #[default]
+    Json,
+    /// A CycloneDX 1.5 JSON BOM
+    #[value(name = "cyclonedx")]
+    #[serde(rename = "cyclonedx")]
+    CycloneDx,
+}
+
+/// Where and how to write the manifest of the seeded packages
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SbomOptions {
+    /// Relative to the context, `sbom.json` in the venv if `None`
+    pub output: Option<Utf8PathBuf>,
+    pub format: SbomFormat,
+}
+
+/// A package installed into the venv while seeding
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SeededPackage {
+    pub name: String,
+    pub version: String,
+    pub filename: String,
+    /// The hash of the wheel, if we installed from a wheel file
+    pub sha256: Option<String>,
+    /// Where the wheel was downloaded from, if we downloaded it
+    pub url: Option<String>,
+}
+
+/// The hex SHA256 of a file
+pub(crate) fn sha256_file(path: &Utf8Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut fs::File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Write the manifest of `packages` to `path`.
+pub(crate) fn write_sbom(
+    path: &Utf8Path,
+    packages: &[SeededPackage],
+    format: SbomFormat,
+) -> io::Result<()> {
+    let sbom = match format {
+        SbomFormat::Json => json!({
+            "generator": format!("gourgeist {}", env!("CARGO_PKG_VERSION")),
+            "packages": packages,
+        }),
+        SbomFormat::CycloneDx => {
+            let components: Vec<_> = packages
+                .iter()
+                .map(|package| {
+                    let mut component = json!({
+                        "type": "library",
+                        "name": package.name,
+                        "version": package.version,
+                        "purl": format!("pkg:pypi/{}@{}", package.name, package.version),
+                    });
+                    if let Some(sha256) = &package.sha256 {
+                        component["hashes"] = json!([{"alg": "SHA-256", "content": sha256}]);
+                    }
+                    if let Some(url) = &package.url {
+                        component["externalReferences"] =
+                            json!([{"type": "distribution", "url": url}]);
+                    }
+                    component
+                })
+                .collect();
+            json!({
+                "bomFormat": "CycloneDX",
+                "specVersion": "1.5",
+                "version": 1,
+                "metadata": {
+                    "tools": [{"name": "gourgeist", "version": env!("CARGO_PKG_VERSION")}],
+                },
+                "components": components,
+            })
+        }
+    };
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, serde_json::to_string_pretty(&sbom)? + "\n")
+}
@@ -0,0 +1,241 @@
+//! Naming and discovery of the console and GUI scripts of installed packages
+
+use crate::entry_points::parse_entry_points;
+use crate::paths::normalized;
+use crate::Error;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use camino::{Utf8Path, Utf8PathBuf};
+use fs_err as fs;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io;
+use tracing::debug;
+
+/// How the console scripts of the seed packages are named in `bin`, e.g. to avoid clashes when
+/// the bin directories of multiple venvs are on `PATH` at the same time.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScriptNaming {
+    /// Prepended to each script name, e.g. `g-` for `g-pip`
+    pub prefix: String,
+    /// Appended to each script name, e.g. `-g` for `pip-g`
+    pub suffix: String,
+    /// Packages that don't get any scripts, compared after [`normalize_name`]
+    pub skip: Vec<String>,
+}
+
+impl ScriptNaming {
+    /// The file name of the launcher for the script `name` of `package`, or `None` if no scripts
+    /// should be generated for `package`.
+    pub fn script_name(&self, package: &str, name: &str) -> Option<String> {
+        let package = normalize_name(package);
+        if self.skip.iter().any(|skip| normalize_name(skip) == package) {
+            return None;
+        }
+        Some(format!("{}{}{}", self.prefix, name, self.suffix))
+    }
+
+    /// Whether all scripts keep their original name
+    pub fn is_identity(&self) -> bool {
+        self.prefix.is_empty() && self.suffix.is_empty() && self.skip.is_empty()
+    }
+}
+
+/// Normalize a package name as specified in PEP 503, e.g. `Foo.Bar_baz` to `foo-bar-baz`.
+pub fn normalize_name(name: &str) -> String {
+    let mut normalized = String::with_capacity(name.len());
+    let mut last_was_separator = false;
+    for char in name.chars() {
+        if matches!(char, '-' | '_' | '.') {
+            if !last_was_separator {
+                normalized.push('-');
+            }
+            last_was_separator = true;
+        } else {
+            normalized.push(char.to_ascii_lowercase());
+            last_was_separator = false;
+        }
+    }
+    normalized
+}
+
+/// A launcher from the `[console_scripts]` or `[gui_scripts]` of an `entry_points.txt`
+// With the `install` feature, install-wheel-rs generates the launchers and we only rename them
+#[cfg_attr(feature = "install", allow(dead_code))]
+#[derive(Debug, Clone)]
+pub(crate) struct Script {
+    pub(crate) name: String,
+    /// The module to import from
+    pub(crate) module: String,
+    /// The (possibly dotted) function to call
+    pub(crate) function: String,
+    /// GUI scripts run with `pythonw` on windows, so they don't open a console window. On unix,
+    /// they are the same as console scripts.
+    pub(crate) gui: bool,
+}
+
+/// The `[console_scripts]` and `[gui_scripts]` from the `entry_points.txt` in a `.dist-info`
+/// directory.
+pub(crate) fn read_scripts(dist_info: &Utf8Path) -> Result<Vec<Script>, Error> {
+    let entry_points = dist_info.join("entry_points.txt");
+    if !entry_points.is_file() {
+        return Ok(Vec::new());
+    }
+    let mut groups =
+        parse_entry_points(&fs::read_to_string(&entry_points)?).map_err(|message| {
+            Error::InvalidEntryPoints {
+                path: entry_points.clone(),
+                message,
+            }
+        })?;
+    let mut scripts = Vec::new();
+    for (group, gui) in [("console_scripts", false), ("gui_scripts", true)] {
+        for entry_point in groups.remove(group).unwrap_or_default() {
+            // Scripts need a function to call, the extras are irrelevant for the launcher
+            let function = entry_point
+                .object
+                .ok_or_else(|| Error::InvalidEntryPoints {
+                    path: entry_points.clone(),
+                    message: format!("The script {} has no function", entry_point.name),
+                })?;
+            scripts.push(Script {
+                name: entry_point.name,
+                module: entry_point.module,
+                function,
+                gui,
+            });
+        }
+    }
+    Ok(scripts)
+}
+
+/// A shebang that runs the `python` next to the script through `/bin/sh`, so the scripts keep
+/// working when the venv is moved. The second line is an `exec` for sh and a string for python.
+pub(crate) const RELOCATABLE_SHEBANG: &str = "#!/bin/sh\n\
+    '''exec' \"$(dirname -- \"$(realpath -- \"$0\")\")\"/'python' \"$0\" \"$@\"\n\
+    ' '''\n";
+
+/// The longest shebang line the kernel reads, see distlib's `_build_shebang`
+const MAX_SHEBANG_LENGTH: usize = if cfg!(target_os = "macos") { 512 } else { 127 };
+
+/// The shebang line for a script run with `python`, including the newline.
+///
+/// If the path contains spaces or is too long for the kernel, we use the `/bin/sh` exec trick like
+/// pip and distlib do: The second line is an `exec` for sh and a string for python.
+pub(crate) fn shebang(python: &Utf8Path) -> String {
+    let shebang = format!("#!{python}");
+    if shebang.len() <= MAX_SHEBANG_LENGTH && !python.as_str().contains(char::is_whitespace) {
+        return format!("{shebang}\n");
+    }
+    let quoted = format!("'{}'", python.as_str().replace('\'', r"'\''"));
+    format!("#!/bin/sh\n'''exec' {quoted} \"$0\" \"$@\"\n' '''\n")
+}
+
+/// Replace the shebang for `interpreter` of the scripts in `bin` with `shebang`, which also covers
+/// the scripts generated by an installer.
+pub(crate) fn rewrite_shebangs(
+    bin: &Utf8Path,
+    interpreter: &Utf8Path,
+    new_shebang: &str,
+) -> io::Result<()> {
+    // Either what an installer writes or what we write
+    let old_shebangs = [format!("#!{interpreter}\n"), shebang(interpreter)];
+    for entry in fs::read_dir(bin)? {
+        let entry = entry?;
+        // Skip the interpreter links
+        if !entry.file_type()?.is_file() {
+            continue;
+        }
+        let contents = fs::read(entry.path())?;
+        if contents.starts_with(new_shebang.as_bytes()) {
+            continue;
+        }
+        let script = old_shebangs
+            .iter()
+            .find_map(|old_shebang| contents.strip_prefix(old_shebang.as_bytes()));
+        if let Some(script) = script {
+            debug!("Rewriting the shebang of {}", entry.path().display());
+            fs::write(entry.path(), [new_shebang.as_bytes(), script].concat())?;
+        }
+    }
+    Ok(())
+}
+
+/// Rename or remove the launchers that an installer generated with the original script names, and
+/// update their rows in the `RECORD` so uninstalling and verifying the package find them.
+#[cfg_attr(not(feature = "install"), allow(dead_code))]
+pub(crate) fn apply_script_naming(
+    bin: &Utf8Path,
+    dist_info: &Utf8Path,
+    package: &str,
+    naming: &ScriptNaming,
+) -> Result<(), Error> {
+    if naming.is_identity() {
+        return Ok(());
+    }
+    // The old launcher and its new path, `None` if it was removed
+    let mut changed = Vec::new();
+    for Script { name, .. } in read_scripts(dist_info)? {
+        // On windows, the launchers are `.exe` files
+        for extension in ["", ".exe"] {
+            let launcher = bin.join(format!("{name}{extension}"));
+            if !launcher.is_file() {
+                continue;
+            }
+            if let Some(new_name) = naming.script_name(package, &name) {
+                debug!("Renaming launcher {name} to {new_name}");
+                let renamed = bin.join(format!("{new_name}{extension}"));
+                fs::rename(&launcher, &renamed)?;
+                changed.push((launcher, Some(renamed)));
+            } else {
+                debug!("Removing launcher {name} of {package}");
+                fs::remove_file(&launcher)?;
+                changed.push((launcher, None));
+            }
+        }
+    }
+    if !changed.is_empty() {
+        update_record(dist_info, &changed)?;
+    }
+    Ok(())
+}
+
+/// Point the `RECORD` rows of renamed launchers to their new name with the new hash and drop the
+/// rows of removed ones.
+fn update_record(
+    dist_info: &Utf8Path,
+    changed: &[(Utf8PathBuf, Option<Utf8PathBuf>)],
+) -> io::Result<()> {
+    let record = dist_info.join("RECORD");
+    if !record.is_file() {
+        return Ok(());
+    }
+    let site_packages = dist_info.parent().unwrap_or(dist_info);
+    let contents = fs::read_to_string(&record)?;
+    let mut updated = String::with_capacity(contents.len());
+    for line in contents.lines() {
+        // Script paths don't contain commas, so they are never quoted
+        let path = Utf8Path::new(line.split(',').next().unwrap_or_default());
+        let absolute = normalized(&site_packages.join(path));
+        let change = changed
+            .iter()
+            .find(|(old, _)| absolute.as_deref() == Some(old.as_path()));
+        match change {
+            Some((_, Some(new))) => {
+                let contents = fs::read(new)?;
+                let hash = URL_SAFE_NO_PAD.encode(Sha256::digest(&contents));
+                let new_path = path.with_file_name(new.file_name().unwrap_or_default());
+                updated.push_str(&format!("{new_path},sha256={hash},{}\n", contents.len()));
+            }
+            Some((_, None)) => {}
+            None => {
+                updated.push_str(line);
+                updated.push('\n');
+            }
+        }
+    }
+    // Write a new file, the old one may be linked to the store
+    fs::remove_file(&record)?;
+    fs::write(&record, updated)
+}
@@ -0,0 +1,241 @@
+//! Shell integration: hooks that auto-activate allowed venvs when entering their directory
+
+use crate::bare::{bin_dir, keeps_symlinks};
+use crate::paths::{canonicalize, venv_root};
+use crate::{Context, Error};
+use camino::{Utf8Path, Utf8PathBuf};
+use clap::ValueEnum;
+use fs_err as fs;
+use std::io;
+
+/// The shells we can generate hooks and activation snippets for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl Shell {
+    /// The user's shell from `$SHELL`, `None` if it's not one we support.
+    pub fn detect(ctx: &Context) -> Option<Self> {
+        let shell = Utf8Path::new(ctx.var("SHELL")?).file_name()?;
+        match shell {
+            "bash" => Some(Shell::Bash),
+            "zsh" => Some(Shell::Zsh),
+            "fish" => Some(Shell::Fish),
+            _ => None,
+        }
+    }
+}
+
+/// The file listing the venvs the shell hook may activate, one canonical path per line.
+pub fn allowlist_path(ctx: &Context) -> io::Result<Utf8PathBuf> {
+    Ok(ctx.data_dir()?.join("allowed-venvs"))
+}
+
+/// Read the allowlist, an empty list if it doesn't exist yet
+pub fn read_allowlist(ctx: &Context) -> Result<Vec<Utf8PathBuf>, Error> {
+    let allowlist = allowlist_path(ctx)?;
+    if !allowlist.is_file() {
+        return Ok(Vec::new());
+    }
+    Ok(fs::read_to_string(allowlist)?
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(Utf8PathBuf::from)
+        .collect())
+}
+
+fn write_allowlist(ctx: &Context, venvs: &[Utf8PathBuf]) -> Result<(), Error> {
+    let allowlist = allowlist_path(ctx)?;
+    if let Some(parent) = allowlist.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut contents = String::new();
+    for venv in venvs {
+        contents.push_str(venv.as_str());
+        contents.push('\n');
+    }
+    fs::write(allowlist, contents)?;
+    Ok(())
+}
+
+/// Allow the shell hook to activate the venv at `location`.
+///
+/// Only venvs created by gourgeist can be allowed, since the hook checks for our `pyvenv.cfg` key.
+pub fn allow_venv(ctx: &Context, location: &Utf8Path) -> Result<Utf8PathBuf, Error> {
+    let location = &ctx.absolute(location);
+    let pyvenv_cfg = location.join("pyvenv.cfg");
+    let created_by_gourgeist = pyvenv_cfg.is_file()
+        && fs::read_to_string(&pyvenv_cfg)?
+            .lines()
+            .any(|line| line.starts_with("gourgeist ="));
+    if !created_by_gourgeist {
+        return Err(Error::NotAGourgeistVenv(location.to_path_buf()));
+    }
+    let location = canonicalize(location)?;
+    let mut venvs = read_allowlist(ctx)?;
+    if !venvs.contains(&location) {
+        venvs.push(location.clone());
+        write_allowlist(ctx, &venvs)?;
+    }
+    Ok(location)
+}
+
+/// Remove the venv at `location` from the allowlist, returning whether it was allowed before.
+pub fn disallow_venv(ctx: &Context, location: &Utf8Path) -> Result<bool, Error> {
+    let location = &ctx.absolute(location);
+    // The venv may already be deleted
+    let location = canonicalize(location).unwrap_or_else(|_| location.to_path_buf());
+    let mut venvs = read_allowlist(ctx)?;
+    let len_before = venvs.len();
+    venvs.retain(|venv| venv != &location);
+    if venvs.len() == len_before {
+        return Ok(false);
+    }
+    write_allowlist(ctx, &venvs)?;
+    Ok(true)
+}
+
+/// A shell function that activates `.venv` when entering a directory that contains a venv created
+/// by gourgeist which is on the allowlist, to be evaluated in the shell's rc file.
+///
+/// The venv stays active in subdirectories and is deactivated when leaving the directory, but only
+/// if the hook activated it: A venv the user activated manually is never touched.
+pub fn shell_hook(ctx: &Context, shell: Shell) -> Result<String, Error> {
+    let allowlist = allowlist_path(ctx)?;
+    let hook = match shell {
+        Shell::Bash | Shell::Zsh => {
+            let install = if shell == Shell::Bash {
+                r#"case ";${PROMPT_COMMAND-};" in
+    *";_gourgeist_hook;"*) ;;
+    *) PROMPT_COMMAND="_gourgeist_hook${PROMPT_COMMAND:+;$PROMPT_COMMAND}" ;;
+esac"#
+            } else {
+                r#"autoload -Uz add-zsh-hook
+add-zsh-hook chpwd _gourgeist_hook
+_gourgeist_hook"#
+            };
+            format!(
+                r#"_gourgeist_hook() {{
+    local dir="$PWD" venv=""
+    # The closest `.venv` in this directory or its parents
+    while [ -n "$dir" ]; do
+        if [ -f "$dir/.venv/pyvenv.cfg" ] && grep -q '^gourgeist =' "$dir/.venv/pyvenv.cfg" 2>/dev/null; then
+            venv="$(cd "$dir/.venv" && pwd -P)" || venv=""
+            break
+        fi
+        dir="${{dir%/*}}"
+    done
+    if [ -n "$venv" ] && ! grep -qxF "$venv" '{allowlist}' 2>/dev/null; then
+        venv=""
+    fi
+    [ "${{VIRTUAL_ENV-}}" = "$venv" ] && return 0
+    if [ -n "${{_GOURGEIST_VENV-}}" ] && [ "${{VIRTUAL_ENV-}}" = "$_GOURGEIST_VENV" ]; then
+        deactivate
+    fi
+    unset _GOURGEIST_VENV
+    if [ -n "$venv" ] && [ -z "${{VIRTUAL_ENV-}}" ]; then
+        . "$venv/bin/activate"
+        _GOURGEIST_VENV="$venv"
+    fi
+}}
+{install}
+"#
+            )
+        }
+        Shell::Fish => format!(
+            r#"function _gourgeist_hook --on-variable PWD
+    set -l dir $PWD
+    set -l venv
+    # The closest `.venv` in this directory or its parents
+    while test -n "$dir"
+        if test -f "$dir/.venv/pyvenv.cfg"; and grep -q '^gourgeist =' "$dir/.venv/pyvenv.cfg" 2>/dev/null
+            set venv (builtin realpath "$dir/.venv")
+            break
+        end
+        set dir (string replace -r '/[^/]*$' '' -- $dir)
+    end
+    if test -n "$venv"; and not grep -qxF "$venv" '{allowlist}' 2>/dev/null
+        set venv
+    end
+    test "$VIRTUAL_ENV" = "$venv"; and return 0
+    if set -q _GOURGEIST_VENV; and test "$VIRTUAL_ENV" = "$_GOURGEIST_VENV"
+        deactivate
+    end
+    set -e _GOURGEIST_VENV
+    if test -n "$venv"; and test -z "$VIRTUAL_ENV"
+        source "$venv/bin/activate.fish"
+        set -g _GOURGEIST_VENV $venv
+    end
+end
+_gourgeist_hook
+"#
+        ),
+    };
+    Ok(hook)
+}
+
+/// Shell code that activates the venv at `location` in the current shell, for
+/// `eval "$(gourgeist activate <venv>)"`.
+pub fn activate_snippet(ctx: &Context, location: &Utf8Path, shell: Shell) -> Result<String, Error> {
+    let location = &ctx.absolute(location);
+    if !location.join("pyvenv.cfg").is_file() {
+        return Err(Error::NotAVenv(location.to_path_buf()));
+    }
+    let bin = bin_dir(&venv_root(location, keeps_symlinks(location))?);
+    Ok(match shell {
+        Shell::Bash | Shell::Zsh => format!(
+            ". '{}'\n",
+            bin.join("activate").as_str().replace('\'', r"'\''")
+        ),
+        Shell::Fish => format!(
+            "source '{}'\n",
+            bin.join("activate.fish")
+                .as_str()
+                .replace('\\', r"\\")
+                .replace('\'', r"\'")
+        ),
+    })
+}
+
+/// Make direnv activate the venv at `location` whenever the user enters its parent directory, by
+/// adding an activation snippet to the `.envrc` there. An existing `.envrc` is appended to.
+///
+/// Returns the path of the `.envrc`, or `None` if it already activates the venv. direnv only
+/// loads the changed file after `direnv allow`.
+pub fn write_envrc(ctx: &Context, location: &Utf8Path) -> Result<Option<Utf8PathBuf>, Error> {
+    let location = &ctx.absolute(location);
+    if !location.join("pyvenv.cfg").is_file() {
+        return Err(Error::NotAVenv(location.to_path_buf()));
+    }
+    let location = canonicalize(location)?;
+    let (Some(parent), Some(name)) = (location.parent(), location.file_name()) else {
+        return Err(Error::NotAVenv(location.to_path_buf()));
+    };
+    let bin = bin_dir(&location);
+    let bin = bin.strip_prefix(&location).unwrap_or(&bin);
+    // Like direnv's `layout python`, but with the existing venv
+    let snippet = format!(
+        "export VIRTUAL_ENV=\"$(expand_path '{}')\"\nPATH_add \"$VIRTUAL_ENV/{bin}\"\nunset PYTHONHOME\n",
+        name.replace('\'', r"'\''")
+    );
+    let envrc = parent.join(".envrc");
+    let existing = match fs::read_to_string(&envrc) {
+        Ok(existing) => existing,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(err.into()),
+    };
+    if existing.contains(&snippet) {
+        return Ok(None);
+    }
+    let mut contents = existing;
+    if !contents.is_empty() && !contents.ends_with('\n') {
+        contents.push('\n');
+    }
+    contents.push_str("# Activate the venv created by gourgeist\n");
+    contents.push_str(&snippet);
+    fs::write(&envrc, contents)?;
+    Ok(Some(envrc))
+}
@@ -0,0 +1,231 @@
+//! A content-addressed store of installed package files shared between venvs
+//!
+//! Each file of an installed seed package is stored once under its sha256 in `objects`, and a
+//! manifest per wheel and venv layout lists which object goes where. Seeding a venv from the store
+//! only links the objects into it, so many venvs of the same packages cost almost no extra disk.
+
+use crate::bare::VenvPaths;
+use crate::cache::{write_atomic, CacheBucket};
+use crate::durable::sync_file;
+use crate::link::LinkMode;
+use crate::paths::normalized;
+use crate::sbom::sha256_file;
+use crate::scripts::shebang;
+use crate::{Context, Error};
+use camino::{Utf8Path, Utf8PathBuf};
+use fs_err as fs;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io;
+use tempfile::NamedTempFile;
+use tracing::{debug, warn};
+
+/// The shebang of the console scripts in the store, replaced by the shebang for the python of
+/// each venv, like the placeholder in wheels
+const STORE_SHEBANG: &str = "#!python\n";
+
+/// Part of the manifest keys, see [`PackageStore::key`]
+const STORE_VERSION: u32 = 2;
+
+/// The files of one installed wheel, relative to the venv root
+#[derive(Debug, Serialize, Deserialize)]
+struct Manifest {
+    files: Vec<ManifestFile>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ManifestFile {
+    path: Utf8PathBuf,
+    sha256: String,
+}
+
+pub(crate) struct PackageStore {
+    root: Utf8PathBuf,
+    link_mode: LinkMode,
+}
+
+impl PackageStore {
+    pub(crate) fn new(ctx: &Context, link_mode: LinkMode) -> io::Result<Self> {
+        Ok(Self {
+            root: CacheBucket::Store.create(ctx)?,
+            link_mode,
+        })
+    }
+
+    /// The manifest key of a wheel installed into a venv with the layout of `paths`. The layout
+    /// is part of the key since the manifest paths are relative to the venv root.
+    ///
+    /// The version is bumped whenever the meaning of the stored files changes, e.g. since the
+    /// console scripts are stored with [`STORE_SHEBANG`].
+    pub(crate) fn key(wheel_sha256: &str, paths: &VenvPaths) -> String {
+        let site_packages = paths
+            .site_packages
+            .strip_prefix(&paths.root)
+            .unwrap_or(&paths.site_packages);
+        let bin = paths.bin.strip_prefix(&paths.root).unwrap_or(&paths.bin);
+        let key = format!("{STORE_VERSION}\n{wheel_sha256}\n{site_packages}\n{bin}");
+        format!("{:016x}", seahash::hash(key.as_bytes()))
+    }
+
+    fn manifest_path(&self, key: &str) -> Utf8PathBuf {
+        self.root.join("manifests").join(key).with_extension("json")
+    }
+
+    fn object_path(&self, sha256: &str) -> Utf8PathBuf {
+        self.root.join("objects").join(&sha256[..2]).join(sha256)
+    }
+
+    /// Link the files of a stored package into the venv, returning `false` if the store doesn't
+    /// have the package yet. Existing files are replaced.
+    ///
+    /// The console scripts are copied instead of linked, with [`STORE_SHEBANG`] replaced by
+    /// `shebang`, the shebang for the python of this venv.
+    pub(crate) fn materialize(
+        &self,
+        key: &str,
+        paths: &VenvPaths,
+        shebang: &str,
+    ) -> Result<bool, Error> {
+        let manifest_path = self.manifest_path(key);
+        let manifest: Manifest = match fs::read(&manifest_path) {
+            Ok(manifest) => match serde_json::from_slice(&manifest) {
+                Ok(manifest) => manifest,
+                Err(err) => {
+                    warn!("Ignoring broken store manifest {manifest_path}: {err}");
+                    return Ok(false);
+                }
+            },
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Err(err) => return Err(err.into()),
+        };
+        // A partially cleaned store is treated like a missing entry
+        if let Some(missing) = manifest
+            .files
+            .iter()
+            .find(|file| !self.object_path(&file.sha256).is_file())
+        {
+            debug!(
+                "Store object for {} is missing, reinstalling the package",
+                missing.path
+            );
+            return Ok(false);
+        }
+        let bin = paths.bin.strip_prefix(&paths.root).unwrap_or(&paths.bin);
+        let mut link_mode = self.link_mode;
+        for file in &manifest.files {
+            let object = self.object_path(&file.sha256);
+            let target = paths.root.join(&file.path);
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            match fs::remove_file(&target) {
+                Ok(()) => {}
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                Err(err) => return Err(err.into()),
+            }
+            if file.path.starts_with(bin) {
+                fs::copy(&object, &target)?;
+                // Older gourgeist versions made linked objects read-only when freezing a venv
+                let mut permissions = fs::metadata(&target)?.permissions();
+                if permissions.readonly() {
+                    #[cfg(unix)]
+                    {
+                        use std::os::unix::fs::PermissionsExt;
+                        permissions.set_mode(permissions.mode() | 0o200);
+                    }
+                    #[cfg(not(unix))]
+                    {
+                        permissions.set_readonly(false);
+                    }
+                    fs::set_permissions(&target, permissions)?;
+                }
+                let contents = fs::read(&target)?;
+                if let Some(script) = contents.strip_prefix(STORE_SHEBANG.as_bytes()) {
+                    fs::write(&target, [shebang.as_bytes(), script].concat())?;
+                }
+            } else {
+                link_mode = link_mode.link_file(object.as_std_path(), target.as_std_path())?;
+            }
+        }
+        Ok(true)
+    }
+
+    /// Add the files of a package that was just installed into the venv to the store, as listed
+    /// in the `RECORD` of its `.dist-info`.
+    ///
+    /// The console scripts are stored with [`STORE_SHEBANG`] instead of the shebang for
+    /// `interpreter`, the python of this venv. If a script has another shebang (e.g. the `.exe`
+    /// launchers on windows, which embed the path), the package isn't stored, so each venv gets
+    /// its own launchers.
+    pub(crate) fn ingest(
+        &self,
+        ctx: &Context,
+        key: &str,
+        paths: &VenvPaths,
+        dist_info: &Utf8Path,
+        interpreter: &Utf8Path,
+    ) -> Result<(), Error> {
+        let bin = paths.bin.strip_prefix(&paths.root).unwrap_or(&paths.bin);
+        // Either what an installer writes or what we write
+        let venv_shebangs = [format!("#!{interpreter}\n"), shebang(interpreter)];
+        let record = fs::read_to_string(dist_info.join("RECORD"))?;
+        let mut files = Vec::new();
+        for line in record.lines() {
+            // `path,hash,size`, where only the path may contain commas
+            let Some(path) = line.rsplitn(3, ',').nth(2) else {
+                continue;
+            };
+            let path = path.trim_matches('"');
+            let Some(absolute) = normalized(&paths.site_packages.join(path)) else {
+                continue;
+            };
+            let Ok(relative) = absolute.strip_prefix(&paths.root) else {
+                warn!("Not storing {path}, which is outside the venv");
+                continue;
+            };
+            if !absolute.is_file() {
+                continue;
+            }
+            let neutral_script = if relative.starts_with(bin) {
+                let contents = fs::read(&absolute)?;
+                let Some(script) = venv_shebangs
+                    .iter()
+                    .find_map(|shebang| contents.strip_prefix(shebang.as_bytes()))
+                else {
+                    debug!("Not storing {dist_info}, {relative} has no shebang we can replace");
+                    return Ok(());
+                };
+                Some([STORE_SHEBANG.as_bytes(), script].concat())
+            } else {
+                None
+            };
+            let sha256 = match &neutral_script {
+                Some(script) => format!("{:x}", Sha256::digest(script)),
+                None => sha256_file(&absolute)?,
+            };
+            let object = self.object_path(&sha256);
+            if !object.is_file() {
+                let parent = object.parent().expect("objects have a parent");
+                fs::create_dir_all(parent)?;
+                let tempfile = NamedTempFile::new_in(parent)?;
+                fs::copy(&absolute, tempfile.path())?;
+                if let Some(script) = &neutral_script {
+                    fs::write(tempfile.path(), script)?;
+                }
+                tempfile.persist(&object)?;
+                sync_file(ctx, &object)?;
+            }
+            files.push(ManifestFile {
+                path: relative.to_path_buf(),
+                sha256,
+            });
+        }
+        let manifest_path = self.manifest_path(key);
+        fs::create_dir_all(manifest_path.parent().expect("manifests have a parent"))?;
+        let manifest = serde_json::to_vec(&Manifest { files }).map_err(io::Error::from)?;
+        write_atomic(&manifest_path, &manifest)?;
+        sync_file(ctx, &manifest_path)?;
+        debug!("Added {dist_info} to the store as {key}");
+        Ok(())
+    }
+}
@@ -0,0 +1,100 @@
+//! `--timings`: Collect the durations of the creation steps from the log events
+//!
+//! The library logs each step with `step` and `duration_ms` fields (and optionally `package`,
+//! `path` and `cached`), which [`TimingsLayer`] records independent of the log level.
+
+use serde::Serialize;
+use std::fmt;
+use std::sync::Mutex;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Metadata, Subscriber};
+use tracing_subscriber::layer::{Context, Filter};
+use tracing_subscriber::Layer;
+
+static TIMINGS: Mutex<Vec<Timing>> = Mutex::new(Vec::new());
+
+/// The duration of one step, e.g. querying the interpreter or installing pip
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Timing {
+    pub step: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub package: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    /// Whether the result came from the cache, for the steps that have a cache
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cached: Option<bool>,
+    pub duration_ms: u64,
+}
+
+impl fmt::Display for Timing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut label = self.step.clone();
+        if let Some(package) = &self.package {
+            label.push(' ');
+            label.push_str(package);
+        }
+        match self.cached {
+            Some(true) => label.push_str(" (cached)"),
+            Some(false) => label.push_str(" (uncached)"),
+            None => {}
+        }
+        write!(f, "{label:<28} {:>6}ms", self.duration_ms)
+    }
+}
+
+/// The timings recorded since the last call
+pub fn take() -> Vec<Timing> {
+    std::mem::take(&mut TIMINGS.lock().unwrap())
+}
+
+pub struct TimingsLayer;
+
+/// Only the events with a `step` field are timings
+pub struct StepFilter;
+
+impl<S> Filter<S> for StepFilter {
+    fn enabled(&self, metadata: &Metadata<'_>, _ctx: &Context<'_, S>) -> bool {
+        metadata.fields().field("step").is_some()
+    }
+}
+
+struct TimingVisitor<'a>(&'a mut Timing);
+
+impl Visit for TimingVisitor<'_> {
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        if field.name() == "duration_ms" {
+            self.0.duration_ms = value;
+        }
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        if field.name() == "cached" {
+            self.0.cached = Some(value);
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            "step" => self.0.step = value.to_string(),
+            "package" => self.0.package = Some(value.to_string()),
+            "path" => self.0.path = Some(value.to_string()),
+            _ => {}
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        // `%path` is recorded through `Debug`
+        if field.name() == "path" {
+            self.0.path = Some(format!("{value:?}"));
+        }
+    }
+}
+
+impl<S: Subscriber> Layer<S> for TimingsLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let mut timing = Timing::default();
+        event.record(&mut TimingVisitor(&mut timing));
+        TIMINGS.lock().unwrap().push(timing);
+    }
+}
@@ -0,0 +1,74 @@
+//! macOS universal2 interpreters, which contain both an `arm64` and an `x86_64` binary
+
+use camino::Utf8Path;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::io;
+
+/// What to do with a universal2 base interpreter when creating a venv
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Universal2 {
+    /// Link or copy the interpreter with both architectures
+    #[default]
+    Preserve,
+    /// Copy the interpreter with only the architecture of this machine, which makes it half the
+    /// size and keeps `arch -x86_64` from switching the venv to the other architecture
+    Thin,
+}
+
+/// The architecture this machine runs natively, as in `platform.machine()`: `arm64` on Apple
+/// Silicon even if gourgeist itself is an `x86_64` binary running under Rosetta.
+pub(crate) fn native_machine() -> &'static str {
+    if is_translated() {
+        return "arm64";
+    }
+    match std::env::consts::ARCH {
+        "aarch64" => "arm64",
+        arch => arch,
+    }
+}
+
+/// Whether this process is an `x86_64` binary translated by Rosetta on Apple Silicon. Its child
+/// processes prefer `x86_64` too, so a universal2 interpreter we query reports `x86_64`.
+pub(crate) fn is_translated() -> bool {
+    #[cfg(all(target_os = "macos", target_arch = "x86_64"))]
+    {
+        std::process::Command::new("sysctl")
+            .args(["-in", "sysctl.proc_translated"])
+            .output()
+            .is_ok_and(|output| String::from_utf8_lossy(&output.stdout).trim() == "1")
+    }
+    #[cfg(not(all(target_os = "macos", target_arch = "x86_64")))]
+    {
+        false
+    }
+}
+
+/// Remove all but the native architecture from the copied universal2 binary at `path` with
+/// `lipo`. This invalidates the code signature, so the caller has to re-sign it with
+/// [`crate::codesign::fix_signature`].
+pub(crate) fn thin(path: &Utf8Path) -> io::Result<()> {
+    #[cfg(target_os = "macos")]
+    {
+        let machine = native_machine();
+        tracing::debug!("Thinning {path} to {machine}");
+        let output = std::process::Command::new("lipo")
+            .arg(path)
+            .args(["-thin", machine, "-output"])
+            .arg(path)
+            .output()?;
+        if !output.status.success() {
+            return Err(io::Error::other(format!(
+                "Failed to thin {path} to {machine}: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        Ok(())
+    }
+    #[cfg(not(target_os = "macos"))]
+    {
+        let _ = path;
+        Ok(())
+    }
+}
@@ -0,0 +1,221 @@
+//! Check the integrity of an existing venv
+
+use crate::bare::{
+    bin_dir, is_interpreter_name, read_pyvenv_cfg, seed_packages, stale_venv, venv_python,
+    VenvPaths, ACTIVATE_TEMPLATES,
+};
+use crate::info::parse_minor;
+use crate::paths::canonicalize;
+use crate::scripts::{normalize_name, shebang, RELOCATABLE_SHEBANG};
+use crate::{Context, Error};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use camino::{Utf8Path, Utf8PathBuf};
+use fs_err as fs;
+use sha2::{Digest, Sha256};
+use std::io;
+
+/// Check that the venv at `location` is intact and return a description of each problem.
+///
+/// This checks that the interpreter links resolve, that `pyvenv.cfg` is consistent with the base
+/// interpreter and the layout, that the activators exist, that the scripts in `bin` run with the
+/// venv python and that the files of the seed packages match the hashes in their `RECORD`.
+pub fn verify_venv(ctx: &Context, location: &Utf8Path) -> Result<Vec<String>, Error> {
+    let location = &ctx.absolute(location);
+    let cfg = read_pyvenv_cfg(location)?;
+    let mut problems = Vec::new();
+
+    // pyvenv.cfg
+    if let Some(reason) = stale_venv(ctx, location)? {
+        problems.push(format!("The venv is stale, {reason}"));
+    }
+    match cfg.get("home") {
+        Some(home) if !Utf8Path::new(home).is_dir() => {
+            problems.push(format!("`home` in pyvenv.cfg, {home}, is not a directory"));
+        }
+        Some(home) => {
+            let base_python = cfg.get("base-executable").or_else(|| cfg.get("executable"));
+            if let Some(base_python) = base_python {
+                if Utf8Path::new(base_python).parent() != Some(Utf8Path::new(home)) {
+                    problems.push(format!(
+                        "The base interpreter {base_python} is not in `home` ({home}) in pyvenv.cfg"
+                    ));
+                }
+            }
+        }
+        None => problems.push("pyvenv.cfg has no `home`".to_string()),
+    }
+    match cfg.get("include-system-site-packages") {
+        Some("true" | "false") | None => {}
+        Some(other) => problems.push(format!(
+            "`include-system-site-packages` in pyvenv.cfg must be `true` or `false`, not `{other}`"
+        )),
+    }
+    let version = cfg.get("version_info").or_else(|| cfg.get("version"));
+    let paths = version
+        .and_then(parse_minor)
+        .map(|(major, minor)| VenvPaths::with_version(location, major, minor));
+    let Some(paths) = paths else {
+        problems.push("pyvenv.cfg has no valid `version_info`".to_string());
+        return Ok(problems);
+    };
+    if !paths.site_packages.is_dir() {
+        problems.push(format!(
+            "{} is missing for python {}",
+            paths.site_packages,
+            version.unwrap_or_default()
+        ));
+    }
+
+    // Interpreter links
+    let bin = bin_dir(location);
+    if !venv_python(location).exists() {
+        problems.push(format!("{} doesn't exist", venv_python(location)));
+    }
+    for entry in fs::read_dir(&bin)? {
+        let entry = entry?;
+        let Some(name) = entry.file_name().to_str().map(ToString::to_string) else {
+            continue;
+        };
+        if is_interpreter_name(&name) && !bin.join(&name).exists() {
+            problems.push(format!("{} is a dangling link", bin.join(&name)));
+        }
+    }
+
+    // Activators, `python -m venv` and virtualenv write a different set than we do
+    let activators: Vec<&str> = if cfg.get("gourgeist").is_some() {
+        ACTIVATE_TEMPLATES.iter().map(|(name, _)| *name).collect()
+    } else {
+        vec!["activate"]
+    };
+    for activator in activators {
+        if !bin.join(activator).is_file() {
+            problems.push(format!("The activator {} is missing", bin.join(activator)));
+        }
+    }
+
+    problems.extend(verify_shebangs(&bin, location)?);
+    for (name, version) in seed_packages(&paths)? {
+        problems.extend(verify_record(&paths.site_packages, &name, &version)?);
+    }
+    Ok(problems)
+}
+
+/// Check that the scripts in `bin` run with the venv python, either through its absolute path or
+/// the relocatable shebang.
+fn verify_shebangs(bin: &Utf8Path, location: &Utf8Path) -> Result<Vec<String>, Error> {
+    // The venv may have been created through a path with symlinks
+    let mut interpreters = vec![venv_python(location)];
+    if let Ok(canonical) = canonicalize(location) {
+        interpreters.push(venv_python(&canonical));
+    }
+    let mut valid_shebangs = vec![RELOCATABLE_SHEBANG.to_string()];
+    for interpreter in &interpreters {
+        valid_shebangs.push(format!("#!{interpreter}\n"));
+        valid_shebangs.push(shebang(interpreter));
+    }
+
+    let mut problems = Vec::new();
+    for entry in fs::read_dir(bin)? {
+        let entry = entry?;
+        let path = Utf8PathBuf::try_from(entry.path()).map_err(|err| err.into_io_error())?;
+        if !entry.file_type()?.is_file() {
+            continue;
+        }
+        let contents = fs::read(&path)?;
+        if !contents.starts_with(b"#!") {
+            continue;
+        }
+        if !valid_shebangs
+            .iter()
+            .any(|valid| contents.starts_with(valid.as_bytes()))
+        {
+            let first_line = contents
+                .split(|&byte| byte == b'\n')
+                .next()
+                .unwrap_or_default();
+            problems.push(format!(
+                "{path} doesn't run with the venv python, its shebang is `{}`",
+                String::from_utf8_lossy(first_line)
+            ));
+        }
+    }
+    Ok(problems)
+}
+
+/// Check the files of an installed package against the hashes in its `RECORD`.
+///
+/// Only the files in site-packages are checked, the scripts are renamed and get new shebangs
+/// after installation, so their hashes don't match anyway.
+fn verify_record(
+    site_packages: &Utf8Path,
+    name: &str,
+    version: &str,
+) -> Result<Vec<String>, Error> {
+    let dist_info = fs::read_dir(site_packages)?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.file_name().to_str().map(ToString::to_string))
+        .find(|file_name| {
+            file_name.strip_suffix(".dist-info").is_some_and(|stem| {
+                stem.split_once('-')
+                    .is_some_and(|(dist_name, dist_version)| {
+                        normalize_name(dist_name) == name && dist_version == version
+                    })
+            })
+        });
+    let Some(dist_info) = dist_info else {
+        return Ok(Vec::new());
+    };
+    let record_path = site_packages.join(&dist_info).join("RECORD");
+    let record = match fs::read_to_string(&record_path) {
+        Ok(record) => record,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            return Ok(vec![format!("{record_path} is missing")]);
+        }
+        Err(err) => return Err(err.into()),
+    };
+
+    let mut problems = Vec::new();
+    for line in record.lines() {
+        let Some((path, hash)) = parse_record_line(line) else {
+            continue;
+        };
+        let Some(expected) = hash.strip_prefix("sha256=") else {
+            continue;
+        };
+        if path.starts_with("..") {
+            continue;
+        }
+        let file = site_packages.join(path);
+        let actual = match fs::File::open(&file) {
+            Ok(mut reader) => {
+                let mut hasher = Sha256::new();
+                io::copy(&mut reader, &mut hasher)?;
+                URL_SAFE_NO_PAD.encode(hasher.finalize())
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                problems.push(format!("{file} from {name} {version} is missing"));
+                continue;
+            }
+            Err(err) => return Err(err.into()),
+        };
+        if actual != expected {
+            problems.push(format!(
+                "{file} from {name} {version} was modified, its hash doesn't match the RECORD"
+            ));
+        }
+    }
+    Ok(problems)
+}
+
+/// The path and hash of a `RECORD` line. The path is quoted if it contains a comma.
+fn parse_record_line(line: &str) -> Option<(&str, &str)> {
+    let (path, rest) = if let Some(quoted) = line.strip_prefix('"') {
+        let (path, rest) = quoted.split_once('"')?;
+        (path, rest.strip_prefix(',')?)
+    } else {
+        line.split_once(',')?
+    };
+    let hash = rest.split(',').next()?;
+    (!hash.is_empty()).then_some((path, hash))
+}
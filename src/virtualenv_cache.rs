@@ -1,19 +1,70 @@
 //! Deprecated, use only as template when implementing caching
 
-use crate::Error;
+use crate::bare::VenvPaths;
+use crate::interpreter::InterpreterInfo;
+use crate::link::LinkMode;
+use crate::plan::PlannedSeed;
+use crate::sbom::SeededPackage;
+use crate::scripts::{read_scripts, shebang, ScriptNaming};
+use crate::wheel_data::install_data_dir;
+use crate::{Context, Error};
 use camino::{Utf8Path, Utf8PathBuf};
-use dirs::data_dir;
 use fs_err as fs;
 use std::io;
 use std::path::Path;
+use std::time::Instant;
 use tracing::debug;
 
+/// Where virtualenv unpacks the seed wheels in its app data
+const PREFIX: &str = "wheel/3.11/image/1/CopyPipInstall/";
+const WHEEL_TAG: &str = "py3-none-any";
+const PACKAGES: [(&str, &str); 3] = [
+    ("pip", "23.2.1"),
+    ("setuptools", "68.2.2"),
+    ("wheel", "0.41.2"),
+];
+
+/// The unpacked wheel of a seed package in virtualenv's app data, which must exist
+fn unpacked_wheel(ctx: &Context, name: &str, version: &str) -> Result<Utf8PathBuf, Error> {
+    let unpacked_wheel = ctx
+        .virtualenv_data_dir()?
+        .join(PREFIX)
+        .join(format!("{name}-{version}-{WHEEL_TAG}"));
+    if !unpacked_wheel.is_dir() {
+        return Err(Error::MissingSeedPackage {
+            name: name.to_string(),
+            path: unpacked_wheel,
+        });
+    }
+    Ok(unpacked_wheel)
+}
+
+/// Where the seed packages would be copied from, for [`plan_venv`](crate::plan_venv)
+pub(crate) fn planned_seeds(ctx: &Context) -> Result<Vec<PlannedSeed>, Error> {
+    PACKAGES
+        .into_iter()
+        .map(|(name, version)| {
+            Ok(PlannedSeed {
+                name: name.to_string(),
+                version: version.to_string(),
+                source: unpacked_wheel(ctx, name, version)?.to_string(),
+                download: false,
+            })
+        })
+        .collect()
+}
+
 /// Install wheel, pip and setuptools from the cache
+///
+/// The files are installed into `staging`, while the launchers point to `venv_python`.
 pub(crate) fn install_base_packages(
-    bin_dir: &Utf8Path,
+    ctx: &Context,
+    staging: &VenvPaths,
     venv_python: &Utf8Path,
-    site_packages: &Utf8Path,
-) -> Result<(), Error> {
+    info: &InterpreterInfo,
+    mut link_mode: LinkMode,
+    script_naming: &ScriptNaming,
+) -> Result<Vec<SeededPackage>, Error> {
     // Install packages
     // TODO: Implement our own logic:
     //  * Our own cache and logic to detect whether a wheel is present
@@ -21,48 +72,36 @@ pub(crate) fn install_base_packages(
     //  * Query pypi API if no, parse versions (pep440) and their metadata
     //  * Download compatible wheel (py3-none-any should do)
     //  * Install into the cache directory
-    let prefix = "virtualenv/wheel/3.11/image/1/CopyPipInstall/";
-    let wheel_tag = "py3-none-any";
-    let packages = &[
-        ("pip", "23.2.1"),
-        ("setuptools", "68.2.2"),
-        ("wheel", "0.41.2"),
-    ];
-    let virtualenv_data_dir: Utf8PathBuf = data_dir().unwrap().try_into().unwrap();
-    for (name, version) in packages {
+    let mut seeded = Vec::new();
+    for (name, version) in PACKAGES {
         // TODO: acquire lock
-        let unpacked_wheel = virtualenv_data_dir
-            .join(prefix)
-            .join(format!("{name}-{version}-{wheel_tag}"));
+        let unpacked_wheel = unpacked_wheel(ctx, name, version)?;
         debug!("Installing {name} by copying from {unpacked_wheel}");
-        copy_dir_all(&unpacked_wheel, site_packages.as_std_path())?;
+        let start = Instant::now();
+        copy_dir_all(
+            &unpacked_wheel,
+            staging.site_packages.as_std_path(),
+            &mut link_mode,
+        )?;
+        install_data_dir(staging, venv_python, info, name, version)?;
 
         // Generate launcher
         // virtualenv for some reason creates extra entrypoints that we don't
         // https://github.com/pypa/virtualenv/blob/025e96fbad37f85617364002ae2a0064b09fc984/src/virtualenv/seed/embed/via_app_data/pip_install/base.py#L74-L95
-        let ini_text = fs::read_to_string(
-            site_packages
-                .join(format!("{name}-{version}.dist-info"))
-                .join("entry_points.txt"),
-        )?;
-        let entry_points_mapping = configparser::ini::Ini::new_cs()
-            .read(ini_text)
-            .map_err(|err| format!("{name} entry_points.txt is invalid: {}", err))
-            .unwrap();
-        for (key, value) in entry_points_mapping
-            .get("console_scripts")
-            .cloned()
-            .unwrap_or_default()
-        {
-            let (import_from, function) = value
-                .as_ref()
-                .and_then(|value| value.split_once(':'))
-                .ok_or_else(|| {
-                    format!("{name} entry_points.txt {key} has an invalid value {value:?}")
-                })
-                .unwrap();
-            let launcher = bin_dir.join(key);
-            let launcher_script = unix_launcher_script(venv_python, import_from, function);
+        let dist_info = staging
+            .site_packages
+            .join(format!("{name}-{version}.dist-info"));
+        for script in read_scripts(&dist_info)? {
+            let Some(script_name) = script_naming.script_name(name, &script.name) else {
+                continue;
+            };
+            let python = if script.gui && cfg!(windows) {
+                venv_python.with_file_name("pythonw.exe")
+            } else {
+                venv_python.to_path_buf()
+            };
+            let launcher = staging.bin.join(script_name);
+            let launcher_script = unix_launcher_script(&python, &script.module, &script.function);
             fs::write(&launcher, launcher_script)?;
             // We need to make the launcher executable
             #[cfg(target_family = "unix")]
@@ -71,20 +110,51 @@ pub(crate) fn install_base_packages(
                 fs::set_permissions(launcher, std::fs::Permissions::from_mode(0o755))?;
             }
         }
+        debug!(
+            step = "install",
+            package = name,
+            path = %staging.root,
+            duration_ms = start.elapsed().as_millis() as u64,
+            "Installed {name}"
+        );
+        // virtualenv only keeps the unpacked wheel, so there's no file to hash
+        seeded.push(SeededPackage {
+            name: name.to_string(),
+            version: version.to_string(),
+            filename: format!("{name}-{version}-{WHEEL_TAG}.whl"),
+            sha256: None,
+            url: None,
+        });
     }
-    Ok(())
+    Ok(seeded)
 }
 
+/// Recursively mirror `src` into `dst` with the given link mode.
+///
+/// Once a file needed a fallback, e.g. because reflinks aren't supported, the remaining files skip
+/// straight to the mode that worked.
+///
 /// https://stackoverflow.com/a/65192210/3549270
-pub fn copy_dir_all(src: impl AsRef<Path>, dst: impl AsRef<Path>) -> io::Result<()> {
+pub fn copy_dir_all(
+    src: impl AsRef<Path>,
+    dst: impl AsRef<Path>,
+    link_mode: &mut LinkMode,
+) -> io::Result<()> {
     fs::create_dir_all(&dst)?;
-    for entry in fs::read_dir(src.as_ref())? {
-        let entry = entry?;
+    // Write the files in a stable order
+    let mut entries = fs::read_dir(src.as_ref())?.collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
         let ty = entry.file_type()?;
         if ty.is_dir() {
-            copy_dir_all(entry.path(), dst.as_ref().join(entry.file_name()))?;
+            copy_dir_all(
+                entry.path(),
+                dst.as_ref().join(entry.file_name()),
+                link_mode,
+            )?;
         } else {
-            fs::copy(entry.path(), dst.as_ref().join(entry.file_name()))?;
+            *link_mode =
+                link_mode.link_file(&entry.path(), &dst.as_ref().join(entry.file_name()))?;
         }
     }
     Ok(())
@@ -92,17 +162,18 @@ pub fn copy_dir_all(src: impl AsRef<Path>, dst: impl AsRef<Path>) -> io::Result<
 
 /// Template for the console scripts in the `bin` directory
 pub fn unix_launcher_script(python: &Utf8Path, import_from: &str, function: &str) -> String {
+    // For `obj.attr`, we import `obj` and call `obj.attr()`
+    let import_name = function.split('.').next().unwrap_or(function);
     format!(
-        r#"#!{python}
-    # -*- coding: utf-8 -*-
+        r#"{shebang}    # -*- coding: utf-8 -*-
 import re
 import sys
-from {import_from} import {function}
+from {import_from} import {import_name}
 if __name__ == '__main__':
     sys.argv[0] = re.sub(r'(-script\.pyw|\.exe)?$', '', sys.argv[0])
     sys.exit({function}())
 "#,
-        python = python,
+        shebang = shebang(python),
         import_from = import_from,
         function = function
     )
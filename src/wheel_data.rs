@@ -0,0 +1,148 @@
+//! Install the `<name>-<version>.data` directory of a wheel
+//!
+//! <https://packaging.python.org/en/latest/specifications/binary-distribution-format/#the-data-directory>
+
+use crate::bare::{relative_to_root, VenvPaths};
+use crate::interpreter::InterpreterInfo;
+use crate::scripts::shebang;
+use camino::{Utf8Path, Utf8PathBuf};
+use fs_err as fs;
+use std::io;
+use tracing::{debug, warn};
+
+/// Move the contents of the `.data` directory of a wheel unpacked into `staging.site_packages` to
+/// their install locations, and update the `RECORD` of the wheel accordingly.
+///
+/// Scripts go to `bin` with their `#!python` shebang pointing to `interpreter`, `purelib` and
+/// `platlib` to site-packages, `headers` to `include/site/pythonX.Y/<name>` and `data` to the
+/// venv root.
+pub(crate) fn install_data_dir(
+    staging: &VenvPaths,
+    interpreter: &Utf8Path,
+    info: &InterpreterInfo,
+    name: &str,
+    version: &str,
+) -> io::Result<()> {
+    let data_dir_name = format!("{name}-{version}.data");
+    let data_dir = staging.site_packages.join(&data_dir_name);
+    if !data_dir.is_dir() {
+        return Ok(());
+    }
+    debug!("Installing {data_dir}");
+    // Where each category of files goes, relative to site-packages for the `RECORD`
+    let site_packages_to_root =
+        relative_to_root(staging.site_packages.strip_prefix(&staging.root).unwrap());
+    let headers = Utf8PathBuf::from("include")
+        .join("site")
+        .join(format!("python{}.{}", info.major, info.minor))
+        .join(name);
+    let mut moved = Vec::new();
+    for entry in fs::read_dir(&data_dir)? {
+        let entry = entry?;
+        let category = entry.file_name().to_string_lossy().to_string();
+        let (target, relative_target) = match category.as_str() {
+            "scripts" => (
+                staging.bin.clone(),
+                site_packages_to_root.join(staging.bin.strip_prefix(&staging.root).unwrap()),
+            ),
+            "purelib" | "platlib" => (staging.site_packages.clone(), Utf8PathBuf::new()),
+            "headers" => (
+                staging.root.join(&headers),
+                site_packages_to_root.join(&headers),
+            ),
+            "data" => (staging.root.clone(), site_packages_to_root.clone()),
+            _ => {
+                warn!("Ignoring unknown directory {category} in {data_dir}");
+                continue;
+            }
+        };
+        let source = data_dir.join(&category);
+        for relative in move_tree(&source, &target)? {
+            if category == "scripts" {
+                rewrite_script_shebang(&target.join(&relative), interpreter)?;
+            }
+            moved.push((
+                format!("{data_dir_name}/{category}/{relative}"),
+                relative_target.join(&relative),
+            ));
+        }
+    }
+    fs::remove_dir_all(&data_dir)?;
+    update_record(
+        &staging
+            .site_packages
+            .join(format!("{name}-{version}.dist-info"))
+            .join("RECORD"),
+        &moved,
+    )
+}
+
+/// Move all files in `source` to the same relative path in `target`, returning the relative paths.
+fn move_tree(source: &Utf8Path, target: &Utf8Path) -> io::Result<Vec<Utf8PathBuf>> {
+    let mut moved = Vec::new();
+    let mut queue = vec![Utf8PathBuf::new()];
+    while let Some(relative_dir) = queue.pop() {
+        fs::create_dir_all(target.join(&relative_dir))?;
+        for entry in fs::read_dir(source.join(&relative_dir))? {
+            let entry = entry?;
+            let relative = relative_dir.join(entry.file_name().to_string_lossy().as_ref());
+            if entry.file_type()?.is_dir() {
+                queue.push(relative);
+            } else {
+                fs::rename(entry.path(), target.join(&relative))?;
+                moved.push(relative);
+            }
+        }
+    }
+    Ok(moved)
+}
+
+/// Scripts in the data directory start with `#!python` (or `#!pythonw`) as placeholder for the
+/// interpreter they are installed with, and must be executable.
+fn rewrite_script_shebang(script: &Utf8Path, interpreter: &Utf8Path) -> io::Result<()> {
+    let mut contents = fs::read(script)?;
+    if contents.starts_with(b"#!python") {
+        let first_line_end = contents
+            .iter()
+            .position(|&byte| byte == b'\n')
+            .map_or(contents.len(), |position| position + 1);
+        contents = [shebang(interpreter).as_bytes(), &contents[first_line_end..]].concat();
+    }
+    replace_file(script, &contents)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(script, std::fs::Permissions::from_mode(0o755))?;
+    }
+    Ok(())
+}
+
+/// Write a new file instead of truncating the existing one, which may be linked to the cache.
+fn replace_file(path: &Utf8Path, contents: &[u8]) -> io::Result<()> {
+    fs::remove_file(path)?;
+    fs::write(path, contents)
+}
+
+/// Point the `RECORD` entries of the moved files to their new location.
+///
+/// The hashes of rewritten scripts don't match anymore, so like for generated files we leave them
+/// blank for moved files.
+fn update_record(record: &Utf8Path, moved: &[(String, Utf8PathBuf)]) -> io::Result<()> {
+    if !record.is_file() {
+        return Ok(());
+    }
+    let contents = fs::read_to_string(record)?;
+    let mut updated = String::with_capacity(contents.len());
+    for line in contents.lines() {
+        let path = line.split(',').next().unwrap_or_default();
+        match moved.iter().find(|(old, _)| old == path) {
+            Some((_, new)) => updated.push_str(&format!("{new},,\n")),
+            None if path.contains(".data/") => {}
+            None => {
+                updated.push_str(line);
+                updated.push('\n');
+            }
+        }
+    }
+    replace_file(record, updated.as_bytes())
+}
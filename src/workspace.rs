@@ -0,0 +1,273 @@
+//! Create an in-project `.venv` for every project of a workspace
+
+use crate::bare::{base_executable, matches_existing};
+use crate::discovery::{find_pythons, FoundPython, PythonSource};
+use crate::interpreter::{get_interpreter_info, parse_python_cli, venv_base_interpreter};
+use crate::paths::canonicalize;
+use crate::{create_venv, Context, Error, VenvOptions};
+use camino::{Utf8Path, Utf8PathBuf};
+use fs_err as fs;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::fmt;
+use std::io;
+
+/// Directories that never contain workspace members
+const SKIPPED_DIRS: &[&str] = &["node_modules", "target", "__pycache__", "site-packages"];
+
+/// What happened to the `.venv` of a workspace member
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "status", rename_all = "kebab-case")]
+pub enum MemberStatus {
+    Created {
+        python: Utf8PathBuf,
+        python_version: String,
+    },
+    Reused {
+        python: Utf8PathBuf,
+        python_version: String,
+    },
+    Skipped {
+        reason: String,
+    },
+}
+
+impl fmt::Display for MemberStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            MemberStatus::Created { .. } => "created",
+            MemberStatus::Reused { .. } => "reused",
+            MemberStatus::Skipped { .. } => "skipped",
+        })
+    }
+}
+
+/// A directory with a `pyproject.toml` and the outcome for its `.venv`
+#[derive(Debug, Clone, Serialize)]
+pub struct WorkspaceMember {
+    pub path: Utf8PathBuf,
+    /// `project.requires-python` of the `pyproject.toml`
+    pub requires_python: Option<String>,
+    #[serde(flatten)]
+    pub status: MemberStatus,
+}
+
+#[derive(Deserialize)]
+struct PyProjectToml {
+    project: Option<Project>,
+}
+
+#[derive(Deserialize)]
+struct Project {
+    #[serde(rename = "requires-python")]
+    requires_python: Option<String>,
+}
+
+/// Create or update the `.venv` of every directory below `root` with a `pyproject.toml`, using
+/// the newest installed python that fits the project's `requires-python`.
+///
+/// Projects without `requires-python` use the default python (`python3`). Projects whose
+/// `pyproject.toml` can't be read or without a matching python are skipped rather than failing
+/// the whole workspace. Hidden directories, such as the `.venv`s themselves, aren't searched.
+pub fn sync_workspace(
+    ctx: &Context,
+    root: &Utf8Path,
+    options: &VenvOptions,
+) -> Result<Vec<WorkspaceMember>, Error> {
+    let mut projects = Vec::new();
+    find_projects(&canonicalize(&ctx.absolute(root))?, &mut projects)?;
+    // Only search for interpreters if a project needs a specific version
+    let mut pythons = None;
+
+    let mut members = Vec::new();
+    for project in projects {
+        let pyproject_toml = project.join("pyproject.toml");
+        let requires_python = match fs::read_to_string(&pyproject_toml)
+            .map_err(|err| err.to_string())
+            .and_then(|contents| {
+                toml::from_str::<PyProjectToml>(&contents).map_err(|err| err.to_string())
+            }) {
+            Ok(pyproject) => pyproject
+                .project
+                .and_then(|project| project.requires_python),
+            Err(err) => {
+                members.push(WorkspaceMember {
+                    path: project,
+                    requires_python: None,
+                    status: MemberStatus::Skipped {
+                        reason: format!("Invalid {pyproject_toml}: {err}"),
+                    },
+                });
+                continue;
+            }
+        };
+
+        let base_python = match &requires_python {
+            Some(requires_python) => {
+                let pythons = match &pythons {
+                    Some(pythons) => pythons,
+                    None => pythons.insert(newest_first(find_pythons(ctx)?)),
+                };
+                let selected = pythons.iter().find_map(|(path, version)| {
+                    match matches_requires_python(requires_python, version) {
+                        Ok(true) => Some(Ok(path.clone())),
+                        Ok(false) => None,
+                        Err(err) => Some(Err(err)),
+                    }
+                });
+                let selected = match selected {
+                    Some(Ok(python)) => Ok(python),
+                    Some(Err(err)) => Err(format!(
+                        "Invalid requires-python `{requires_python}`: {err}"
+                    )),
+                    None => Err(format!(
+                        "No installed python matches requires-python `{requires_python}`"
+                    )),
+                };
+                match selected {
+                    Ok(python) => python,
+                    Err(reason) => {
+                        members.push(WorkspaceMember {
+                            path: project,
+                            requires_python: Some(requires_python.clone()),
+                            status: MemberStatus::Skipped { reason },
+                        });
+                        continue;
+                    }
+                }
+            }
+            None => parse_python_cli(ctx, None)?,
+        };
+        let base_python = venv_base_interpreter(ctx, &base_python)?.unwrap_or(base_python);
+        let info = get_interpreter_info(ctx, &base_python)?;
+        let location = project.join(".venv");
+        let reused = !options.rebuild
+            && matches_existing(
+                &location,
+                &base_executable(&base_python, &info),
+                &info,
+                options,
+            );
+        let paths = create_venv(ctx, &location, &base_python, &info, options)?;
+        let python_version = info.python_version.clone();
+        let python = paths.interpreter;
+        members.push(WorkspaceMember {
+            path: project,
+            requires_python,
+            status: if reused {
+                MemberStatus::Reused {
+                    python,
+                    python_version,
+                }
+            } else {
+                MemberStatus::Created {
+                    python,
+                    python_version,
+                }
+            },
+        });
+    }
+    Ok(members)
+}
+
+/// Collect the directories with a `pyproject.toml`, in a stable order.
+fn find_projects(dir: &Utf8Path, projects: &mut Vec<Utf8PathBuf>) -> io::Result<()> {
+    if dir.join("pyproject.toml").is_file() {
+        projects.push(dir.to_path_buf());
+    }
+    let mut entries = fs::read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let Some(name) = entry.file_name().to_str().map(str::to_string) else {
+            continue;
+        };
+        if name.starts_with('.') || SKIPPED_DIRS.contains(&name.as_str()) {
+            continue;
+        }
+        // Don't follow symlinks, they could create cycles
+        if entry.file_type()?.is_dir() {
+            find_projects(&dir.join(name), projects)?;
+        }
+    }
+    Ok(())
+}
+
+/// The interpreters with their versions, the newest version first.
+///
+/// For the same version, installations found directly are preferred over those in `PATH`, which
+/// can be wrappers such as pyenv shims that don't work as the base interpreter of a venv.
+fn newest_first(mut pythons: Vec<FoundPython>) -> Vec<(Utf8PathBuf, String)> {
+    pythons.sort_by_key(|python| {
+        (
+            Reverse(release(&python.python_version)),
+            python.source == PythonSource::Path,
+        )
+    });
+    pythons
+        .into_iter()
+        .map(|python| (python.path, python.python_version))
+        .collect()
+}
+
+/// The numeric release segments of a version, e.g. `[3, 11, 7]` for `3.11.7`. Pre-release
+/// suffixes such as `rc1` are ignored.
+pub(crate) fn release(version: &str) -> Vec<u64> {
+    version
+        .split('.')
+        .map_while(|part| {
+            let digits: String = part.chars().take_while(char::is_ascii_digit).collect();
+            digits.parse().ok()
+        })
+        .collect()
+}
+
+/// Whether the python version satisfies a PEP 440 specifier set such as `>=3.8,<3.12`.
+///
+/// This covers the operators used for `requires-python`: `>=`, `<=`, `>`, `<`, `==` (including
+/// `==3.11.*`), `!=` and `~=`.
+pub(crate) fn matches_requires_python(specifiers: &str, version: &str) -> Result<bool, String> {
+    let version = release(version);
+    for specifier in specifiers.split(',').map(str::trim) {
+        if specifier.is_empty() {
+            continue;
+        }
+        let operator_len = specifier
+            .find(|c: char| c.is_ascii_digit())
+            .ok_or_else(|| format!("Invalid specifier `{specifier}`"))?;
+        let (operator, bound) = specifier.split_at(operator_len);
+        let (bound, wildcard) = match bound.strip_suffix(".*") {
+            Some(bound) => (bound, true),
+            None => (bound, false),
+        };
+        let bound = release(bound);
+        // Compare with the missing segments of the shorter version as zeros
+        let len = version.len().max(bound.len());
+        let pad = |release: &[u64]| {
+            let mut padded = release.to_vec();
+            padded.resize(len, 0);
+            padded
+        };
+        let prefix_matches = version.len() >= bound.len() && version[..bound.len()] == bound[..];
+        let matches = match (operator.trim(), wildcard) {
+            ("==", true) => prefix_matches,
+            ("!=", true) => !prefix_matches,
+            ("==", false) => pad(&version) == pad(&bound),
+            ("!=", false) => pad(&version) != pad(&bound),
+            (">=", false) => pad(&version) >= pad(&bound),
+            ("<=", false) => pad(&version) <= pad(&bound),
+            (">", false) => pad(&version) > pad(&bound),
+            ("<", false) => pad(&version) < pad(&bound),
+            ("~=", false) if bound.len() >= 2 => {
+                let prefix = &bound[..bound.len() - 1];
+                pad(&version) >= pad(&bound)
+                    && version.len() >= prefix.len()
+                    && version[..prefix.len()] == *prefix
+            }
+            _ => return Err(format!("Invalid specifier `{specifier}`")),
+        };
+        if !matches {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}